@@ -0,0 +1,132 @@
+//! Throughput of turning CSV records into JSON lines.
+//!
+//! `map_per_record` is the previous approach (a `serde_json::Map` built from owned
+//! header names and values for every record); `record_encoder` writes JSON straight
+//! from byte records. `convert_to_ndjson` measures the whole parser on a file.
+//!
+//! Run with `cargo bench --bench csv_conversion`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use csv::{ByteRecord, ReaderBuilder, StringRecord};
+use csv_converter::{convert_field_value, ConvertOptions, CsvParser, Parser, RecordEncoder};
+use serde_json::Map;
+use std::hint::black_box;
+
+const ROWS: usize = 20_000;
+
+/// A CSV file with a mix of integers, floats, booleans, zip codes and quoted text
+fn generate_csv(rows: usize) -> Vec<u8> {
+    let mut csv = String::from("id,name,city,zip,amount,ratio,active,note\n");
+    for i in 0..rows {
+        csv.push_str(&format!(
+            "{},customer {},{},0{:04},{}.{:02},{},{},\"note, with \"\"quotes\"\" {}\"\n",
+            i,
+            i,
+            ["Amsterdam", "Berlin", "Paris"][i % 3],
+            i % 10_000,
+            i * 7,
+            i % 100,
+            (i % 1000) as f64 / 1000.0,
+            i % 2 == 0,
+            i
+        ));
+    }
+    csv.into_bytes()
+}
+
+fn options() -> ConvertOptions {
+    ConvertOptions {
+        string_fields: vec!["name".to_string()],
+        ..ConvertOptions::default()
+    }
+}
+
+fn map_per_record(input: &[u8], options: &ConvertOptions) -> usize {
+    let mut reader = ReaderBuilder::new().from_reader(input);
+    let headers = reader.headers().unwrap().clone();
+    let mut written = 0;
+    for record in reader.records() {
+        let record = record.unwrap();
+        let mut json = Map::new();
+        for (i, field) in record.iter().enumerate() {
+            let name = headers.get(i).map(|s| s.to_string()).unwrap();
+            let value = convert_field_value(
+                field,
+                &name,
+                options.no_type_conversion,
+                &options.string_fields,
+            );
+            json.insert(name, value);
+        }
+        written += serde_json::to_string(&json).unwrap().len() + 1;
+    }
+    written
+}
+
+fn record_encoder(input: &[u8], options: &ConvertOptions) -> usize {
+    let mut reader = ReaderBuilder::new().from_reader(input);
+    let headers: StringRecord = reader.headers().unwrap().clone();
+    let encoder = RecordEncoder::new(&headers, options);
+    let mut record = ByteRecord::new();
+    let mut json = Vec::new();
+    let mut written = 0;
+    while reader.read_byte_record(&mut record).unwrap() {
+        json.clear();
+        encoder.encode(&record, &[], &mut json).unwrap();
+        written += json.len() + 1;
+    }
+    written
+}
+
+fn bench_records(c: &mut Criterion) {
+    let input = generate_csv(ROWS);
+    let options = options();
+    assert_eq!(
+        map_per_record(&input, &options),
+        record_encoder(&input, &options)
+    );
+
+    let mut group = c.benchmark_group("csv_records");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("map_per_record", |b| {
+        b.iter(|| map_per_record(black_box(&input), &options))
+    });
+    group.bench_function("record_encoder", |b| {
+        b.iter(|| record_encoder(black_box(&input), &options))
+    });
+    group.finish();
+}
+
+fn bench_convert_to_ndjson(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("csv_conversion_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("input.csv");
+    let output_path = dir.join("output.ndjson");
+    let input = generate_csv(ROWS);
+    std::fs::write(&input_path, &input).unwrap();
+    let options = options();
+
+    let mut group = c.benchmark_group("convert_to_ndjson");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(20);
+    for threads in [1, 4] {
+        let parser = CsvParser::new(b',', b'"', None, csv::Terminator::CRLF).with_threads(threads);
+        group.bench_with_input(
+            BenchmarkId::new("threads", threads),
+            &parser,
+            |b, parser| {
+                b.iter(|| {
+                    parser
+                        .convert_to_ndjson(&input_path, Some(&output_path), &options)
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+criterion_group!(benches, bench_records, bench_convert_to_ndjson);
+criterion_main!(benches);
@@ -0,0 +1,278 @@
+//! Synthetic CSV and XLSX inputs for the benchmarks.
+//!
+//! A dataset is described by a `DatasetSpec` (rows, columns, how many text fields need
+//! quoting and the mix of column types) and generated deterministically, so the same
+//! spec always produces the same bytes. Rows are generated one at a time, so large
+//! datasets can be written without holding them in memory.
+
+use rust_xlsxwriter::Workbook;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Int,
+    Float,
+    Bool,
+    Text,
+}
+
+impl ColumnKind {
+    fn name(self) -> &'static str {
+        match self {
+            ColumnKind::Int => "int",
+            ColumnKind::Float => "float",
+            ColumnKind::Bool => "bool",
+            ColumnKind::Text => "text",
+        }
+    }
+}
+
+/// Relative number of columns of each kind, written like `int:2,float:2,bool:1,text:3`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeMix(Vec<(ColumnKind, usize)>);
+
+impl Default for TypeMix {
+    fn default() -> Self {
+        TypeMix(vec![
+            (ColumnKind::Int, 2),
+            (ColumnKind::Float, 2),
+            (ColumnKind::Bool, 1),
+            (ColumnKind::Text, 3),
+        ])
+    }
+}
+
+impl FromStr for TypeMix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (kind, weight) = part.split_once(':').unwrap_or((part, "1"));
+            let kind = match kind.trim().to_lowercase().as_str() {
+                "int" => ColumnKind::Int,
+                "float" => ColumnKind::Float,
+                "bool" => ColumnKind::Bool,
+                "text" => ColumnKind::Text,
+                other => return Err(format!("unknown column type '{}'", other)),
+            };
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight in '{}'", part))?;
+            mix.push((kind, weight));
+        }
+        if mix.iter().all(|&(_, weight)| weight == 0) {
+            return Err("type mix needs at least one column type".to_string());
+        }
+        Ok(TypeMix(mix))
+    }
+}
+
+impl fmt::Display for TypeMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(kind, weight)| format!("{}:{}", kind.name(), weight))
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DatasetSpec {
+    pub rows: usize,
+    pub columns: usize,
+    /// Fraction of text fields (0.0 to 1.0) containing delimiters, quotes or newlines
+    pub quoting: f64,
+    pub types: TypeMix,
+}
+
+impl Default for DatasetSpec {
+    fn default() -> Self {
+        Self {
+            rows: 20_000,
+            columns: 8,
+            quoting: 0.2,
+            types: TypeMix::default(),
+        }
+    }
+}
+
+impl DatasetSpec {
+    /// The default spec, overridden by the `BENCH_ROWS`, `BENCH_COLUMNS`,
+    /// `BENCH_QUOTING` and `BENCH_TYPES` environment variables
+    pub fn from_env() -> Result<Self, String> {
+        fn var<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+            match std::env::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .map_err(|_| format!("invalid value for {}: '{}'", name, value)),
+                Err(_) => Ok(default),
+            }
+        }
+
+        let defaults = Self::default();
+        let spec = Self {
+            rows: var("BENCH_ROWS", defaults.rows)?,
+            columns: var("BENCH_COLUMNS", defaults.columns)?,
+            quoting: var("BENCH_QUOTING", defaults.quoting)?,
+            types: match std::env::var("BENCH_TYPES") {
+                Ok(value) => value.parse()?,
+                Err(_) => defaults.types,
+            },
+        };
+        if spec.columns == 0 || !(0.0..=1.0).contains(&spec.quoting) {
+            return Err("BENCH_COLUMNS must be positive and BENCH_QUOTING within 0..1".into());
+        }
+        Ok(spec)
+    }
+
+    /// Column kinds, cycling through the type mix by weight
+    pub fn kinds(&self) -> Vec<ColumnKind> {
+        let cycle: Vec<ColumnKind> = self
+            .types
+            .0
+            .iter()
+            .flat_map(|&(kind, weight)| std::iter::repeat_n(kind, weight))
+            .collect();
+        (0..self.columns).map(|i| cycle[i % cycle.len()]).collect()
+    }
+
+    pub fn headers(&self) -> Vec<String> {
+        self.kinds()
+            .iter()
+            .enumerate()
+            .map(|(i, kind)| format!("{}_{}", kind.name(), i))
+            .collect()
+    }
+
+    /// Generates the data rows, calling `row` with the values of each
+    pub fn for_each_row(&self, mut row: impl FnMut(&[Value])) {
+        let kinds = self.kinds();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut values = Vec::with_capacity(kinds.len());
+        for i in 0..self.rows {
+            values.clear();
+            for &kind in &kinds {
+                values.push(match kind {
+                    ColumnKind::Int => Value::Int(rng.below(2_000_000) as i64 - 1_000_000),
+                    ColumnKind::Float => Value::Float(rng.below(10_000_000) as f64 / 100.0),
+                    ColumnKind::Bool => Value::Bool(rng.below(2) == 0),
+                    ColumnKind::Text => {
+                        let word = WORDS[rng.below(WORDS.len() as u64) as usize];
+                        if (rng.below(10_000) as f64) < self.quoting * 10_000.0 {
+                            Value::Text(format!("{}, \"{}\"\nrow {}", word, word, i))
+                        } else {
+                            Value::Text(format!("{} {}", word, i))
+                        }
+                    }
+                });
+            }
+            row(&values);
+        }
+    }
+
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(std::fs::File::create(path)?);
+        writeln!(out, "{}", self.headers().join(","))?;
+        let mut result = Ok(());
+        let mut line = String::new();
+        self.for_each_row(|values| {
+            line.clear();
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                value.write_csv(&mut line);
+            }
+            line.push('\n');
+            if result.is_ok() {
+                result = out.write_all(line.as_bytes());
+            }
+        });
+        result?;
+        out.flush()
+    }
+
+    /// Writes the dataset as one sheet in constant memory mode: rows are flushed as they
+    /// are written, and text is stored as inline strings.
+    pub fn write_xlsx(&self, path: &Path) -> Result<(), rust_xlsxwriter::XlsxError> {
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet_with_constant_memory();
+        for (col, header) in self.headers().iter().enumerate() {
+            sheet.write_string(0, col as u16, header)?;
+        }
+
+        let mut result = Ok(());
+        let mut row = 0;
+        self.for_each_row(|values| {
+            row += 1;
+            for (col, value) in values.iter().enumerate() {
+                let col = col as u16;
+                let written = match value {
+                    Value::Int(n) => sheet.write_number(row, col, *n as f64),
+                    Value::Float(n) => sheet.write_number(row, col, *n),
+                    Value::Bool(b) => sheet.write_boolean(row, col, *b),
+                    Value::Text(s) => sheet.write_string(row, col, s),
+                };
+                if let Err(e) = written {
+                    result = Err(e);
+                }
+            }
+        });
+        result?;
+        workbook.save(path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+}
+
+impl Value {
+    fn write_csv(&self, out: &mut String) {
+        match self {
+            Value::Int(n) => out.push_str(&n.to_string()),
+            Value::Float(n) => out.push_str(&format!("{:.2}", n)),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Text(s) if s.contains([',', '"', '\n']) => {
+                out.push('"');
+                out.push_str(&s.replace('"', "\"\""));
+                out.push('"');
+            }
+            Value::Text(s) => out.push_str(s),
+        }
+    }
+}
+
+const WORDS: [&str; 8] = [
+    "Amsterdam",
+    "Berlin",
+    "Paris",
+    "Lisbon",
+    "customer",
+    "invoice",
+    "widget",
+    "naïve café",
+];
+
+/// xorshift64, enough to vary the data without pulling in a random number crate
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
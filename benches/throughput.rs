@@ -0,0 +1,162 @@
+//! Throughput of format detection, value conversion and whole-file conversion on
+//! generated datasets, reported in MB/s and rows/s.
+//!
+//! The dataset is configured with environment variables (see `DatasetSpec::from_env`):
+//!
+//! ```text
+//! BENCH_ROWS=200000 BENCH_COLUMNS=20 BENCH_QUOTING=0.5 BENCH_TYPES=int:1,text:4 \
+//!     cargo bench --bench throughput
+//! ```
+//!
+//! `BENCH_SECONDS` sets how long each benchmark runs (default 2). Arguments that are
+//! not flags select benchmarks whose names contain them. Progress messages from the
+//! parsers go to stderr; redirect it to keep only the report.
+
+mod datasets;
+
+use csv_converter::{
+    convert_field_value, detect_csv_format, ConvertOptions, CsvParser, Parser, XlsxParser,
+};
+use datasets::DatasetSpec;
+use std::hint::black_box;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Runs `f` repeatedly for the configured time and returns the median run time
+fn measure(budget: Duration, mut f: impl FnMut()) -> Duration {
+    // Warm up caches and the page cache for the input file
+    f();
+
+    let mut times = Vec::new();
+    let started = Instant::now();
+    while times.len() < 5 || (started.elapsed() < budget && times.len() < 1000) {
+        let run = Instant::now();
+        f();
+        times.push(run.elapsed());
+    }
+    times.sort();
+    times[times.len() / 2]
+}
+
+struct Report {
+    budget: Duration,
+    filters: Vec<String>,
+}
+
+impl Report {
+    /// Measures one benchmark processing `bytes` and `rows` per run and prints a line
+    fn run(&self, name: &str, bytes: u64, rows: u64, f: impl FnMut()) {
+        if !self.filters.is_empty() && !self.filters.iter().any(|f| name.contains(f.as_str())) {
+            return;
+        }
+
+        let time = measure(self.budget, f);
+        let seconds = time.as_secs_f64();
+        println!(
+            "{:<28} {:>10.3} ms {:>10.1} MB/s {:>12.0} rows/s",
+            name,
+            seconds * 1000.0,
+            bytes as f64 / 1_000_000.0 / seconds,
+            rows as f64 / seconds
+        );
+    }
+}
+
+fn main() {
+    let spec = DatasetSpec::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    let seconds: f64 = std::env::var("BENCH_SECONDS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(2.0);
+    let report = Report {
+        budget: Duration::from_secs_f64(seconds),
+        filters: std::env::args()
+            .skip(1)
+            .filter(|arg| !arg.starts_with('-'))
+            .collect(),
+    };
+
+    let dir = std::env::temp_dir().join(format!("csv_converter_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let csv_path = dir.join("dataset.csv");
+    let xlsx_path = dir.join("dataset.xlsx");
+    let output_path = dir.join("output.ndjson");
+    spec.write_csv(&csv_path).unwrap();
+    spec.write_xlsx(&xlsx_path).unwrap();
+
+    let csv_bytes = std::fs::metadata(&csv_path).unwrap().len();
+    let xlsx_bytes = std::fs::metadata(&xlsx_path).unwrap().len();
+    let rows = spec.rows as u64;
+    println!(
+        "dataset: {} rows x {} columns, quoting {}, types {} (CSV {:.1} MB, XLSX {:.1} MB)",
+        spec.rows,
+        spec.columns,
+        spec.quoting,
+        spec.types,
+        csv_bytes as f64 / 1_000_000.0,
+        xlsx_bytes as f64 / 1_000_000.0
+    );
+
+    bench_detection(&report, &csv_path);
+    bench_field_conversion(&report, &csv_path);
+
+    let options = ConvertOptions::default();
+    let (delimiter, quote, escape, terminator) = detect_csv_format(&csv_path).unwrap();
+    let csv_parser = CsvParser::new(delimiter, quote, escape, terminator);
+    report.run("csv convert_to_ndjson", csv_bytes, rows, || {
+        csv_parser
+            .convert_to_ndjson(&csv_path, Some(&output_path), &options)
+            .unwrap()
+    });
+
+    // Sizes are of the compressed workbook
+    let xlsx_parser = XlsxParser::new();
+    report.run("xlsx convert_to_ndjson", xlsx_bytes, rows, || {
+        xlsx_parser
+            .convert_to_ndjson(&xlsx_path, Some(&output_path), &options)
+            .unwrap()
+    });
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Detection samples the first 250 lines, so throughput is relative to those
+fn bench_detection(report: &Report, csv_path: &Path) {
+    let content = std::fs::read(csv_path).unwrap();
+    let sampled: Vec<&[u8]> = content.split_inclusive(|&b| b == b'\n').take(250).collect();
+    let bytes = sampled.iter().map(|line| line.len() as u64).sum();
+
+    // Rows with embedded newlines span several lines; count the records sampled
+    let records = csv::Reader::from_reader(sampled.concat().as_slice())
+        .byte_records()
+        .count() as u64;
+
+    report.run("detect_csv_format", bytes, records, || {
+        black_box(detect_csv_format(black_box(csv_path)).unwrap());
+    });
+}
+
+/// Converts every field of the parsed dataset, so throughput counts field bytes only
+fn bench_field_conversion(report: &Report, csv_path: &Path) {
+    let mut reader = csv::Reader::from_path(csv_path).unwrap();
+    let headers: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+    let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    let bytes = records.iter().map(|r| r.as_slice().len() as u64).sum();
+    let options = ConvertOptions::default();
+
+    report.run("convert_field_value", bytes, records.len() as u64, || {
+        for record in &records {
+            for (field, header) in record.iter().zip(&headers) {
+                black_box(convert_field_value(
+                    black_box(field),
+                    header,
+                    options.no_type_conversion,
+                    &options.string_fields,
+                ));
+            }
+        }
+    });
+}
@@ -0,0 +1,237 @@
+use regex::Regex;
+use std::str::FromStr;
+
+use crate::unflatten::top_level_key;
+
+/// One entry of `--columns` or `--exclude-columns`
+#[derive(Debug, Clone)]
+pub enum ColumnSelector {
+    /// A header name, or a zero-based position when no header has this name and it is
+    /// all digits
+    Name(String),
+    /// Headers matching a regular expression, written as `/pattern/`
+    Pattern(Regex),
+}
+
+impl ColumnSelector {
+    /// Positions of the matching headers, in header order
+    fn matches(&self, headers: &[&str]) -> Vec<usize> {
+        let matching = |matches: &dyn Fn(&str) -> bool| {
+            (0..headers.len())
+                .filter(|&i| matches(headers[i]))
+                .collect::<Vec<_>>()
+        };
+
+        match self {
+            ColumnSelector::Name(name) => {
+                let by_name = matching(&|header| header == name);
+                if !by_name.is_empty() {
+                    return by_name;
+                }
+                name.parse::<usize>()
+                    .ok()
+                    .filter(|&index| index < headers.len())
+                    .into_iter()
+                    .collect()
+            }
+            ColumnSelector::Pattern(pattern) => matching(&|header| pattern.is_match(header)),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            ColumnSelector::Name(name) => name.clone(),
+            ColumnSelector::Pattern(pattern) => format!("/{}/", pattern.as_str()),
+        }
+    }
+}
+
+/// A comma-separated list of column selectors, like `id,3,/^price_/`. Commas inside a
+/// `/pattern/` belong to the pattern.
+#[derive(Debug, Clone)]
+pub struct ColumnList(pub Vec<ColumnSelector>);
+
+impl FromStr for ColumnList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut selectors = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let (selector, after) = match rest.strip_prefix('/') {
+                Some(pattern) => {
+                    // The pattern ends at the first '/' followed by a comma or the end
+                    let end = pattern
+                        .match_indices('/')
+                        .map(|(i, _)| i)
+                        .find(|&i| matches!(pattern[i + 1..].chars().next(), None | Some(',')))
+                        .ok_or_else(|| format!("unterminated pattern in '{}'", s))?;
+                    let regex = Regex::new(&pattern[..end])
+                        .map_err(|e| format!("invalid pattern '/{}/': {}", &pattern[..end], e))?;
+                    (ColumnSelector::Pattern(regex), &pattern[end + 1..])
+                }
+                None => {
+                    let end = rest.find(',').unwrap_or(rest.len());
+                    (
+                        ColumnSelector::Name(rest[..end].trim().to_string()),
+                        &rest[end..],
+                    )
+                }
+            };
+            if matches!(&selector, ColumnSelector::Name(name) if name.is_empty()) {
+                return Err(format!("empty column name in '{}'", s));
+            }
+            selectors.push(selector);
+            rest = after.strip_prefix(',').unwrap_or(after);
+        }
+
+        if selectors.is_empty() {
+            return Err("no columns given".to_string());
+        }
+        Ok(Self(selectors))
+    }
+}
+
+/// Which columns to write, from `--columns` and `--exclude-columns`
+#[derive(Debug, Clone, Default)]
+pub struct ColumnSelection {
+    /// Columns to write, in this order; empty for all columns
+    pub include: Vec<ColumnSelector>,
+    /// Columns to leave out
+    pub exclude: Vec<ColumnSelector>,
+}
+
+impl ColumnSelection {
+    pub fn is_enabled(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Resolves the selection against a row of headers. Selectors that match no header
+    /// are reported, since a sheet or file may lack some of the columns.
+    ///
+    /// `separator` is the one used to nest keys, if any; the key order then lists the
+    /// top-level keys of the selected headers.
+    pub fn resolve(&self, headers: &[&str], separator: Option<&str>) -> SelectedColumns {
+        let mut columns: Vec<usize> = if self.include.is_empty() {
+            (0..headers.len()).collect()
+        } else {
+            let mut columns = Vec::new();
+            for selector in &self.include {
+                let matches = selector.matches(headers);
+                warn_unmatched("--columns", selector, &matches);
+                for column in matches {
+                    if !columns.contains(&column) {
+                        columns.push(column);
+                    }
+                }
+            }
+            columns
+        };
+
+        for selector in &self.exclude {
+            let matches = selector.matches(headers);
+            warn_unmatched("--exclude-columns", selector, &matches);
+            columns.retain(|column| !matches.contains(column));
+        }
+
+        let mut key_order: Vec<String> = Vec::new();
+        if !self.include.is_empty() {
+            for &column in &columns {
+                let key = match separator {
+                    Some(separator) => top_level_key(headers[column], separator),
+                    None => headers[column],
+                };
+                if !key_order.iter().any(|k| k == key) {
+                    key_order.push(key.to_string());
+                }
+            }
+        }
+
+        SelectedColumns { columns, key_order }
+    }
+}
+
+fn warn_unmatched(option: &str, selector: &ColumnSelector, matches: &[usize]) {
+    if matches.is_empty() {
+        eprintln!(
+            "Warning: {} '{}' matches no column",
+            option,
+            selector.describe()
+        );
+    }
+}
+
+/// The columns to write, resolved from the headers of a file or sheet
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectedColumns {
+    /// Positions of the columns to write, in output order
+    pub columns: Vec<usize>,
+    /// Keys to write first, in this order; empty to write all keys sorted
+    pub key_order: Vec<String>,
+}
+
+impl SelectedColumns {
+    /// All columns, with keys in the usual sorted order
+    pub fn all(width: usize) -> Self {
+        Self {
+            columns: (0..width).collect(),
+            key_order: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(include: &str, exclude: Option<&str>, headers: &[&str]) -> SelectedColumns {
+        let selection = ColumnSelection {
+            include: include
+                .parse::<ColumnList>()
+                .map_or(Vec::new(), |list| list.0),
+            exclude: exclude.map_or(Vec::new(), |list| list.parse::<ColumnList>().unwrap().0),
+        };
+        selection.resolve(headers, None)
+    }
+
+    #[test]
+    fn test_select_by_name_index_and_pattern() {
+        let headers = ["id", "name", "price_net", "price_gross", "2024"];
+
+        let selected = select("price_gross,0,/^price_/,2024", None, &headers);
+        assert_eq!(selected.columns, [3, 0, 2, 4]);
+        assert_eq!(
+            selected.key_order,
+            ["price_gross", "id", "price_net", "2024"]
+        );
+
+        // Exclusions alone keep the sorted key order
+        let selected = select("", Some("/^price/,1"), &headers);
+        assert_eq!(selected.columns, [0, 4]);
+        assert!(selected.key_order.is_empty());
+
+        let selected = select("/./", Some("name"), &headers);
+        assert_eq!(selected.columns, [0, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_key_order_of_nested_headers() {
+        let selection = ColumnSelection {
+            include: "b.x,a[0],b.y,id".parse::<ColumnList>().unwrap().0,
+            exclude: Vec::new(),
+        };
+        let selected = selection.resolve(&["id", "a[0]", "b.x", "b.y"], Some("."));
+        assert_eq!(selected.key_order, ["b", "a", "id"]);
+    }
+
+    #[test]
+    fn test_parse_column_list() {
+        let list: ColumnList = "a, b ,/x{1,2}/,/a\\/b/,c".parse().unwrap();
+        let described: Vec<String> = list.0.iter().map(ColumnSelector::describe).collect();
+        assert_eq!(described, ["a", "b", "/x{1,2}/", "/a\\/b/", "c"]);
+
+        assert!("a,,b".parse::<ColumnList>().is_err());
+        assert!("/(/".parse::<ColumnList>().is_err());
+        assert!("/abc".parse::<ColumnList>().is_err());
+    }
+}
@@ -0,0 +1,635 @@
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
+use chrono::{
+    DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How recognized dates and times are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateOutput {
+    /// ISO-8601 dates and datetimes
+    #[default]
+    Iso,
+    /// Milliseconds since the Unix epoch
+    EpochMillis,
+    /// Seconds since the Unix epoch
+    EpochSeconds,
+}
+
+impl FromStr for DateOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "iso" => Ok(DateOutput::Iso),
+            "epoch-ms" | "epoch" => Ok(DateOutput::EpochMillis),
+            "epoch-s" => Ok(DateOutput::EpochSeconds),
+            _ => Err(format!(
+                "unknown date output '{}' (expected 'iso', 'epoch-ms' or 'epoch-s')",
+                s
+            )),
+        }
+    }
+}
+
+/// Time zone assigned to dates and times written without an offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeZoneSetting {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl TimeZoneSetting {
+    /// Places a local time in the zone. Times skipped by a daylight saving change do
+    /// not exist and give None; repeated times resolve to the earlier one.
+    fn localize(&self, local: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self {
+            TimeZoneSetting::Fixed(offset) => offset.from_local_datetime(local).earliest(),
+            TimeZoneSetting::Named(tz) => tz
+                .from_local_datetime(local)
+                .earliest()
+                .map(|time| time.fixed_offset()),
+        }
+    }
+}
+
+impl FromStr for TimeZoneSetting {
+    type Err = String;
+
+    /// Parses "UTC", an offset like "+01:00" or "-0500", or an IANA name like
+    /// "Europe/Amsterdam"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(TimeZoneSetting::Fixed(FixedOffset::east_opt(0).unwrap()));
+        }
+        if s.starts_with(['+', '-']) {
+            let offset =
+                DateTime::parse_from_str(&format!("2000-01-01 00:00 {}", s), "%F %R %z")
+                    .map_err(|_| format!("invalid UTC offset '{}' (expected like +01:00)", s))?;
+            return Ok(TimeZoneSetting::Fixed(*offset.offset()));
+        }
+        s.parse().map(TimeZoneSetting::Named).map_err(|_| {
+            format!(
+                "unknown time zone '{}' (expected UTC, an offset like +01:00 or a name like Europe/Amsterdam)",
+                s
+            )
+        })
+    }
+}
+
+/// A strftime-style date format from `--date-format`, for all columns or, written as
+/// `column=format`, for one column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFormatSpec {
+    pub column: Option<String>,
+    pub format: String,
+}
+
+impl FromStr for DateFormatSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, format) = match s.split_once('=') {
+            Some((column, format)) if !column.contains('%') => {
+                (Some(column.trim().to_string()), format)
+            }
+            _ => (None, s),
+        };
+        DatePattern::custom(format)?;
+        Ok(Self {
+            column,
+            format: format.to_string(),
+        })
+    }
+}
+
+/// Date recognition settings, off unless `detect` is set or formats are given
+#[derive(Debug, Clone, Default)]
+pub struct DateOptions {
+    /// Recognize common formats like "2024-03-01", "03/01/2024 14:05" and "1 Mar 2024"
+    pub detect: bool,
+    /// Formats tried before the common ones, or the only format of a column
+    pub formats: Vec<DateFormatSpec>,
+    /// Read ambiguous dates like "01/02/2024" as day/month when scanning a column
+    /// cannot tell
+    pub day_first: bool,
+    pub output: DateOutput,
+    /// Zone of dates and times without an offset; without one they stay local times
+    /// (and count as UTC for epoch output)
+    pub timezone: Option<TimeZoneSetting>,
+}
+
+impl DateOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.detect || !self.formats.is_empty()
+    }
+
+    /// Formats for columns that cannot be scanned up front: every format is tried in
+    /// each column, reading ambiguous dates in the preferred order
+    pub fn unscanned(&self) -> ColumnDates {
+        let mut candidates = self.candidates();
+        let dropped = if self.day_first {
+            FieldOrder::MonthFirst
+        } else {
+            FieldOrder::DayFirst
+        };
+        candidates.retain(|pattern| pattern.order != Some(dropped));
+
+        ColumnDates {
+            default: (!candidates.is_empty()).then(|| self.column(candidates)),
+            columns: self.column_formats(),
+        }
+    }
+
+    /// Chooses the formats of each column from sample rows, returning them by column
+    /// index along with the names of columns whose dates could be read either way
+    /// (month or day first)
+    pub fn scan(
+        &self,
+        headers: &StringRecord,
+        rows: &[StringRecord],
+    ) -> (Vec<Option<Arc<DateColumn>>>, Vec<String>) {
+        let candidates = self.candidates();
+        let by_name = self.column_formats();
+        let mut ambiguous = Vec::new();
+
+        let columns = headers
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                if let Some(column) = by_name.get(name) {
+                    return Some(column.clone());
+                }
+
+                let mut matches = vec![0usize; candidates.len()];
+                for field in rows.iter().filter_map(|row| row.get(i)) {
+                    for (count, pattern) in matches.iter_mut().zip(&candidates) {
+                        if !field.is_empty() && pattern.parse(field).is_some() {
+                            *count += 1;
+                        }
+                    }
+                }
+
+                // Dates that only fit one order decide the column; a tie falls back to
+                // the preference
+                let total = |order| {
+                    candidates
+                        .iter()
+                        .zip(&matches)
+                        .filter(|(pattern, _)| pattern.order == Some(order))
+                        .map(|(_, &count)| count)
+                        .sum::<usize>()
+                };
+                let (month_first, day_first) =
+                    (total(FieldOrder::MonthFirst), total(FieldOrder::DayFirst));
+                let dropped = if month_first > day_first {
+                    FieldOrder::DayFirst
+                } else if day_first > month_first {
+                    FieldOrder::MonthFirst
+                } else {
+                    if month_first > 0 {
+                        ambiguous.push(name.to_string());
+                    }
+                    if self.day_first {
+                        FieldOrder::MonthFirst
+                    } else {
+                        FieldOrder::DayFirst
+                    }
+                };
+
+                let mut found: Vec<(usize, &DatePattern)> = matches
+                    .into_iter()
+                    .zip(&candidates)
+                    .filter(|&(count, pattern)| count > 0 && pattern.order != Some(dropped))
+                    .collect();
+                // Most common formats are tried first; the sort keeps the given order
+                // among equals
+                found.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+
+                (!found.is_empty())
+                    .then(|| self.column(found.into_iter().map(|(_, p)| p.clone()).collect()))
+            })
+            .collect();
+
+        (columns, ambiguous)
+    }
+
+    /// Reports the columns that `scan` found ambiguous and the order they are read in
+    pub fn warn_ambiguous(&self, columns: &[String]) {
+        let order = if self.day_first {
+            "day/month"
+        } else {
+            "month/day (use --day-first for day/month)"
+        };
+        for name in columns {
+            eprintln!(
+                "Warning: dates in column '{}' fit both month/day and day/month order; reading them as {}",
+                name, order
+            );
+        }
+    }
+
+    /// Formats given for all columns followed by the common ones
+    fn candidates(&self) -> Vec<DatePattern> {
+        let mut candidates: Vec<DatePattern> = self
+            .formats
+            .iter()
+            .filter(|spec| spec.column.is_none())
+            .filter_map(|spec| DatePattern::custom(&spec.format).ok())
+            .collect();
+        if self.detect {
+            candidates.extend(DatePattern::builtin());
+        }
+        candidates
+    }
+
+    fn column_formats(&self) -> HashMap<String, Arc<DateColumn>> {
+        self.formats
+            .iter()
+            .filter_map(|spec| {
+                let column = spec.column.clone()?;
+                let pattern = DatePattern::custom(&spec.format).ok()?;
+                Some((column, self.column(vec![pattern])))
+            })
+            .collect()
+    }
+
+    fn column(&self, patterns: Vec<DatePattern>) -> Arc<DateColumn> {
+        Arc::new(DateColumn {
+            patterns,
+            output: self.output,
+            timezone: self.timezone,
+        })
+    }
+}
+
+/// Date formats of the columns of an input, by header name
+#[derive(Debug, Clone, Default)]
+pub struct ColumnDates {
+    default: Option<Arc<DateColumn>>,
+    columns: HashMap<String, Arc<DateColumn>>,
+}
+
+impl ColumnDates {
+    pub fn get(&self, header_name: &str) -> Option<Arc<DateColumn>> {
+        self.columns
+            .get(header_name)
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+/// The date formats recognized in one column and how to write the dates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateColumn {
+    patterns: Vec<DatePattern>,
+    output: DateOutput,
+    timezone: Option<TimeZoneSetting>,
+}
+
+/// A recognized date, as text or as a number for epoch output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateValue {
+    Text(String),
+    Epoch(i64),
+}
+
+impl DateColumn {
+    /// Reads a field with the first format that matches it
+    pub fn parse(&self, field: &str) -> Option<DateValue> {
+        let parsed = self.patterns.iter().find_map(|p| p.parse(field))?;
+
+        if self.output == DateOutput::Iso {
+            return Some(DateValue::Text(match (&parsed, self.timezone) {
+                (Parsed::Local(local, false), Some(zone)) => zone
+                    .localize(local)?
+                    .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+                // Dates stay dates, even in a time zone
+                _ => parsed.to_iso(),
+            }));
+        }
+
+        let time = match parsed {
+            Parsed::Zoned(time) => time,
+            Parsed::Local(local, _) => match self.timezone {
+                Some(zone) => zone.localize(&local)?,
+                None => Utc.from_utc_datetime(&local).fixed_offset(),
+            },
+        };
+        Some(DateValue::Epoch(match self.output {
+            DateOutput::EpochSeconds => time.timestamp(),
+            _ => time.timestamp_millis(),
+        }))
+    }
+}
+
+/// Which of day and month comes first in a built-in format like "01/02/2024"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldOrder {
+    MonthFirst,
+    DayFirst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PatternKind {
+    Date,
+    DateTime,
+    /// A date and time with a UTC offset
+    Zoned,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DatePattern {
+    format: String,
+    kind: PatternKind,
+    /// Set for built-in formats that read digits as month and day in a fixed order
+    order: Option<FieldOrder>,
+    /// Built-in formats reject years below 1000, which come from two-digit years
+    four_digit_year: bool,
+}
+
+enum Parsed {
+    Zoned(DateTime<FixedOffset>),
+    /// A local time, and whether it was only a date
+    Local(NaiveDateTime, bool),
+}
+
+impl Parsed {
+    fn to_iso(&self) -> String {
+        match self {
+            Parsed::Zoned(time) => time.to_rfc3339_opts(SecondsFormat::AutoSi, false),
+            Parsed::Local(local, true) => local.format("%Y-%m-%d").to_string(),
+            Parsed::Local(local, false) => local.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+        }
+    }
+}
+
+impl DatePattern {
+    /// Formats recognized with date detection, tried in this order
+    fn builtin() -> Vec<DatePattern> {
+        use FieldOrder::{DayFirst, MonthFirst};
+        use PatternKind::{Date, DateTime, Zoned};
+
+        let formats = [
+            ("%Y-%m-%d", Date, None),
+            ("%Y-%m-%dT%H:%M:%S%.f", DateTime, None),
+            ("%Y-%m-%d %H:%M:%S%.f", DateTime, None),
+            ("%Y-%m-%dT%H:%M", DateTime, None),
+            ("%Y-%m-%d %H:%M", DateTime, None),
+            // RFC 3339, like "2024-03-01T14:05:00Z" or "2024-03-01 14:05:00+01:00"
+            ("%+", Zoned, None),
+            ("%m/%d/%Y", Date, Some(MonthFirst)),
+            ("%m/%d/%Y %H:%M", DateTime, Some(MonthFirst)),
+            ("%m/%d/%Y %H:%M:%S", DateTime, Some(MonthFirst)),
+            ("%d/%m/%Y", Date, Some(DayFirst)),
+            ("%d/%m/%Y %H:%M", DateTime, Some(DayFirst)),
+            ("%d/%m/%Y %H:%M:%S", DateTime, Some(DayFirst)),
+            ("%m-%d-%Y", Date, Some(MonthFirst)),
+            ("%m-%d-%Y %H:%M", DateTime, Some(MonthFirst)),
+            ("%m-%d-%Y %H:%M:%S", DateTime, Some(MonthFirst)),
+            ("%d-%m-%Y", Date, Some(DayFirst)),
+            ("%d-%m-%Y %H:%M", DateTime, Some(DayFirst)),
+            ("%d-%m-%Y %H:%M:%S", DateTime, Some(DayFirst)),
+            ("%d.%m.%Y", Date, None),
+            ("%d.%m.%Y %H:%M", DateTime, None),
+            ("%d.%m.%Y %H:%M:%S", DateTime, None),
+            ("%d %b %Y", Date, None),
+            ("%d %B %Y", Date, None),
+            ("%d-%b-%Y", Date, None),
+            ("%b %d, %Y", Date, None),
+            ("%B %d, %Y", Date, None),
+        ];
+        formats
+            .into_iter()
+            .map(|(format, kind, order)| DatePattern {
+                format: format.to_string(),
+                kind,
+                order,
+                four_digit_year: true,
+            })
+            .collect()
+    }
+
+    /// A user-given strftime-style format, typed by the fields it contains
+    fn custom(format: &str) -> Result<DatePattern, String> {
+        let mut has_date = false;
+        let mut has_time = false;
+        let mut has_offset = false;
+        for item in StrftimeItems::new(format) {
+            match item {
+                Item::Error => return Err(format!("invalid date format '{}'", format)),
+                Item::Numeric(
+                    Numeric::Hour | Numeric::Hour12 | Numeric::Minute | Numeric::Second,
+                    _,
+                )
+                | Item::Fixed(Fixed::Nanosecond | Fixed::LowerAmPm | Fixed::UpperAmPm) => {
+                    has_time = true
+                }
+                Item::Numeric(Numeric::Timestamp, _) | Item::Fixed(Fixed::RFC3339) => {
+                    has_offset = true
+                }
+                Item::Fixed(
+                    Fixed::TimezoneOffset
+                    | Fixed::TimezoneOffsetColon
+                    | Fixed::TimezoneOffsetDoubleColon
+                    | Fixed::TimezoneOffsetColonZ
+                    | Fixed::TimezoneOffsetZ,
+                ) => has_offset = true,
+                Item::Numeric(..) | Item::Fixed(..) => has_date = true,
+                _ => {}
+            }
+        }
+
+        let kind = if has_offset {
+            PatternKind::Zoned
+        } else if has_time {
+            PatternKind::DateTime
+        } else if has_date {
+            PatternKind::Date
+        } else {
+            return Err(format!(
+                "date format '{}' has no date fields (use strftime fields like %Y-%m-%d)",
+                format
+            ));
+        };
+        Ok(DatePattern {
+            format: format.to_string(),
+            kind,
+            order: None,
+            four_digit_year: false,
+        })
+    }
+
+    fn parse(&self, field: &str) -> Option<Parsed> {
+        let parsed = match self.kind {
+            PatternKind::Date => NaiveDate::parse_from_str(field, &self.format)
+                .ok()
+                .map(|date| Parsed::Local(date.and_time(Default::default()), true)),
+            PatternKind::DateTime => NaiveDateTime::parse_from_str(field, &self.format)
+                .ok()
+                .map(|time| Parsed::Local(time, false)),
+            PatternKind::Zoned => DateTime::parse_from_str(field, &self.format)
+                .ok()
+                .map(Parsed::Zoned),
+        }?;
+
+        let year = match &parsed {
+            Parsed::Zoned(time) => time.year(),
+            Parsed::Local(time, _) => time.year(),
+        };
+        if self.four_digit_year && year < 1000 {
+            return None;
+        }
+        Some(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(detect: bool) -> DateOptions {
+        DateOptions {
+            detect,
+            ..Default::default()
+        }
+    }
+
+    fn parse(options: &DateOptions, field: &str) -> Option<DateValue> {
+        options.unscanned().get("any")?.parse(field)
+    }
+
+    fn text(s: &str) -> Option<DateValue> {
+        Some(DateValue::Text(s.to_string()))
+    }
+
+    #[test]
+    fn test_builtin_formats_to_iso() {
+        let options = options(true);
+        assert_eq!(parse(&options, "2024-03-01"), text("2024-03-01"));
+        assert_eq!(
+            parse(&options, "03/01/2024 14:05"),
+            text("2024-03-01T14:05:00")
+        );
+        assert_eq!(parse(&options, "1 Mar 2024"), text("2024-03-01"));
+        assert_eq!(parse(&options, "March 1, 2024"), text("2024-03-01"));
+        assert_eq!(parse(&options, "01.03.2024"), text("2024-03-01"));
+        assert_eq!(
+            parse(&options, "03-01-2024 14:05:09"),
+            text("2024-03-01T14:05:09")
+        );
+        assert_eq!(
+            parse(&options, "2024-03-01T14:05:00.5Z"),
+            text("2024-03-01T14:05:00.500+00:00")
+        );
+
+        // Two-digit years, invalid dates and other text are not dates
+        assert_eq!(parse(&options, "03/01/24"), None);
+        assert_eq!(parse(&options, "2024-02-30"), None);
+        assert_eq!(parse(&options, "1.5"), None);
+        assert_eq!(parse(&options, "March"), None);
+    }
+
+    #[test]
+    fn test_epoch_output_and_time_zones() {
+        let mut options = options(true);
+        options.output = DateOutput::EpochMillis;
+        assert_eq!(
+            parse(&options, "1970-01-02"),
+            Some(DateValue::Epoch(86_400_000))
+        );
+
+        options.timezone = Some("+01:00".parse().unwrap());
+        assert_eq!(
+            parse(&options, "1970-01-01 01:00"),
+            Some(DateValue::Epoch(0))
+        );
+        // Times with their own offset keep it
+        assert_eq!(
+            parse(&options, "1970-01-01T00:00:00Z"),
+            Some(DateValue::Epoch(0))
+        );
+
+        options.output = DateOutput::Iso;
+        options.timezone = Some("Europe/Amsterdam".parse().unwrap());
+        assert_eq!(
+            parse(&options, "2024-07-01 12:00"),
+            text("2024-07-01T12:00:00+02:00")
+        );
+        assert_eq!(parse(&options, "2024-07-01"), text("2024-07-01"));
+        // Skipped by the switch to summer time
+        assert_eq!(parse(&options, "2024-03-31 02:30"), None);
+    }
+
+    #[test]
+    fn test_custom_formats() {
+        let spec: DateFormatSpec = "created=%Y%m%d".parse().unwrap();
+        assert_eq!(spec.column.as_deref(), Some("created"));
+        assert!("%Q".parse::<DateFormatSpec>().is_err());
+        assert!("plain".parse::<DateFormatSpec>().is_err());
+
+        let options = DateOptions {
+            formats: vec![spec, "%d/%m/%y %I:%M %p".parse().unwrap()],
+            ..Default::default()
+        };
+        let dates = options.unscanned();
+        assert_eq!(
+            dates.get("created").unwrap().parse("20240301"),
+            text("2024-03-01")
+        );
+        assert_eq!(
+            dates.get("other").unwrap().parse("01/03/24 2:05 PM"),
+            text("2024-03-01T14:05:00")
+        );
+    }
+
+    #[test]
+    fn test_scan_resolves_day_month_order() {
+        let headers = StringRecord::from(vec!["us", "eu", "unclear", "name"]);
+        let rows: Vec<StringRecord> = [
+            ["03/01/2024", "03/01/2024", "03/01/2024", "Alice"],
+            ["12/25/2024", "25/12/2024", "04/05/2024", "Bob"],
+        ]
+        .into_iter()
+        .map(|row| StringRecord::from(row.to_vec()))
+        .collect();
+
+        let (columns, ambiguous) = options(true).scan(&headers, &rows);
+        let parse = |i: usize| columns[i].as_ref().unwrap().parse("03/01/2024");
+        assert_eq!(parse(0), text("2024-03-01"));
+        assert_eq!(parse(1), text("2024-01-03"));
+        assert_eq!(parse(2), text("2024-03-01"));
+        assert!(columns[3].is_none());
+        assert_eq!(ambiguous, vec!["unclear"]);
+
+        let day_first = DateOptions {
+            day_first: true,
+            ..options(true)
+        };
+        let (columns, _) = day_first.scan(&headers, &rows);
+        assert_eq!(
+            columns[2].as_ref().unwrap().parse("03/01/2024"),
+            text("2024-01-03")
+        );
+    }
+
+    #[test]
+    fn test_scan_dates_with_dashes() {
+        let headers = StringRecord::from(vec!["due"]);
+        let rows: Vec<StringRecord> = ["03-01-2024", "13-01-2024"]
+            .into_iter()
+            .map(|field| StringRecord::from(vec![field]))
+            .collect();
+
+        let (columns, ambiguous) = options(true).scan(&headers, &rows);
+        let due = columns[0].as_ref().unwrap();
+        assert_eq!(due.parse("03-01-2024"), text("2024-01-03"));
+        assert_eq!(due.parse("13-01-2024"), text("2024-01-13"));
+        assert!(ambiguous.is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+use std::str::FromStr;
+
+const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// Days between the Excel epoch (1899-12-30) and the Unix epoch (1970-01-01)
+const UNIX_EPOCH_SERIAL: i64 = 25569;
+
+/// How Excel date, time and duration cells are written to the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExcelDateMode {
+    /// ISO-8601 dates, datetimes, times and durations
+    #[default]
+    Iso,
+    /// Milliseconds since the Unix epoch (durations as milliseconds)
+    EpochMillis,
+    /// The raw Excel serial number
+    Serial,
+}
+
+impl FromStr for ExcelDateMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "iso" => Ok(ExcelDateMode::Iso),
+            "epoch-ms" | "epoch" => Ok(ExcelDateMode::EpochMillis),
+            "serial" => Ok(ExcelDateMode::Serial),
+            _ => Err(format!(
+                "unknown date mode '{}' (expected 'iso', 'epoch-ms' or 'serial')",
+                s
+            )),
+        }
+    }
+}
+
+/// Formats an Excel date serial (1900 date system) according to the date mode.
+///
+/// Workbooks using the 1904 date system are already shifted to 1900 serials by
+/// calamine, so only the 1900 leap-year bug needs handling here.
+pub fn format_excel_datetime(serial: f64, mode: ExcelDateMode) -> String {
+    match mode {
+        ExcelDateMode::Iso => excel_datetime_to_iso(serial),
+        ExcelDateMode::EpochMillis if serial >= 1.0 => {
+            excel_datetime_to_epoch_ms(serial).to_string()
+        }
+        // Time-only values have no date, so they stay ISO times
+        ExcelDateMode::EpochMillis => excel_datetime_to_iso(serial),
+        ExcelDateMode::Serial => format_serial(serial),
+    }
+}
+
+/// Formats an Excel duration (in days) according to the date mode
+pub fn format_excel_duration(days: f64, mode: ExcelDateMode) -> String {
+    match mode {
+        ExcelDateMode::Iso => excel_duration_to_iso(days),
+        ExcelDateMode::EpochMillis => ((days * MS_PER_DAY as f64).round() as i64).to_string(),
+        ExcelDateMode::Serial => format_serial(days),
+    }
+}
+
+/// Converts an Excel date serial to an ISO-8601 date, datetime or time string.
+///
+/// Serials below 1 are treated as a time of day. Whole serials become plain dates.
+pub fn excel_datetime_to_iso(serial: f64) -> String {
+    if (0.0..1.0).contains(&serial) {
+        let ms_of_day = (serial * MS_PER_DAY as f64).round() as i64;
+        if ms_of_day < MS_PER_DAY {
+            return format_time(ms_of_day);
+        }
+    }
+
+    let (days, ms_of_day) = split_serial(serial);
+    let (year, month, day) = civil_from_days(days - UNIX_EPOCH_SERIAL);
+    if ms_of_day == 0 {
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    } else {
+        format!(
+            "{:04}-{:02}-{:02}T{}",
+            year,
+            month,
+            day,
+            format_time(ms_of_day)
+        )
+    }
+}
+
+/// Converts an Excel date serial to milliseconds since the Unix epoch
+pub fn excel_datetime_to_epoch_ms(serial: f64) -> i64 {
+    let (days, ms_of_day) = split_serial(serial);
+    (days - UNIX_EPOCH_SERIAL) * MS_PER_DAY + ms_of_day
+}
+
+/// Converts an Excel duration in days to an ISO-8601 duration like `PT36H30M`
+pub fn excel_duration_to_iso(days: f64) -> String {
+    let total_ms = (days * MS_PER_DAY as f64).round() as i64;
+    let sign = if total_ms < 0 { "-" } else { "" };
+    let total_ms = total_ms.abs();
+
+    let hours = total_ms / 3_600_000;
+    let minutes = (total_ms / 60_000) % 60;
+    let seconds = (total_ms / 1000) % 60;
+    let millis = total_ms % 1000;
+
+    let mut out = format!("{}PT", sign);
+    if hours > 0 {
+        out.push_str(&format!("{}H", hours));
+    }
+    if minutes > 0 {
+        out.push_str(&format!("{}M", minutes));
+    }
+    if millis > 0 {
+        out.push_str(&format!("{}.{:03}S", seconds, millis));
+    } else if seconds > 0 || (hours == 0 && minutes == 0) {
+        out.push_str(&format!("{}S", seconds));
+    }
+    out
+}
+
+/// Splits a serial into whole days since 1899-12-30 and milliseconds into the day,
+/// correcting for Excel treating 1900 as a leap year.
+fn split_serial(serial: f64) -> (i64, i64) {
+    // Excel counts a non-existent 1900-02-29 as serial 60, so serials before it
+    // are one day behind the real calendar.
+    let serial = if serial < 60.0 { serial + 1.0 } else { serial };
+    let total_ms = (serial * MS_PER_DAY as f64).round() as i64;
+    (
+        total_ms.div_euclid(MS_PER_DAY),
+        total_ms.rem_euclid(MS_PER_DAY),
+    )
+}
+
+fn format_time(ms_of_day: i64) -> String {
+    let hours = ms_of_day / 3_600_000;
+    let minutes = (ms_of_day / 60_000) % 60;
+    let seconds = (ms_of_day / 1000) % 60;
+    let millis = ms_of_day % 1000;
+
+    if millis > 0 {
+        format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+    } else {
+        format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+    }
+}
+
+fn format_serial(serial: f64) -> String {
+    if serial.fract() == 0.0 {
+        format!("{:.0}", serial)
+    } else {
+        serial.to_string()
+    }
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excel_date_to_iso() {
+        assert_eq!(excel_datetime_to_iso(45352.0), "2024-03-01");
+        assert_eq!(excel_datetime_to_iso(25569.0), "1970-01-01");
+    }
+
+    #[test]
+    fn test_excel_datetime_to_iso() {
+        assert_eq!(excel_datetime_to_iso(45352.5), "2024-03-01T12:00:00");
+        assert_eq!(
+            excel_datetime_to_iso(45352.25 + 0.5 / 86400.0),
+            "2024-03-01T06:00:00.500"
+        );
+    }
+
+    #[test]
+    fn test_excel_time_only_to_iso() {
+        assert_eq!(excel_datetime_to_iso(0.75), "18:00:00");
+    }
+
+    #[test]
+    fn test_excel_1900_leap_year_bug() {
+        assert_eq!(excel_datetime_to_iso(1.0), "1900-01-01");
+        assert_eq!(excel_datetime_to_iso(59.0), "1900-02-28");
+        assert_eq!(excel_datetime_to_iso(61.0), "1900-03-01");
+    }
+
+    #[test]
+    fn test_excel_1904_shifted_serial() {
+        // 1904-based serial 0 (1904-01-01) arrives shifted by 1462 days
+        assert_eq!(excel_datetime_to_iso(1462.0), "1904-01-01");
+    }
+
+    #[test]
+    fn test_excel_datetime_to_epoch_ms() {
+        assert_eq!(excel_datetime_to_epoch_ms(25569.0), 0);
+        assert_eq!(excel_datetime_to_epoch_ms(45352.5), 1_709_294_400_000);
+    }
+
+    #[test]
+    fn test_excel_duration_to_iso() {
+        assert_eq!(excel_duration_to_iso(1.5), "PT36H");
+        assert_eq!(excel_duration_to_iso(1.0 / 24.0 + 30.0 / 1440.0), "PT1H30M");
+        assert_eq!(excel_duration_to_iso(0.0), "PT0S");
+        assert_eq!(excel_duration_to_iso(-1.0 / 1440.0), "-PT1M");
+    }
+
+    #[test]
+    fn test_format_excel_datetime_modes() {
+        assert_eq!(
+            format_excel_datetime(25570.0, ExcelDateMode::EpochMillis),
+            "86400000"
+        );
+        assert_eq!(
+            format_excel_datetime(0.5, ExcelDateMode::EpochMillis),
+            "12:00:00"
+        );
+        assert_eq!(
+            format_excel_datetime(45123.5, ExcelDateMode::Serial),
+            "45123.5"
+        );
+    }
+}
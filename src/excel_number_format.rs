@@ -0,0 +1,499 @@
+use std::str::FromStr;
+
+/// How Excel number formats are applied to numeric cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormatMode {
+    /// Write the stored value and ignore the number format
+    #[default]
+    Off,
+    /// Write the text Excel displays, like `"12.50%"` or `"00123"`
+    Display,
+    /// Write the stored value plus the cell's format code
+    Metadata,
+}
+
+impl FromStr for NumberFormatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" | "none" => Ok(NumberFormatMode::Off),
+            "display" => Ok(NumberFormatMode::Display),
+            "metadata" => Ok(NumberFormatMode::Metadata),
+            _ => Err(format!(
+                "unknown number format mode '{}' (expected 'off', 'display' or 'metadata')",
+                s
+            )),
+        }
+    }
+}
+
+/// What kind of value a number format displays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormatKind {
+    #[default]
+    Number,
+    /// A date, time or datetime, like `yyyy-mm-dd`
+    DateTime,
+    /// An elapsed time, like `[h]:mm:ss`
+    Duration,
+}
+
+/// Format code of one of Excel's built-in number formats, by `numFmtId`
+pub fn builtin_format(id: u32) -> Option<&'static str> {
+    Some(match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        5 => "$#,##0_);($#,##0)",
+        6 => "$#,##0_);[Red]($#,##0)",
+        7 => "$#,##0.00_);($#,##0.00)",
+        8 => "$#,##0.00_);[Red]($#,##0.00)",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => return None,
+    })
+}
+
+/// Kind of value shown by a built-in number format
+pub fn builtin_format_kind(id: u32) -> NumberFormatKind {
+    match id {
+        14..=22 | 45 | 47 => NumberFormatKind::DateTime,
+        46 => NumberFormatKind::Duration,
+        _ => NumberFormatKind::Number,
+    }
+}
+
+/// Kind of value shown by a custom format code, judged by its first section.
+///
+/// Date and time letters outside quotes and brackets make it a date; a leading
+/// elapsed-time bracket like `[h]` makes it a duration. These are the same rules
+/// calamine uses, so cells read directly from the sheet XML get the same types.
+pub fn number_format_kind(format_code: &str) -> NumberFormatKind {
+    let mut escaped = false;
+    let mut in_quotes = false;
+    let mut brackets = 0u8;
+    let mut prev = ' ';
+    let mut elapsed = false;
+    let mut am_pm = false;
+
+    for c in format_code.chars() {
+        match (c, escaped, in_quotes, am_pm, brackets) {
+            (_, true, ..) => escaped = false,
+            ('_' | '\\', ..) => escaped = true,
+            ('"', _, true, _, _) => in_quotes = false,
+            (_, _, true, _, _) => {}
+            ('"', ..) => in_quotes = true,
+            (';', ..) => return NumberFormatKind::Number,
+            ('[', ..) => brackets += 1,
+            (']', .., 1) if elapsed => return NumberFormatKind::Duration,
+            (']', ..) => brackets = brackets.saturating_sub(1),
+            ('a' | 'A', _, _, false, 0) => am_pm = true,
+            ('p' | 'm' | '/' | 'P' | 'M', _, _, true, 0) => return NumberFormatKind::DateTime,
+            ('d' | 'm' | 'h' | 'y' | 's' | 'D' | 'M' | 'H' | 'Y' | 'S', _, _, false, 0) => {
+                return NumberFormatKind::DateTime
+            }
+            _ => {
+                if !(elapsed && c.eq_ignore_ascii_case(&prev)) {
+                    elapsed = prev == '[' && matches!(c, 'm' | 'h' | 's' | 'M' | 'H' | 'S');
+                }
+            }
+        }
+        prev = c;
+    }
+
+    NumberFormatKind::Number
+}
+
+/// Whether a format code leaves values as they are
+pub fn is_general_format(format_code: &str) -> bool {
+    format_code.is_empty() || format_code.eq_ignore_ascii_case("general")
+}
+
+/// Formats a number the way Excel displays it with the given format code.
+///
+/// Covers the codes used for plain numbers: digit placeholders, thousands
+/// separators and scaling, percentages, scientific notation, literal text,
+/// currency symbols and separate sections for negative numbers and zero.
+/// Date and fraction codes are not interpreted; calamine already reads
+/// date-formatted cells as dates, and fractions fall back to General.
+pub fn format_number(value: f64, format_code: &str) -> String {
+    let sections = split_sections(format_code);
+
+    // Sections are positive;negative;zero;text. A negative section supplies its
+    // own sign (often parentheses), otherwise a minus sign is prepended.
+    let (section, value, sign) = if value < 0.0 && sections.len() >= 2 {
+        (sections[1].as_str(), -value, "")
+    } else if value == 0.0 && sections.len() >= 3 {
+        (sections[2].as_str(), value, "")
+    } else {
+        let sign = if value < 0.0 { "-" } else { "" };
+        (sections[0].as_str(), value.abs(), sign)
+    };
+
+    let parsed = parse_section(section);
+    let number = match parsed.pattern {
+        Some(ref pattern) if !pattern.contains('/') => {
+            format_digits(value, pattern, parsed.percent)
+        }
+        _ => format_general(value),
+    };
+
+    let mut out = String::from(sign);
+    for part in parsed.parts {
+        match part {
+            Part::Literal(text) => out.push_str(&text),
+            Part::Number => out.push_str(&number),
+            Part::General | Part::Text => out.push_str(&format_general(value)),
+        }
+    }
+    out
+}
+
+/// Formats a number like Excel's General format does for whole and decimal numbers
+pub fn format_general(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        format!("{:.0}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+enum Part {
+    Literal(String),
+    /// Where the digits go
+    Number,
+    /// The `General` keyword
+    General,
+    /// The `@` text placeholder
+    Text,
+}
+
+struct Section {
+    parts: Vec<Part>,
+    /// Digit placeholders, separators and exponent, like `#,##0.00`
+    pattern: Option<String>,
+    percent: bool,
+}
+
+/// Splits a format code on `;`, ignoring semicolons inside quotes and escapes
+fn split_sections(format_code: &str) -> Vec<String> {
+    let mut sections = vec![String::new()];
+    let mut chars = format_code.chars();
+    let mut in_quotes = false;
+
+    while let Some(c) = chars.next() {
+        let current = sections.last_mut().unwrap();
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            '\\' if !in_quotes => {
+                current.push(c);
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            ';' if !in_quotes => sections.push(String::new()),
+            _ => current.push(c),
+        }
+    }
+    sections
+}
+
+fn parse_section(section: &str) -> Section {
+    let mut parts = Vec::new();
+    let mut pattern: Option<String> = None;
+    let mut percent = false;
+    let mut chars = section.char_indices().peekable();
+
+    let push_literal = |parts: &mut Vec<Part>, text: &str| {
+        if let Some(Part::Literal(last)) = parts.last_mut() {
+            last.push_str(text);
+        } else {
+            parts.push(Part::Literal(text.to_string()));
+        }
+    };
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                let text: String = chars
+                    .by_ref()
+                    .map(|(_, c)| c)
+                    .take_while(|&c| c != '"')
+                    .collect();
+                push_literal(&mut parts, &text);
+            }
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    push_literal(&mut parts, &escaped.to_string());
+                }
+            }
+            // `_x` leaves room for the width of x, `*x` repeats x to fill the cell
+            '_' => {
+                chars.next();
+                push_literal(&mut parts, " ");
+            }
+            '*' => {
+                chars.next();
+            }
+            '[' => {
+                let content: String = chars
+                    .by_ref()
+                    .map(|(_, c)| c)
+                    .take_while(|&c| c != ']')
+                    .collect();
+                // `[$€-407]` is a currency symbol with a locale; colours and conditions are dropped
+                if let Some(currency) = content.strip_prefix('$') {
+                    let symbol = currency.split('-').next().unwrap_or_default();
+                    push_literal(&mut parts, symbol);
+                }
+            }
+            '%' => {
+                percent = true;
+                push_literal(&mut parts, "%");
+            }
+            '@' => parts.push(Part::Text),
+            '0' | '#' | '?' | '.' | ',' => {
+                let pattern = match &mut pattern {
+                    Some(pattern) => pattern,
+                    None => {
+                        parts.push(Part::Number);
+                        pattern.insert(String::new())
+                    }
+                };
+                pattern.push(c);
+
+                // An exponent directly follows the digits, like `0.00E+00`
+                if matches!(chars.peek(), Some((_, 'E' | 'e'))) {
+                    let mut lookahead = chars.clone();
+                    lookahead.next();
+                    if let Some(&(_, exponent_sign @ ('+' | '-'))) = lookahead.peek() {
+                        pattern.push('E');
+                        pattern.push(exponent_sign);
+                        lookahead.next();
+                        chars = lookahead;
+                    }
+                }
+            }
+            '/' if pattern.is_some() => pattern.as_mut().unwrap().push(c),
+            'G' | 'g'
+                if section
+                    .get(i..i + "general".len())
+                    .is_some_and(|word| word.eq_ignore_ascii_case("general")) =>
+            {
+                chars.nth("eneral".len() - 1);
+                parts.push(Part::General);
+            }
+            _ => push_literal(&mut parts, &c.to_string()),
+        }
+    }
+
+    Section {
+        parts,
+        pattern,
+        percent,
+    }
+}
+
+/// Formats a number with a digit pattern like `#,##0.00`, `00000` or `0.00E+00`
+fn format_digits(value: f64, pattern: &str, percent: bool) -> String {
+    let value = if percent { value * 100.0 } else { value };
+
+    if let Some((mantissa_pattern, exponent_pattern)) = pattern.split_once('E') {
+        return format_scientific(value, mantissa_pattern, exponent_pattern);
+    }
+
+    let (int_pattern, frac_pattern) = match pattern.split_once('.') {
+        Some((int_pattern, frac_pattern)) => (int_pattern, Some(frac_pattern)),
+        None => (pattern, None),
+    };
+
+    // Commas after the last digit placeholder scale the number down by thousands
+    let trimmed = int_pattern.trim_end_matches(',');
+    let scale_commas = int_pattern.len() - trimmed.len()
+        + frac_pattern.map_or(0, |f| f.len() - f.trim_end_matches(',').len());
+    let value = value / 1000f64.powi(scale_commas as i32);
+    let thousands = trimmed.contains(',');
+    let min_int_digits = trimmed.chars().filter(|&c| c == '0').count();
+
+    let frac_pattern = frac_pattern.map(|f| f.trim_end_matches(','));
+    let decimals = frac_pattern.map_or(0, |f| f.chars().filter(|c| "0#?".contains(*c)).count());
+    let min_decimals = frac_pattern.map_or(0, |f| f.chars().filter(|&c| c == '0').count());
+
+    let rounded = format!("{:.*}", decimals, value);
+    let (int_digits, frac_digits) = rounded.split_once('.').unwrap_or((&rounded, ""));
+
+    let mut int_digits = if int_digits == "0" && min_int_digits == 0 {
+        String::new()
+    } else {
+        format!("{:0>width$}", int_digits, width = min_int_digits)
+    };
+    if thousands {
+        int_digits = group_thousands(&int_digits);
+    }
+
+    let mut frac_digits = frac_digits.to_string();
+    while frac_digits.len() > min_decimals && frac_digits.ends_with('0') {
+        frac_digits.pop();
+    }
+
+    match frac_pattern {
+        Some(_) => format!("{}.{}", int_digits, frac_digits),
+        None => int_digits,
+    }
+}
+
+fn format_scientific(value: f64, mantissa_pattern: &str, exponent_pattern: &str) -> String {
+    let decimals = mantissa_pattern
+        .split_once('.')
+        .map_or(0, |(_, f)| f.chars().filter(|c| "0#?".contains(*c)).count());
+    let exponent_digits = exponent_pattern.chars().filter(|&c| c == '0').count();
+
+    let mut exponent = if value == 0.0 {
+        0
+    } else {
+        value.log10().floor() as i32
+    };
+    let mut mantissa = format!("{:.*}", decimals, value / 10f64.powi(exponent));
+    // Rounding can carry the mantissa up to 10, as in 9.999 -> 10.00
+    if mantissa.starts_with("10") {
+        exponent += 1;
+        mantissa = format!("{:.*}", decimals, value / 10f64.powi(exponent));
+    }
+
+    let sign = if exponent < 0 {
+        "-"
+    } else if exponent_pattern.starts_with('+') {
+        "+"
+    } else {
+        ""
+    };
+    format!(
+        "{}E{}{:0>width$}",
+        mantissa,
+        sign,
+        exponent.abs(),
+        width = exponent_digits
+    )
+}
+
+/// Inserts `,` between groups of three digits
+fn group_thousands(digits: &str) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_percent() {
+        assert_eq!(format_number(0.125, "0.00%"), "12.50%");
+        assert_eq!(format_number(0.5, "0%"), "50%");
+    }
+
+    #[test]
+    fn test_format_leading_zeros() {
+        assert_eq!(format_number(123.0, "00000"), "00123");
+        assert_eq!(format_number(0.5, "#.00"), ".50");
+    }
+
+    #[test]
+    fn test_format_currency() {
+        assert_eq!(format_number(1234.5, "$#,##0.00"), "$1,234.50");
+        assert_eq!(format_number(1234.5, "#,##0.00 [$€-407]"), "1,234.50 €");
+        assert_eq!(
+            format_number(-1234.5, "$#,##0.00_);($#,##0.00)"),
+            "($1,234.50)"
+        );
+        assert_eq!(
+            format_number(1234.5, "$#,##0.00_);($#,##0.00)"),
+            "$1,234.50 "
+        );
+    }
+
+    #[test]
+    fn test_format_sections() {
+        assert_eq!(format_number(-1.5, "0.00"), "-1.50");
+        assert_eq!(format_number(0.0, r#"0.0;-0.0;"zero""#), "zero");
+        assert_eq!(format_number(-2.0, r#"0.0;[Red]"minus "0.0"#), "minus 2.0");
+    }
+
+    #[test]
+    fn test_format_thousands_and_scaling() {
+        assert_eq!(format_number(1234567.0, "#,##0"), "1,234,567");
+        assert_eq!(format_number(1500000.0, r#"#,##0,"K""#), "1,500K");
+        assert_eq!(format_number(999.0, "#,##0"), "999");
+    }
+
+    #[test]
+    fn test_format_scientific() {
+        assert_eq!(format_number(12345.0, "0.00E+00"), "1.23E+04");
+        assert_eq!(format_number(0.00012, "0.00E+00"), "1.20E-04");
+        assert_eq!(format_number(9.999, "0.00E+00"), "1.00E+01");
+    }
+
+    #[test]
+    fn test_format_general_and_text() {
+        assert_eq!(format_number(1.5, "General"), "1.5");
+        assert_eq!(format_number(123.0, "@"), "123");
+        assert_eq!(format_number(3.0, r#"General" units""#), "3 units");
+        assert_eq!(format_number(3.0, r#""€ "General"#), "€ 3");
+    }
+
+    #[test]
+    fn test_number_format_kind() {
+        assert_eq!(number_format_kind("yyyy-mm-dd"), NumberFormatKind::DateTime);
+        assert_eq!(number_format_kind("h:mm AM/PM"), NumberFormatKind::DateTime);
+        assert_eq!(number_format_kind("[h]:mm:ss"), NumberFormatKind::Duration);
+        assert_eq!(number_format_kind("[Red]0.00"), NumberFormatKind::Number);
+        assert_eq!(
+            number_format_kind(r#"0.00" days""#),
+            NumberFormatKind::Number
+        );
+        assert_eq!(number_format_kind("$#,##0.00"), NumberFormatKind::Number);
+        assert_eq!(builtin_format_kind(14), NumberFormatKind::DateTime);
+        assert_eq!(builtin_format_kind(46), NumberFormatKind::Duration);
+    }
+
+    #[test]
+    fn test_builtin_formats() {
+        assert_eq!(builtin_format(10), Some("0.00%"));
+        assert_eq!(builtin_format(49), Some("@"));
+        assert_eq!(builtin_format(164), None);
+        assert!(is_general_format("General"));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A rectangular block of cells given in A1 notation, like `B3:H500`.
+///
+/// Positions are zero-based (row, column) pairs. A range written with column
+/// letters only (`B:H`) or with an open end (`B3:H`) has no last row and extends
+/// to the end of the sheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRange {
+    pub start: (u32, u32),
+    pub end_column: u32,
+    /// Last row of the range, or None to read to the end of the sheet
+    pub end_row: Option<u32>,
+}
+
+impl CellRange {
+    /// Clamp the range to the used area of a sheet, returning (start, end) positions
+    /// or None when the range lies entirely outside it
+    pub fn clamp_to(&self, used_end: (u32, u32)) -> Option<((u32, u32), (u32, u32))> {
+        let end_row = self.end_row.unwrap_or(used_end.0).min(used_end.0);
+        let end_column = self.end_column.min(used_end.1);
+        if self.start.0 > end_row || self.start.1 > end_column {
+            return None;
+        }
+        Some((self.start, (end_row, end_column)))
+    }
+}
+
+impl FromStr for CellRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid cell range '{}' (expected A1 notation like B3:H500)",
+                s
+            )
+        };
+
+        let (start, end) = s.trim().split_once(':').unwrap_or((s.trim(), s.trim()));
+        let (start_col, start_row) = parse_cell_reference(start).ok_or_else(invalid)?;
+        let (end_col, end_row) = parse_cell_reference(end).ok_or_else(invalid)?;
+
+        let start_row = start_row.unwrap_or(0);
+        if end_col < start_col || end_row.is_some_and(|row| row < start_row) {
+            return Err(format!(
+                "invalid cell range '{}' (end comes before start)",
+                s
+            ));
+        }
+
+        Ok(CellRange {
+            start: (start_row, start_col),
+            end_column: end_col,
+            end_row,
+        })
+    }
+}
+
+impl fmt::Display for CellRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            cell_reference(self.start),
+            column_name(self.end_column)
+        )?;
+        if let Some(row) = self.end_row {
+            write!(f, "{}", row + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a sheet-qualified reference like `Report!$B$3:$H$500` or `'Q1 Sales'!A:C`,
+/// as used by defined names, into the sheet name and cell range
+pub fn parse_sheet_reference(reference: &str) -> Option<(String, CellRange)> {
+    let reference = reference.trim().trim_start_matches('=');
+    let (sheet, cells) = reference.rsplit_once('!')?;
+
+    let sheet = match sheet.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        Some(quoted) => quoted.replace("''", "'"),
+        // Unquoted sheet names are plain words; anything else is a formula or a
+        // multi-area reference like `Sheet1!A1,Sheet1!C1`
+        None if sheet.contains([',', '!', '(', ')', ' ']) => return None,
+        None => sheet.to_string(),
+    };
+    if sheet.is_empty() {
+        return None;
+    }
+
+    Some((sheet, cells.parse().ok()?))
+}
+
+/// Parse a reference like `B3` (or just `B`) into a zero-based column and optional row
+pub fn parse_cell_reference(reference: &str) -> Option<(u32, Option<u32>)> {
+    let reference = reference.trim().replace('$', "");
+    let split = reference
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(reference.len());
+    let (letters, digits) = reference.split_at(split);
+
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut column: u32 = 0;
+    for c in letters.chars() {
+        column = column
+            .checked_mul(26)?
+            .checked_add(c.to_ascii_uppercase() as u32 - 'A' as u32 + 1)?;
+    }
+
+    let row = if digits.is_empty() {
+        None
+    } else {
+        let row: u32 = digits.parse().ok()?;
+        if row == 0 {
+            return None;
+        }
+        Some(row - 1)
+    };
+
+    Some((column - 1, row))
+}
+
+/// Format a zero-based column index as Excel column letters (0 -> A, 27 -> AB)
+pub fn column_name(column: u32) -> String {
+    let mut letters = Vec::new();
+    let mut n = column + 1;
+    while n > 0 {
+        let rem = (n - 1) % 26;
+        letters.push(b'A' + rem as u8);
+        n = (n - 1) / 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap()
+}
+
+/// Format a zero-based (row, column) position as an A1 cell reference
+pub fn cell_reference((row, column): (u32, u32)) -> String {
+    format!("{}{}", column_name(column), row + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cell_range() {
+        let range: CellRange = "B3:H500".parse().unwrap();
+        assert_eq!(range.start, (2, 1));
+        assert_eq!(range.end_column, 7);
+        assert_eq!(range.end_row, Some(499));
+    }
+
+    #[test]
+    fn test_parse_open_ended_range() {
+        let range: CellRange = "b3:h".parse().unwrap();
+        assert_eq!(range.start, (2, 1));
+        assert_eq!(range.end_row, None);
+
+        let range: CellRange = "$A:$C".parse().unwrap();
+        assert_eq!(range.start, (0, 0));
+        assert_eq!(range.end_column, 2);
+    }
+
+    #[test]
+    fn test_parse_invalid_range() {
+        assert!("".parse::<CellRange>().is_err());
+        assert!("3B:H5".parse::<CellRange>().is_err());
+        assert!("H3:B5".parse::<CellRange>().is_err());
+        assert!("A0:B5".parse::<CellRange>().is_err());
+    }
+
+    #[test]
+    fn test_column_names() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+        assert_eq!(parse_cell_reference("AAA1"), Some((702, Some(0))));
+    }
+
+    #[test]
+    fn test_parse_sheet_reference() {
+        let (sheet, range) = parse_sheet_reference("Report!$B$3:$C$5").unwrap();
+        assert_eq!(sheet, "Report");
+        assert_eq!(range.to_string(), "B3:C5");
+
+        let (sheet, _) = parse_sheet_reference("'Bob''s Q1'!$A$1:$B$2").unwrap();
+        assert_eq!(sheet, "Bob's Q1");
+
+        assert!(parse_sheet_reference("Report!#REF!").is_none());
+        assert!(parse_sheet_reference("0.21").is_none());
+        assert!(parse_sheet_reference("Report!$A$1,Report!$C$1").is_none());
+    }
+
+    #[test]
+    fn test_clamp_range() {
+        let range: CellRange = "B3:H".parse().unwrap();
+        assert_eq!(range.clamp_to((99, 9)), Some(((2, 1), (99, 7))));
+        assert_eq!(range.clamp_to((99, 3)), Some(((2, 1), (99, 3))));
+        assert_eq!(range.clamp_to((1, 3)), None);
+        assert_eq!(range.clamp_to((99, 0)), None);
+    }
+}
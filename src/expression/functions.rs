@@ -0,0 +1,460 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat};
+use md5::Md5;
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+use super::{number, text, Num};
+
+/// A function that expressions can call, like `upper(name)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Function {
+    Upper,
+    Lower,
+    Trim,
+    Length,
+    Substr,
+    Replace,
+    Concat,
+    Coalesce,
+    Round,
+    Abs,
+    Year,
+    Month,
+    Day,
+    Weekday,
+    FormatDate,
+    AddDays,
+    DaysBetween,
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+/// Names of the functions and their smallest and largest number of arguments
+const FUNCTIONS: [(&str, Function, usize, usize); 20] = [
+    ("upper", Function::Upper, 1, 1),
+    ("lower", Function::Lower, 1, 1),
+    ("trim", Function::Trim, 1, 1),
+    ("length", Function::Length, 1, 1),
+    ("substr", Function::Substr, 2, 3),
+    ("replace", Function::Replace, 3, 3),
+    ("concat", Function::Concat, 1, usize::MAX),
+    ("coalesce", Function::Coalesce, 1, usize::MAX),
+    ("round", Function::Round, 1, 2),
+    ("abs", Function::Abs, 1, 1),
+    ("year", Function::Year, 1, 1),
+    ("month", Function::Month, 1, 1),
+    ("day", Function::Day, 1, 1),
+    ("weekday", Function::Weekday, 1, 1),
+    ("format_date", Function::FormatDate, 2, 2),
+    ("add_days", Function::AddDays, 2, 2),
+    ("days_between", Function::DaysBetween, 2, 2),
+    ("md5", Function::Md5, 1, 1),
+    ("sha1", Function::Sha1, 1, 1),
+    ("sha256", Function::Sha256, 1, 1),
+];
+
+impl Function {
+    /// The function with this name, ignoring case, if the argument count fits
+    pub(super) fn lookup(name: &str, arguments: usize) -> Result<Self, String> {
+        let Some(&(name, function, min, max)) = FUNCTIONS
+            .iter()
+            .find(|(known, ..)| known.eq_ignore_ascii_case(name))
+        else {
+            let names: Vec<&str> = FUNCTIONS.iter().map(|(name, ..)| *name).collect();
+            return Err(format!(
+                "unknown function '{}' (expected one of {})",
+                name,
+                names.join(", ")
+            ));
+        };
+
+        if (min..=max).contains(&arguments) {
+            return Ok(function);
+        }
+        let expected = match (min, max) {
+            (min, max) if min == max => min.to_string(),
+            (min, usize::MAX) => format!("at least {}", min),
+            (min, max) => format!("{} or {}", min, max),
+        };
+        Err(format!(
+            "{}() takes {} argument{}, not {}",
+            name,
+            expected,
+            if expected.ends_with(" 1") || expected == "1" {
+                ""
+            } else {
+                "s"
+            },
+            arguments
+        ))
+    }
+
+    fn name(self) -> &'static str {
+        FUNCTIONS
+            .iter()
+            .find(|(_, function, ..)| *function == self)
+            .map(|(name, ..)| *name)
+            .expect("every function has a name")
+    }
+
+    /// Calls the function. Functions of one value give null for null, and date functions
+    /// give null for values that are not dates.
+    pub(super) fn call(self, arguments: Vec<Value>) -> Result<Value> {
+        match self {
+            Function::Concat => {
+                let joined: String = arguments
+                    .iter()
+                    .filter(|argument| !argument.is_null())
+                    .map(text)
+                    .collect();
+                return Ok(Value::String(joined));
+            }
+            Function::Coalesce => {
+                let first = arguments.into_iter().find(|argument| !argument.is_null());
+                return Ok(first.unwrap_or(Value::Null));
+            }
+            _ if arguments[0].is_null() => return Ok(Value::Null),
+            _ => {}
+        }
+
+        let value = &arguments[0];
+        Ok(match self {
+            Function::Upper => Value::String(text(value).to_uppercase()),
+            Function::Lower => Value::String(text(value).to_lowercase()),
+            Function::Trim => Value::String(text(value).trim().to_string()),
+            Function::Length => Value::from(match value {
+                Value::Array(elements) => elements.len(),
+                Value::Object(members) => members.len(),
+                other => text(other).chars().count(),
+            }),
+            Function::Substr => {
+                let start = self.integer(&arguments[1])?;
+                let length = match arguments.get(2) {
+                    Some(length) => Some(self.integer(length)?.max(0) as usize),
+                    None => None,
+                };
+                Value::String(substring(&text(value), start, length))
+            }
+            Function::Replace => {
+                let (from, to) = (text(&arguments[1]), text(&arguments[2]));
+                if from.is_empty() {
+                    Value::String(text(value))
+                } else {
+                    Value::String(text(value).replace(&from, &to))
+                }
+            }
+            Function::Round => {
+                let digits = match arguments.get(1) {
+                    Some(digits) => self.integer(digits)?,
+                    None => 0,
+                };
+                match self.number(value)? {
+                    Num::Int(int) if digits >= 0 => Value::from(int),
+                    number => round(number.to_f64(), digits),
+                }
+            }
+            Function::Abs => match self.number(value)? {
+                Num::Int(int) => int
+                    .checked_abs()
+                    .map_or_else(|| Value::from((int as f64).abs()), Value::from),
+                Num::Float(float) => Value::from(float.abs()),
+            },
+            Function::Year | Function::Month | Function::Day | Function::Weekday => {
+                let Some(date) = DateValue::parse(value) else {
+                    return Ok(Value::Null);
+                };
+                let date = date.date();
+                Value::from(match self {
+                    Function::Year => date.year() as i64,
+                    Function::Month => date.month() as i64,
+                    Function::Day => date.day() as i64,
+                    _ => date.weekday().number_from_monday() as i64,
+                })
+            }
+            Function::FormatDate => {
+                let Some(date) = DateValue::parse(value) else {
+                    return Ok(Value::Null);
+                };
+                Value::String(date.format(&text(&arguments[1]))?)
+            }
+            Function::AddDays => {
+                let Some(date) = DateValue::parse(value) else {
+                    return Ok(Value::Null);
+                };
+                if arguments[1].is_null() {
+                    return Ok(Value::Null);
+                }
+                let days = Duration::try_days(self.integer(&arguments[1])?);
+                match days.and_then(|days| date.add(days)) {
+                    Some(date) => Value::String(date.to_iso()),
+                    None => bail!("add_days() goes past the supported dates"),
+                }
+            }
+            Function::DaysBetween => {
+                match (DateValue::parse(value), DateValue::parse(&arguments[1])) {
+                    (Some(start), Some(end)) => Value::from((end.date() - start.date()).num_days()),
+                    _ => Value::Null,
+                }
+            }
+            Function::Md5 => Value::String(hex_digest::<Md5>(value)),
+            Function::Sha1 => Value::String(hex_digest::<Sha1>(value)),
+            Function::Sha256 => Value::String(hex_digest::<Sha256>(value)),
+            Function::Concat | Function::Coalesce => unreachable!("handled above"),
+        })
+    }
+
+    fn number(self, value: &Value) -> Result<Num> {
+        match number(value) {
+            Some(number) => Ok(number),
+            None => bail!("{}() needs a number, not {}", self.name(), value),
+        }
+    }
+
+    fn integer(self, value: &Value) -> Result<i64> {
+        match number(value) {
+            Some(Num::Int(int)) => Ok(int),
+            _ => bail!("{}() needs a whole number, not {}", self.name(), value),
+        }
+    }
+}
+
+/// `length` characters of `text` from `start`, counting from the end when negative
+fn substring(text: &str, start: i64, length: Option<usize>) -> String {
+    let count = text.chars().count();
+    let start = if start < 0 {
+        count.saturating_sub(start.unsigned_abs() as usize)
+    } else {
+        (start as usize).min(count)
+    };
+    let chars = text.chars().skip(start);
+    match length {
+        Some(length) => chars.take(length).collect(),
+        None => chars.collect(),
+    }
+}
+
+/// Rounds half away from zero to `digits` decimals, or to tens, hundreds and so on when
+/// negative
+fn round(number: f64, digits: i64) -> Value {
+    let scale = 10f64.powi(digits.clamp(-308, 308) as i32);
+    let rounded = (number * scale).round() / scale;
+    if digits <= 0 && rounded.abs() < i64::MAX as f64 {
+        Value::from(rounded as i64)
+    } else {
+        Value::from(rounded)
+    }
+}
+
+/// The hash of a value's text as lowercase hexadecimal
+fn hex_digest<D: Digest>(value: &Value) -> String {
+    let hash = D::digest(text(value).as_bytes());
+    let mut hex = String::with_capacity(hash.len() * 2);
+    for byte in hash {
+        write!(hex, "{:02x}", byte).expect("writing to a string cannot fail");
+    }
+    hex
+}
+
+/// A date or time as written by the date conversion: `2024-03-01`,
+/// `2024-03-01T14:30:00` or with an offset like `2024-03-01T14:30:00+01:00`
+enum DateValue {
+    Date(NaiveDate),
+    Local(NaiveDateTime),
+    Zoned(DateTime<FixedOffset>),
+}
+
+impl DateValue {
+    fn parse(value: &Value) -> Option<Self> {
+        let Value::String(text) = value else {
+            return None;
+        };
+        if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            return Some(DateValue::Date(date));
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+            return Some(DateValue::Zoned(time));
+        }
+        [
+            "%Y-%m-%dT%H:%M:%S%.f",
+            "%Y-%m-%d %H:%M:%S%.f",
+            "%Y-%m-%dT%H:%M",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .map(DateValue::Local)
+    }
+
+    /// The calendar date, in the time's own offset for times with one
+    fn date(&self) -> NaiveDate {
+        match self {
+            DateValue::Date(date) => *date,
+            DateValue::Local(time) => time.date(),
+            DateValue::Zoned(time) => time.date_naive(),
+        }
+    }
+
+    fn add(&self, duration: Duration) -> Option<Self> {
+        Some(match self {
+            DateValue::Date(date) => DateValue::Date(date.checked_add_signed(duration)?),
+            DateValue::Local(time) => DateValue::Local(time.checked_add_signed(duration)?),
+            DateValue::Zoned(time) => DateValue::Zoned(time.checked_add_signed(duration)?),
+        })
+    }
+
+    /// Formats like the date conversion does
+    fn to_iso(&self) -> String {
+        match self {
+            DateValue::Date(date) => date.format("%Y-%m-%d").to_string(),
+            DateValue::Local(time) => time.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            DateValue::Zoned(time) => time.to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        }
+    }
+
+    fn format(&self, format: &str) -> Result<String> {
+        let mut formatted = String::new();
+        let written = match self {
+            DateValue::Date(date) => write!(formatted, "{}", date.format(format)),
+            DateValue::Local(time) => write!(formatted, "{}", time.format(format)),
+            DateValue::Zoned(time) => write!(formatted, "{}", time.format(format)),
+        };
+        if written.is_err() {
+            bail!(
+                "format_date() cannot format {} with '{}'",
+                self.to_iso(),
+                format
+            );
+        }
+        Ok(formatted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(name: &str, arguments: Value) -> Result<Value> {
+        let Value::Array(arguments) = arguments else {
+            panic!("arguments are an array")
+        };
+        Function::lookup(name, arguments.len())
+            .unwrap()
+            .call(arguments)
+    }
+
+    #[test]
+    fn test_text_and_number_functions() {
+        assert_eq!(call("upper", json!(["straße"])).unwrap(), json!("STRASSE"));
+        assert_eq!(call("trim", json!(["  a b "])).unwrap(), json!("a b"));
+        assert_eq!(call("length", json!(["Zoé"])).unwrap(), json!(3));
+        assert_eq!(call("length", json!([["a", "b"]])).unwrap(), json!(2));
+        assert_eq!(
+            call("substr", json!(["Amsterdam", 0, 3])).unwrap(),
+            json!("Ams")
+        );
+        assert_eq!(
+            call("substr", json!(["Amsterdam", -3])).unwrap(),
+            json!("dam")
+        );
+        assert_eq!(
+            call("substr", json!(["Amsterdam", 20, 3])).unwrap(),
+            json!("")
+        );
+        assert_eq!(
+            call("replace", json!(["a-b-c", "-", ""])).unwrap(),
+            json!("abc")
+        );
+        assert_eq!(
+            call("concat", json!(["a", null, 1, true])).unwrap(),
+            json!("a1true")
+        );
+        assert_eq!(call("coalesce", json!([null, 0, 1])).unwrap(), json!(0));
+        assert_eq!(call("round", json!([2.345, 2])).unwrap(), json!(2.35));
+        assert_eq!(call("round", json!([-2.5])).unwrap(), json!(-3));
+        assert_eq!(call("round", json!([1234, -2])).unwrap(), json!(1200));
+        assert_eq!(call("abs", json!([-4])).unwrap(), json!(4));
+        assert_eq!(call("upper", json!([null])).unwrap(), json!(null));
+
+        let error = call("substr", json!(["abc", 1.5])).unwrap_err();
+        assert_eq!(error.to_string(), "substr() needs a whole number, not 1.5");
+        let error = call("abs", json!(["x"])).unwrap_err();
+        assert_eq!(error.to_string(), "abs() needs a number, not \"x\"");
+    }
+
+    #[test]
+    fn test_date_functions() {
+        assert_eq!(call("year", json!(["2024-02-29"])).unwrap(), json!(2024));
+        assert_eq!(
+            call("month", json!(["2024-02-29T23:30:00"])).unwrap(),
+            json!(2)
+        );
+        assert_eq!(
+            call("day", json!(["2024-02-29T23:30:00-05:00"])).unwrap(),
+            json!(29)
+        );
+        assert_eq!(call("weekday", json!(["2024-03-03"])).unwrap(), json!(7));
+        assert_eq!(call("year", json!(["yesterday"])).unwrap(), json!(null));
+        assert_eq!(
+            call(
+                "format_date",
+                json!(["2024-03-01T14:30:00", "%d/%m/%Y %H:%M"])
+            )
+            .unwrap(),
+            json!("01/03/2024 14:30")
+        );
+        assert_eq!(
+            call("add_days", json!(["2024-02-28", 2])).unwrap(),
+            json!("2024-03-01")
+        );
+        assert_eq!(
+            call("add_days", json!(["2024-02-28T10:00:00+01:00", -1])).unwrap(),
+            json!("2024-02-27T10:00:00+01:00")
+        );
+        assert_eq!(
+            call("days_between", json!(["2024-01-01", "2024-03-01T08:00:00"])).unwrap(),
+            json!(60)
+        );
+
+        let error = call("format_date", json!(["2024-03-01", "%H:%M"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "format_date() cannot format 2024-03-01 with '%H:%M'"
+        );
+    }
+
+    #[test]
+    fn test_hash_functions() {
+        assert_eq!(
+            call("md5", json!(["abc"])).unwrap(),
+            json!("900150983cd24fb0d6963f7d28e17f72")
+        );
+        assert_eq!(
+            call("sha1", json!(["abc"])).unwrap(),
+            json!("a9993e364706816aba3e25717850c26c9cd0d89d")
+        );
+        assert_eq!(
+            call("sha256", json!([42])).unwrap(),
+            json!("73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049")
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(Function::lookup("UPPER", 1), Ok(Function::Upper));
+        assert_eq!(
+            Function::lookup("upper", 2),
+            Err("upper() takes 1 argument, not 2".to_string())
+        );
+        assert_eq!(
+            Function::lookup("substr", 1),
+            Err("substr() takes 2 or 3 arguments, not 1".to_string())
+        );
+        assert!(Function::lookup("shout", 1)
+            .unwrap_err()
+            .starts_with("unknown function 'shout'"));
+    }
+}
@@ -0,0 +1,856 @@
+use anyhow::{bail, Result};
+use regex::Regex;
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use functions::Function;
+
+mod functions;
+
+/// An expression over the converted values of a record, like
+/// `country == 'NL' && amount > 100`.
+///
+/// Columns are referred to by header name, or between backticks for names that are not
+/// plain identifiers, like `` `Order Date` ``. Strings are quoted with `'` or `"`.
+/// Supported are `+ - * / %` on numbers, `+` on strings, the comparisons
+/// `== != < <= > >=`, `contains`, `matches '<regex>'` (or `=~`), `is null`,
+/// `is not null`, and `&&`/`and`, `||`/`or`, `!`/`not`. Arithmetic on null gives null,
+/// null only equals null, and ordering null with `<` and the like is false.
+///
+/// Functions like `upper(name)`, `round(price * 1.21, 2)`, `year(created)` and
+/// `sha256(email)` are listed in the `functions` module.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Expr,
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = ExprParser {
+            tokens,
+            next: 0,
+            end: s.chars().count(),
+        };
+        let root = parser.or()?;
+        if let Some((token, position)) = parser.tokens.get(parser.next) {
+            return Err(format!(
+                "unexpected {} at position {}",
+                token.describe(),
+                position + 1
+            ));
+        }
+        Ok(Self {
+            source: s.to_string(),
+            root,
+        })
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expression {
+    /// Fails when the expression refers to a column that is not in `columns`
+    pub fn check_columns(&self, columns: &[&str]) -> Result<()> {
+        let mut referenced = Vec::new();
+        self.root.columns(&mut referenced);
+        for name in referenced {
+            if !columns.contains(&name) {
+                bail!(
+                    "Unknown column '{}' (the columns are: {})",
+                    name,
+                    columns.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of the expression for a record; columns missing from the record are null
+    pub fn evaluate(&self, record: &Map<String, Value>) -> Result<Value> {
+        self.root.evaluate(record)
+    }
+
+    /// Whether the expression is true for a record; null counts as false
+    pub fn is_true(&self, record: &Map<String, Value>) -> Result<bool> {
+        match self.evaluate(record)? {
+            Value::Bool(result) => Ok(result),
+            Value::Null => Ok(false),
+            other => bail!(
+                "Expression \"{}\" gave {} instead of true or false",
+                self.source,
+                other
+            ),
+        }
+    }
+}
+
+/// A column computed from the other values of a record, written as `name=expression`
+#[derive(Debug, Clone)]
+pub struct AddedColumn {
+    pub name: String,
+    pub expression: Expression,
+}
+
+impl FromStr for AddedColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, expression)) = s.split_once('=') else {
+            return Err(format!("expected 'name=expression', got '{}'", s));
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("missing column name in '{}'", s));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            expression: expression.trim().parse()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Column(String),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    IsNull(Box<Expr>),
+    Matches(Box<Expr>, Regex),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Contains,
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Remainder => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessOrEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterOrEqual => ">=",
+            BinaryOp::Contains => "contains",
+        }
+    }
+}
+
+impl Expr {
+    /// Adds the names of the referenced columns to `names`
+    fn columns<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Column(name) => names.push(name),
+            Expr::Not(operand)
+            | Expr::Negate(operand)
+            | Expr::IsNull(operand)
+            | Expr::Matches(operand, _) => operand.columns(names),
+            Expr::And(left, right) | Expr::Or(left, right) | Expr::Binary(_, left, right) => {
+                left.columns(names);
+                right.columns(names);
+            }
+            Expr::Call(_, arguments) => {
+                for argument in arguments {
+                    argument.columns(names);
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, record: &Map<String, Value>) -> Result<Value> {
+        Ok(match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Column(name) => record.get(name).cloned().unwrap_or(Value::Null),
+            Expr::Not(operand) => match operand.evaluate(record)? {
+                Value::Null => Value::Null,
+                value => Value::Bool(!truth(&value, "!")?),
+            },
+            Expr::Negate(operand) => match operand.evaluate(record)? {
+                Value::Null => Value::Null,
+                value => match number(&value) {
+                    Some(Num::Int(int)) => int
+                        .checked_neg()
+                        .map_or_else(|| Value::from(-(int as f64)), Value::from),
+                    Some(Num::Float(float)) => Value::from(-float),
+                    None => bail!("Cannot negate {}", value),
+                },
+            },
+            // Null counts as false, so `a && b` and `a || b` are always true or false
+            Expr::And(left, right) => {
+                let result =
+                    truth(&left.evaluate(record)?, "&&")? && truth(&right.evaluate(record)?, "&&")?;
+                Value::Bool(result)
+            }
+            Expr::Or(left, right) => {
+                let result =
+                    truth(&left.evaluate(record)?, "||")? || truth(&right.evaluate(record)?, "||")?;
+                Value::Bool(result)
+            }
+            Expr::IsNull(operand) => Value::Bool(operand.evaluate(record)?.is_null()),
+            Expr::Matches(operand, pattern) => Value::Bool(match operand.evaluate(record)? {
+                Value::String(text) => pattern.is_match(&text),
+                value @ (Value::Number(_) | Value::Bool(_)) => pattern.is_match(&value.to_string()),
+                _ => false,
+            }),
+            Expr::Binary(op, left, right) => {
+                binary(*op, left.evaluate(record)?, right.evaluate(record)?)?
+            }
+            Expr::Call(function, arguments) => {
+                let values = arguments
+                    .iter()
+                    .map(|argument| argument.evaluate(record))
+                    .collect::<Result<Vec<_>>>()?;
+                function.call(values)?
+            }
+        })
+    }
+}
+
+/// Whether a value counts as true for `&&`, `||` and `!`
+fn truth(value: &Value, op: &str) -> Result<bool> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        Value::Null => Ok(false),
+        other => bail!(
+            "Cannot use {} with '{}', which needs true or false",
+            other,
+            op
+        ),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn to_f64(self) -> f64 {
+        match self {
+            Num::Int(int) => int as f64,
+            Num::Float(float) => float,
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<Num> {
+    let Value::Number(number) = value else {
+        return None;
+    };
+    match number.as_i64() {
+        Some(int) => Some(Num::Int(int)),
+        None => number.as_f64().map(Num::Float),
+    }
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value> {
+    let comparison =
+        |accept: fn(Ordering) -> bool| Value::Bool(compare(&left, &right).is_some_and(accept));
+
+    Ok(match op {
+        BinaryOp::Equal => Value::Bool(equal(&left, &right)),
+        BinaryOp::NotEqual => Value::Bool(!equal(&left, &right)),
+        BinaryOp::Less => comparison(Ordering::is_lt),
+        BinaryOp::LessOrEqual => comparison(Ordering::is_le),
+        BinaryOp::Greater => comparison(Ordering::is_gt),
+        BinaryOp::GreaterOrEqual => comparison(Ordering::is_ge),
+        BinaryOp::Contains => Value::Bool(match (&left, &right) {
+            (Value::String(text), Value::String(part)) => text.contains(part.as_str()),
+            (Value::Array(elements), element) => {
+                elements.iter().any(|candidate| equal(candidate, element))
+            }
+            _ => false,
+        }),
+        _ if left.is_null() || right.is_null() => Value::Null,
+        BinaryOp::Add if left.is_string() || right.is_string() => {
+            Value::String(format!("{}{}", text(&left), text(&right)))
+        }
+        _ => arithmetic(op, &left, &right)?,
+    })
+}
+
+/// A value as text in concatenations, without the quotes of JSON strings
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn arithmetic(op: BinaryOp, left: &Value, right: &Value) -> Result<Value> {
+    let (Some(a), Some(b)) = (number(left), number(right)) else {
+        bail!("Cannot apply '{}' to {} and {}", op.symbol(), left, right);
+    };
+
+    if let (Num::Int(a), Num::Int(b)) = (a, b) {
+        let exact = match op {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Subtract => a.checked_sub(b),
+            BinaryOp::Multiply => a.checked_mul(b),
+            BinaryOp::Divide if b != 0 && a.checked_rem(b) == Some(0) => a.checked_div(b),
+            BinaryOp::Remainder if b == 0 => bail!("Division by zero in {} % {}", a, b),
+            BinaryOp::Remainder => a.checked_rem(b),
+            _ => None,
+        };
+        if let Some(result) = exact {
+            return Ok(Value::from(result));
+        }
+    }
+
+    let (a, b) = (a.to_f64(), b.to_f64());
+    if b == 0.0 && matches!(op, BinaryOp::Divide | BinaryOp::Remainder) {
+        bail!("Division by zero in {} {} {}", left, op.symbol(), right);
+    }
+    let result = match op {
+        BinaryOp::Add => a + b,
+        BinaryOp::Subtract => a - b,
+        BinaryOp::Multiply => a * b,
+        BinaryOp::Divide => a / b,
+        BinaryOp::Remainder => a % b,
+        _ => unreachable!("not an arithmetic operator"),
+    };
+    Ok(Value::from(result))
+}
+
+fn equal(left: &Value, right: &Value) -> bool {
+    match (number(left), number(right)) {
+        (Some(_), Some(_)) => compare(left, right) == Some(Ordering::Equal),
+        _ => left == right,
+    }
+}
+
+/// Orders two numbers or two strings; other values are not ordered
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => match (number(left)?, number(right)?) {
+            (Num::Int(a), Num::Int(b)) => Some(a.cmp(&b)),
+            (a, b) => a.to_f64().partial_cmp(&b.to_f64()),
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Text(String),
+    /// A bare name: a column or a keyword like `and`
+    Name(String),
+    /// A column name between backticks
+    Column(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(number) => format!("number {}", number),
+            Token::Text(text) => format!("string '{}'", text),
+            Token::Name(name) => format!("'{}'", name),
+            Token::Column(name) => format!("`{}`", name),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Name(name) if name.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// Symbols, longest first so `<=` is not read as `<` and `=`
+const SYMBOLS: [&str; 18] = [
+    "==", "!=", "<=", ">=", "&&", "||", "=~", "<", ">", "!", "+", "-", "*", "/", "%", "(", ")", ",",
+];
+
+/// Splits an expression into tokens, each with its position in characters
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                i += 1;
+                if i < chars.len() && matches!(chars[i], '+' | '-') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            Token::Number(chars[start..i].iter().collect())
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.')) {
+                i += 1;
+            }
+            Token::Name(chars[start..i].iter().collect())
+        } else if matches!(c, '\'' | '"' | '`') {
+            // Quotes are escaped by doubling them, like 'it''s'
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(format!(
+                            "unterminated {} starting at position {}",
+                            if c == '`' { "column name" } else { "string" },
+                            start + 1
+                        ))
+                    }
+                    Some(&quote) if quote == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some(&quote) if quote == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            if c == '`' {
+                Token::Column(text)
+            } else {
+                Token::Text(text)
+            }
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) else {
+                let hint = if c == '=' { " (use == to compare)" } else { "" };
+                return Err(format!(
+                    "unexpected '{}' at position {}{}",
+                    c,
+                    start + 1,
+                    hint
+                ));
+            };
+            i += symbol.chars().count();
+            Token::Symbol(symbol)
+        };
+        tokens.push((token, start));
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, one method per precedence level from lowest to highest
+struct ExprParser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Length of the source, for errors at its end
+    end: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    /// Consumes the next token if it is one of `symbols` or keywords
+    fn accept(&mut self, alternatives: &[&str]) -> bool {
+        let found = self.peek().is_some_and(|token| {
+            alternatives.iter().any(|alternative| {
+                matches!(token, Token::Symbol(symbol) if symbol == alternative)
+                    || token.is_keyword(alternative)
+            })
+        });
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn error(&self, expected: &str) -> String {
+        match self.tokens.get(self.next) {
+            Some((token, position)) => format!(
+                "expected {} at position {}, found {}",
+                expected,
+                position + 1,
+                token.describe()
+            ),
+            None => format!("expected {} at position {}", expected, self.end + 1),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.accept(&["||", "or"]) {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.accept(&["&&", "and"]) {
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.accept(&["!", "not"]) {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.additive()?;
+
+        if self.accept(&["is"]) {
+            let negated = self.accept(&["not"]);
+            if !self.accept(&["null"]) {
+                return Err(self.error("'null'"));
+            }
+            let is_null = Expr::IsNull(Box::new(left));
+            return Ok(if negated {
+                Expr::Not(Box::new(is_null))
+            } else {
+                is_null
+            });
+        }
+
+        if self.accept(&["matches", "=~"]) {
+            let Some(Token::Text(pattern)) = self.peek().cloned() else {
+                return Err(self.error("a quoted regular expression"));
+            };
+            let regex = Regex::new(&pattern)
+                .map_err(|e| format!("invalid regular expression '{}': {}", pattern, e))?;
+            self.next += 1;
+            return Ok(Expr::Matches(Box::new(left), regex));
+        }
+
+        let operators = [
+            ("==", BinaryOp::Equal),
+            ("!=", BinaryOp::NotEqual),
+            ("<=", BinaryOp::LessOrEqual),
+            (">=", BinaryOp::GreaterOrEqual),
+            ("<", BinaryOp::Less),
+            (">", BinaryOp::Greater),
+            ("contains", BinaryOp::Contains),
+        ];
+        for (symbol, op) in operators {
+            if self.accept(&[symbol]) {
+                let right = self.additive()?;
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = if self.accept(&["+"]) {
+                BinaryOp::Add
+            } else if self.accept(&["-"]) {
+                BinaryOp::Subtract
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.accept(&["*"]) {
+                BinaryOp::Multiply
+            } else if self.accept(&["/"]) {
+                BinaryOp::Divide
+            } else if self.accept(&["%"]) {
+                BinaryOp::Remainder
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.accept(&["-"]) {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error("a value"));
+        };
+
+        let expr = match token {
+            Token::Symbol("(") => {
+                self.next += 1;
+                let inner = self.or()?;
+                if !self.accept(&[")"]) {
+                    return Err(self.error("')'"));
+                }
+                return Ok(inner);
+            }
+            Token::Number(number) => {
+                let parsed = Number::from_str(&number).map_err(|_| {
+                    format!(
+                        "invalid number {} at position {}",
+                        number,
+                        self.tokens[self.next].1 + 1
+                    )
+                })?;
+                Expr::Literal(Value::Number(parsed))
+            }
+            Token::Text(text) => Expr::Literal(Value::String(text)),
+            Token::Column(name) => Expr::Column(name),
+            Token::Name(name) => match name.to_ascii_lowercase().as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                "and" | "or" | "not" | "is" | "contains" | "matches" => {
+                    return Err(self.error("a value"))
+                }
+                _ if self.tokens.get(self.next + 1).map(|(token, _)| token)
+                    == Some(&Token::Symbol("(")) =>
+                {
+                    return self.call(&name);
+                }
+                _ => Expr::Column(name),
+            },
+            Token::Symbol(_) => return Err(self.error("a value")),
+        };
+        self.next += 1;
+        Ok(expr)
+    }
+
+    /// A function call, starting at its name
+    fn call(&mut self, name: &str) -> Result<Expr, String> {
+        let position = self.tokens[self.next].1;
+        self.next += 2;
+
+        let mut arguments = Vec::new();
+        if !self.accept(&[")"]) {
+            loop {
+                arguments.push(self.or()?);
+                if self.accept(&[")"]) {
+                    break;
+                }
+                if !self.accept(&[","]) {
+                    return Err(self.error("',' or ')'"));
+                }
+            }
+        }
+
+        let function = Function::lookup(name, arguments.len())
+            .map_err(|e| format!("{} at position {}", e, position + 1))?;
+        Ok(Expr::Call(function, arguments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn evaluate(expression: &str, record: Value) -> Value {
+        let Value::Object(record) = record else {
+            panic!("records are objects")
+        };
+        let expression: Expression = expression.parse().unwrap();
+        expression.evaluate(&record).unwrap()
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let record =
+            json!({"country": "NL", "amount": 150, "rate": 0.5, "tags": ["a", "b"], "note": null});
+
+        assert_eq!(
+            evaluate("country == 'NL' && amount > 100", record.clone()),
+            json!(true)
+        );
+        assert_eq!(
+            evaluate("not (amount >= 150) or rate < 1", record.clone()),
+            json!(true)
+        );
+        assert_eq!(
+            evaluate("amount == 150.0 and rate != 0.25", record.clone()),
+            json!(true)
+        );
+        assert_eq!(
+            evaluate("note is null and missing IS NULL", record.clone()),
+            json!(true)
+        );
+        assert_eq!(evaluate("country is not null", record.clone()), json!(true));
+        assert_eq!(
+            evaluate("tags contains 'b' && country contains 'N'", record.clone()),
+            json!(true)
+        );
+        assert_eq!(
+            evaluate("country matches '^n' || country =~ '^N'", record.clone()),
+            json!(true)
+        );
+
+        // Ordering null or values of different types is false
+        assert_eq!(
+            evaluate("note < 1 || note >= 1 || country > 1", record.clone()),
+            json!(false)
+        );
+        assert_eq!(evaluate("note == null && note != 0", record), json!(true));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let record = json!({"qty": 3, "price": 2.5, "first": "Ann", "Order Total": 10});
+
+        assert_eq!(evaluate("qty * price", record.clone()), json!(7.5));
+        assert_eq!(evaluate("-qty + 2 * 3 - 1", record.clone()), json!(2));
+        assert_eq!(evaluate("`Order Total` / 4", record.clone()), json!(2.5));
+        assert_eq!(evaluate("`Order Total` / 5 % 3", record.clone()), json!(2));
+        assert_eq!(
+            evaluate("9223372036854775807 + qty", record.clone()),
+            json!(9.223372036854776e18)
+        );
+        assert_eq!(
+            evaluate("first + ' #' + qty", record.clone()),
+            json!("Ann #3")
+        );
+        assert_eq!(evaluate("missing * 2", record.clone()), json!(null));
+
+        let Value::Object(record) = record else {
+            unreachable!()
+        };
+        let error = "first * 2"
+            .parse::<Expression>()
+            .unwrap()
+            .evaluate(&record)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Cannot apply '*' to \"Ann\" and 2");
+        let error = "qty / (qty - 3)"
+            .parse::<Expression>()
+            .unwrap()
+            .evaluate(&record)
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Division by zero"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| source.parse::<Expression>().unwrap_err();
+
+        assert_eq!(error("amount > "), "expected a value at position 10");
+        assert_eq!(error("(a == 1"), "expected ')' at position 8");
+        assert_eq!(
+            error("a = 1"),
+            "unexpected '=' at position 3 (use == to compare)"
+        );
+        assert_eq!(
+            error("a == 'x"),
+            "unterminated string starting at position 6"
+        );
+        assert_eq!(error("a b"), "unexpected 'b' at position 3");
+        assert_eq!(
+            error("a matches b"),
+            "expected a quoted regular expression at position 11, found 'b'"
+        );
+        assert!(error("a =~ '('").starts_with("invalid regular expression '('"));
+        assert_eq!(error("a > 1.2.3"), "invalid number 1.2.3 at position 5");
+    }
+
+    #[test]
+    fn test_function_calls() {
+        let record = json!({"first": " ann ", "last": "Smith", "created": "2024-03-01T14:30:00"});
+
+        assert_eq!(
+            evaluate(
+                "upper(substr(trim(first), 0, 1)) + '. ' + last",
+                record.clone()
+            ),
+            json!("A. Smith")
+        );
+        assert_eq!(
+            evaluate("YEAR(created) * 100 + month(created)", record.clone()),
+            json!(202403)
+        );
+        assert_eq!(evaluate("coalesce(middle, '-')", record), json!("-"));
+
+        let error = |source: &str| source.parse::<Expression>().unwrap_err();
+        assert_eq!(
+            error("1 + upper()"),
+            "upper() takes 1 argument, not 0 at position 5"
+        );
+        assert_eq!(
+            error("upper(a b)"),
+            "expected ',' or ')' at position 9, found 'b'"
+        );
+
+        let added: AddedColumn = "total = qty * price".parse().unwrap();
+        assert_eq!(added.name, "total");
+        assert_eq!(added.expression.to_string(), "qty * price");
+        assert!("= 1".parse::<AddedColumn>().is_err());
+        assert!("total".parse::<AddedColumn>().is_err());
+    }
+
+    #[test]
+    fn test_check_columns() {
+        let expression: Expression = "country == 'NL' && `Order Total` > 1".parse().unwrap();
+        assert!(expression
+            .check_columns(&["Order Total", "country"])
+            .is_ok());
+
+        let error = expression
+            .check_columns(&["Order Total", "land"])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown column 'country' (the columns are: Order Total, land)"
+        );
+    }
+}
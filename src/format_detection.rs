@@ -4,6 +4,8 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
+use crate::input::SAMPLE_LINES;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FileFormat {
     Csv,
@@ -24,6 +26,11 @@ pub fn detect_file_format(file_path: &Path) -> Result<FileFormat> {
 
     // If extension is unclear, try to detect by content (magic bytes)
     let mut file = File::open(file_path).context("Failed to open file for format detection")?;
+
+    // Reading from a pipe would consume the bytes, and spreadsheets need a seekable file
+    if !file.metadata().is_ok_and(|m| m.is_file()) {
+        return Ok(FileFormat::Csv);
+    }
     let mut magic = [0u8; 4];
 
     use std::io::Read;
@@ -45,13 +52,21 @@ pub fn detect_file_format(file_path: &Path) -> Result<FileFormat> {
 /// Detects the CSV format by analyzing a sample of the file
 pub fn detect_csv_format(file_path: &Path) -> Result<(u8, u8, Option<u8>, Terminator)> {
     let file = File::open(file_path).context("Failed to open file for format detection")?;
-    let reader = BufReader::new(file);
+    detect_csv_format_from(BufReader::new(file))
+}
+
+/// Detects the CSV format from the start of an input already in memory, such as
+/// `CsvInput::sample`
+pub fn detect_csv_format_in(sample: &[u8]) -> Result<(u8, u8, Option<u8>, Terminator)> {
+    detect_csv_format_from(sample)
+}
 
+fn detect_csv_format_from(reader: impl BufRead) -> Result<(u8, u8, Option<u8>, Terminator)> {
     // Read first 250 lines for detection (or until EOF)
     // This gives us a better chance to detect escape characters
     let mut lines: Vec<String> = Vec::new();
     for (i, line) in reader.lines().enumerate() {
-        if i >= 250 {
+        if i >= SAMPLE_LINES {
             break;
         }
         lines.push(line?);
@@ -0,0 +1,286 @@
+use anyhow::{bail, Context, Result};
+use heck::{ToLowerCamelCase, ToSnakeCase};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::unflatten::split_indexes;
+
+/// A step of `--normalize-headers`. Every step also trims the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderNormalization {
+    /// Remove surrounding whitespace
+    Trim,
+    /// Drop accents, like "Café" to "Cafe", and leave out other non-ASCII characters
+    Ascii,
+    /// Lowercase words joined by underscores, like "customer_name"
+    Snake,
+    /// Words joined in camel case, like "customerName"
+    Camel,
+}
+
+impl FromStr for HeaderNormalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "trim" | "trimmed" => Ok(HeaderNormalization::Trim),
+            "ascii" => Ok(HeaderNormalization::Ascii),
+            "snake" | "snake_case" => Ok(HeaderNormalization::Snake),
+            "camel" | "camelcase" | "camel_case" => Ok(HeaderNormalization::Camel),
+            _ => Err(format!(
+                "unknown header normalization '{}' (expected 'trim', 'ascii', 'snake' or 'camel')",
+                s
+            )),
+        }
+    }
+}
+
+/// How header names become JSON keys
+#[derive(Debug, Clone, Default)]
+pub struct HeaderOptions {
+    pub normalize: Vec<HeaderNormalization>,
+    /// New keys by header name, from a rename file. Renamed headers are not normalized.
+    pub renames: HashMap<String, String>,
+}
+
+impl HeaderOptions {
+    pub fn is_enabled(&self) -> bool {
+        !self.normalize.is_empty() || !self.renames.is_empty()
+    }
+
+    /// The keys for a row of headers.
+    ///
+    /// With a `separator` for nested keys, like `address.city`, each part is normalized
+    /// on its own and list indexes like `items[0]` are kept. Headers that end up empty
+    /// are named `column_N` after their position.
+    pub fn apply<'a>(
+        &self,
+        headers: impl IntoIterator<Item = &'a str>,
+        separator: Option<&str>,
+    ) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        let mut originals: HashMap<String, &str> = HashMap::new();
+
+        for (i, header) in headers.into_iter().enumerate() {
+            let key = match self.renames.get(header.trim()) {
+                Some(key) => key.clone(),
+                None if self.normalize.is_empty() => header.to_string(),
+                None => match self.normalize(header, separator) {
+                    key if key.is_empty() => format!("column_{}", i),
+                    key => key,
+                },
+            };
+
+            if let Some(other) = originals.insert(key.clone(), header) {
+                if other != header {
+                    eprintln!(
+                        "Warning: headers '{}' and '{}' both become '{}'; the last column wins",
+                        other, header, key
+                    );
+                }
+            }
+            keys.push(key);
+        }
+        keys
+    }
+
+    fn normalize(&self, header: &str, separator: Option<&str>) -> String {
+        let mut name = header.trim().to_string();
+        if self.normalize.contains(&HeaderNormalization::Ascii) {
+            name = fold_to_ascii(&name);
+        }
+
+        let case = self.normalize.iter().rev().find_map(|step| match step {
+            HeaderNormalization::Snake => Some(str::to_snake_case as fn(&str) -> String),
+            HeaderNormalization::Camel => Some(str::to_lower_camel_case as fn(&str) -> String),
+            _ => None,
+        });
+        let Some(case) = case else {
+            return name;
+        };
+
+        let Some(separator) = separator else {
+            return case(&name);
+        };
+        name.split(separator)
+            .map(|part| match split_indexes(part) {
+                Some((base, _)) => format!("{}{}", case(base), &part[base.len()..]),
+                None => case(part),
+            })
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
+
+/// Reads a rename file with one `old name -> new_key` mapping per line. Blank lines and
+/// lines starting with `#` are skipped.
+pub fn read_rename_file(path: &Path) -> Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read rename file: {:?}", path))?;
+
+    let mut renames = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((old, new)) = line.split_once("->") else {
+            bail!(
+                "Line {} of rename file {:?}: expected 'old name -> new_key'",
+                i + 1,
+                path
+            );
+        };
+        let (old, new) = (old.trim(), new.trim());
+        if old.is_empty() || new.is_empty() {
+            bail!(
+                "Line {} of rename file {:?}: names cannot be empty",
+                i + 1,
+                path
+            );
+        }
+        renames.insert(old.to_string(), new.to_string());
+    }
+    Ok(renames)
+}
+
+/// Base letters of U+00C0 to U+017F (Latin-1 Supplement and Latin Extended-A), with
+/// `_` for characters without one
+const LATIN_LETTERS: &[u8; 192] = b"AAAAAA_CEEEEIIIIDNOOOOO_OUUUUY__aaaaaa_ceeeeiiii\
+dnooooo_ouuuuy_yAaAaAaCcCcCcCcDdDdEeEeEeEeEeGgGg\
+GgGgHhHhIiIiIiIiIi__JjKkkLlLlLlLlLlNnNnNnnNnOoOo\
+Oo__RrRrRrSsSsSsSsTtTtTtUuUuUuUuUuUuWwYyYZzZzZzs";
+
+/// Replaces accented Latin letters by their base letters and ligatures by letter pairs,
+/// leaving out other non-ASCII characters
+fn fold_to_ascii(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            _ if c.is_ascii() => folded.push(c),
+            'Æ' => folded.push_str("AE"),
+            'æ' => folded.push_str("ae"),
+            'Þ' => folded.push_str("Th"),
+            'þ' => folded.push_str("th"),
+            'ß' => folded.push_str("ss"),
+            'Ĳ' => folded.push_str("IJ"),
+            'ĳ' => folded.push_str("ij"),
+            'Œ' => folded.push_str("OE"),
+            'œ' => folded.push_str("oe"),
+            '\u{c0}'..='\u{17f}' => match LATIN_LETTERS[c as usize - 0xc0] {
+                b'_' => {}
+                letter => folded.push(letter as char),
+            },
+            _ => {}
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(steps: &[HeaderNormalization], headers: &[&str]) -> Vec<String> {
+        let options = HeaderOptions {
+            normalize: steps.to_vec(),
+            ..Default::default()
+        };
+        options.apply(headers.iter().copied(), None)
+    }
+
+    #[test]
+    fn test_header_normalization() {
+        use HeaderNormalization::*;
+        let headers = [
+            "Customer Name ",
+            "E-mail",
+            "customerID",
+            " Straße Nº",
+            "名前",
+        ];
+
+        assert_eq!(
+            normalize(&[Trim], &headers),
+            ["Customer Name", "E-mail", "customerID", "Straße Nº", "名前"]
+        );
+        assert_eq!(
+            normalize(&[Snake], &headers),
+            [
+                "customer_name",
+                "e_mail",
+                "customer_id",
+                "straße_nº",
+                "名前"
+            ]
+        );
+        assert_eq!(
+            normalize(&[Ascii, Snake], &headers),
+            [
+                "customer_name",
+                "e_mail",
+                "customer_id",
+                "strasse_n",
+                "column_4"
+            ]
+        );
+        assert_eq!(
+            normalize(&[Camel], &headers),
+            ["customerName", "eMail", "customerId", "straßeNº", "名前"]
+        );
+        assert_eq!(
+            fold_to_ascii("Crème Brûlée, Łódź, Œuvre"),
+            "Creme Brulee, Lodz, OEuvre"
+        );
+    }
+
+    #[test]
+    fn test_renames_and_nested_keys() {
+        let options = HeaderOptions {
+            normalize: vec![HeaderNormalization::Snake],
+            renames: HashMap::from([("E-mail".to_string(), "contactEmail".to_string())]),
+        };
+
+        assert_eq!(
+            options.apply(
+                ["E-mail ", "Home Address.Zip Code", "Order Items[0].SKU"],
+                Some(".")
+            ),
+            [
+                "contactEmail",
+                "home_address.zip_code",
+                "order_items[0].sku"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_rename_file() {
+        let path = std::env::temp_dir().join("csv_converter_renames.txt");
+        fs::write(
+            &path,
+            "# comment\nCustomer Name -> name\n\n E-mail->email \n",
+        )
+        .unwrap();
+        let renames = read_rename_file(&path).unwrap();
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames["Customer Name"], "name");
+        assert_eq!(renames["E-mail"], "email");
+
+        fs::write(&path, "Customer Name = name\n").unwrap();
+        let error = read_rename_file(&path).unwrap_err();
+        assert!(error.to_string().contains("Line 1 of rename file"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_normalization_names() {
+        assert_eq!("snake_case".parse(), Ok(HeaderNormalization::Snake));
+        assert_eq!("camelCase".parse(), Ok(HeaderNormalization::Camel));
+        assert!("kebab".parse::<HeaderNormalization>().is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::{bail, Context, Result};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Read buffer size used unless configured otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Number of lines format detection looks at
+pub const SAMPLE_LINES: usize = 250;
+
+/// How CSV input is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputMode {
+    /// Memory-map regular files and read everything else (pipes, devices) as a stream
+    #[default]
+    Auto,
+    /// Always memory-map; fails for inputs that are not regular files. Truncating a
+    /// mapped file while it is read kills the process with SIGBUS.
+    Mmap,
+    /// Always read through a buffer
+    Read,
+}
+
+impl FromStr for InputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(InputMode::Auto),
+            "mmap" => Ok(InputMode::Mmap),
+            "read" => Ok(InputMode::Read),
+            _ => Err(format!(
+                "unknown input mode '{}' (expected 'auto', 'mmap' or 'read')",
+                s
+            )),
+        }
+    }
+}
+
+/// A CSV input opened once and shared by format detection and parsing.
+///
+/// Mapped files are detected and parsed from the same memory, so the file is only read
+/// once. Streams such as pipes cannot be read twice, so the lines read ahead for format
+/// detection are kept and handed to the parser before the rest of the stream.
+pub struct CsvInput {
+    source: Source,
+}
+
+enum Source {
+    Mapped(Mmap),
+    Stream {
+        sample: Vec<u8>,
+        rest: Box<dyn Read + Send>,
+    },
+}
+
+impl CsvInput {
+    pub fn open(path: &Path, mode: InputMode, buffer_size: usize) -> Result<Self> {
+        let file = File::open(path).context(format!("Failed to open input file: {:?}", path))?;
+        let metadata = file
+            .metadata()
+            .context(format!("Failed to read metadata of input file: {:?}", path))?;
+
+        // Empty files cannot be mapped, and there is nothing to gain from it
+        let mappable = metadata.is_file() && metadata.len() > 0;
+        match mode {
+            InputMode::Mmap if !metadata.is_file() => bail!(
+                "Cannot memory-map {:?}: not a regular file (use --input-mode read)",
+                path
+            ),
+            InputMode::Mmap | InputMode::Auto if mappable => match map_file(&file) {
+                Ok(mmap) => return Ok(Self::mapped(mmap)),
+                Err(e) if mode == InputMode::Mmap => {
+                    return Err(e).context(format!("Failed to memory-map {:?}", path))
+                }
+                // Some file systems do not support mapping; read the file instead
+                Err(_) => {}
+            },
+            _ => {}
+        }
+
+        Self::stream(Box::new(file), buffer_size)
+    }
+
+    fn mapped(mmap: Mmap) -> Self {
+        Self {
+            source: Source::Mapped(mmap),
+        }
+    }
+
+    /// Wraps a stream, reading ahead until `SAMPLE_LINES` lines or the end of the input
+    pub fn stream(mut rest: Box<dyn Read + Send>, buffer_size: usize) -> Result<Self> {
+        let mut sample = Vec::new();
+        let mut chunk = vec![0; buffer_size.max(1)];
+        let mut lines = 0;
+        while lines < SAMPLE_LINES {
+            let read = match rest.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).context("Failed to read input"),
+            };
+            lines += chunk[..read].iter().filter(|&&b| b == b'\n').count();
+            sample.extend_from_slice(&chunk[..read]);
+        }
+
+        Ok(Self {
+            source: Source::Stream { sample, rest },
+        })
+    }
+
+    /// Whether the input is memory-mapped
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.source, Source::Mapped(_))
+    }
+
+    /// The first `SAMPLE_LINES` lines of the input, for format detection and date
+    /// scanning. These are the same whether the input is mapped or streamed, whatever
+    /// the buffer size.
+    pub fn sample(&self) -> &[u8] {
+        let read: &[u8] = match &self.source {
+            Source::Mapped(mmap) => mmap,
+            Source::Stream { sample, .. } => sample,
+        };
+        let end = read
+            .iter()
+            .enumerate()
+            .filter(|&(_, &byte)| byte == b'\n')
+            .nth(SAMPLE_LINES - 1)
+            .map_or(read.len(), |(newline, _)| newline + 1);
+        &read[..end]
+    }
+
+    /// Turns the input into a reader over all of its bytes from the start
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        match self.source {
+            Source::Mapped(mmap) => Box::new(Cursor::new(mmap)),
+            Source::Stream { sample, rest } => Box::new(Cursor::new(sample).chain(rest)),
+        }
+    }
+}
+
+fn map_file(file: &File) -> std::io::Result<Mmap> {
+    // SAFETY: the mapping is only read. If another process truncates the file while it
+    // is being converted, reads past the new end fault and end the process, as with
+    // other tools that map their input; modifications may show up in the output.
+    let mmap = unsafe { Mmap::map(file)? };
+
+    // Only a hint to read ahead aggressively, so failures do not matter
+    #[cfg(unix)]
+    let _ = mmap.advise(memmap2::Advice::Sequential);
+
+    Ok(mmap)
+}
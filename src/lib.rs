@@ -1,13 +1,50 @@
+//! Streaming conversion of CSV and XLSX files to NDJSON.
+//!
+//! Numbers are written exactly, as `BigNumbers::Exact` describes, using serde_json's
+//! `arbitrary_precision` feature. Cargo enables features across a whole build, so
+//! crates depending on this one get the feature too: a `serde_json::Number` then holds
+//! the digits it was parsed from, and numbers inside untagged or flattened serde types
+//! may fail to deserialize.
+
 #![allow(clippy::approx_constant)]
 
+pub mod column_selection;
+pub mod date_recognition;
+pub mod excel_dates;
+pub mod excel_number_format;
+pub mod excel_range;
+pub mod expression;
 pub mod format_detection;
+pub mod headers;
+pub mod input;
+pub mod output;
 pub mod parsers;
+pub mod unflatten;
 pub mod value_conversion;
 
 // Re-export commonly used items for convenience
-pub use format_detection::{detect_csv_format, detect_file_format, FileFormat};
-pub use parsers::{csv::CsvParser, xlsx::XlsxParser, Parser};
-pub use value_conversion::convert_field_value;
+pub use column_selection::{ColumnList, ColumnSelection, ColumnSelector};
+pub use date_recognition::{DateFormatSpec, DateOptions, DateOutput, TimeZoneSetting};
+pub use excel_dates::ExcelDateMode;
+pub use excel_number_format::NumberFormatMode;
+pub use excel_range::CellRange;
+pub use expression::{AddedColumn, Expression};
+pub use format_detection::{
+    detect_csv_format, detect_csv_format_in, detect_file_format, FileFormat,
+};
+pub use headers::{read_rename_file, HeaderNormalization, HeaderOptions};
+pub use input::{CsvInput, InputMode};
+pub use output::{OutputFormat, RecordWriter};
+pub use parsers::xlsx::{CellDetailsMode, ErrorCellPolicy, XlsxParser};
+pub use parsers::{
+    csv::{CsvParser, RecordEncoder},
+    ConvertOptions, Parser,
+};
+pub use unflatten::unflatten_record;
+pub use value_conversion::{
+    convert_field_value, BigNumbers, CellTransform, ColumnConverter, ListColumn, LocaleChoice,
+    NumberLocale, Vocabulary, VocabularyOptions, WordList,
+};
 
 use anyhow::{Context, Result};
 use csv::Terminator;
@@ -20,23 +57,36 @@ pub fn convert_to_ndjson(
     output_path: Option<&Path>,
     no_type_conversion: bool,
     string_fields: &[String],
+) -> Result<()> {
+    let options = ConvertOptions::new(no_type_conversion, string_fields);
+    convert_with_options(input_path, output_path, &options)
+}
+
+/// Converts any supported format using the given conversion and output options
+pub fn convert_with_options(
+    input_path: &Path,
+    output_path: Option<&Path>,
+    options: &ConvertOptions,
 ) -> Result<()> {
     let format = detect_file_format(input_path)?;
 
     match format {
         FileFormat::Csv => {
-            let (delimiter, quote, escape, terminator) = detect_csv_format(input_path)?;
+            // Open the input once so detection and parsing share it
+            let input = CsvInput::open(input_path, InputMode::Auto, input::DEFAULT_BUFFER_SIZE)?;
+            let (delimiter, quote, escape, terminator) = detect_csv_format_in(input.sample())?;
             let parser = CsvParser::new(delimiter, quote, escape, terminator);
-            parser.convert_to_ndjson(input_path, output_path, no_type_conversion, string_fields)
+            parser.convert_input(input, output_path, options)
         }
         FileFormat::Xlsx => {
             let parser = XlsxParser::new();
-            parser.convert_to_ndjson(input_path, output_path, no_type_conversion, string_fields)
+            parser.convert_to_ndjson(input_path, output_path, options)
         }
     }
 }
 
 /// Legacy function for backwards compatibility - converts CSV to NDJSON
+#[allow(clippy::too_many_arguments)]
 pub fn convert_csv_to_ndjson(
     input_path: &Path,
     output_path: Option<&Path>,
@@ -48,8 +98,9 @@ pub fn convert_csv_to_ndjson(
     string_fields: &[String],
 ) -> Result<()> {
     let parser = CsvParser::new(delimiter, quote, escape, terminator);
+    let options = ConvertOptions::new(no_type_conversion, string_fields);
     parser
-        .convert_to_ndjson(input_path, output_path, no_type_conversion, string_fields)
+        .convert_to_ndjson(input_path, output_path, &options)
         .context("Failed to convert CSV to NDJSON")
 }
 
@@ -67,7 +118,8 @@ pub fn convert_xlsx_to_ndjson(
         XlsxParser::new()
     };
 
+    let options = ConvertOptions::new(no_type_conversion, string_fields);
     parser
-        .convert_to_ndjson(input_path, output_path, no_type_conversion, string_fields)
+        .convert_to_ndjson(input_path, output_path, &options)
         .context("Failed to convert XLSX to NDJSON")
 }
@@ -1,10 +1,19 @@
 use anyhow::Result;
 use clap::Parser;
 use csv::Terminator;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+use csv_converter::input::DEFAULT_BUFFER_SIZE;
 use csv_converter::parsers::Parser as ParserTrait;
-use csv_converter::{detect_csv_format, detect_file_format, CsvParser, FileFormat, XlsxParser};
+use csv_converter::{
+    detect_csv_format_in, detect_file_format, read_rename_file, AddedColumn, BigNumbers,
+    CellDetailsMode, CellRange, CellTransform, ColumnList, ColumnSelection, ConvertOptions,
+    CsvInput, CsvParser, DateFormatSpec, DateOptions, DateOutput, ErrorCellPolicy, ExcelDateMode,
+    Expression, FileFormat, HeaderNormalization, HeaderOptions, InputMode, ListColumn,
+    LocaleChoice, NumberFormatMode, NumberLocale, OutputFormat, TimeZoneSetting, VocabularyOptions,
+    WordList, XlsxParser,
+};
 
 #[derive(clap::Parser, Debug)]
 #[command(
@@ -16,10 +25,18 @@ struct Args {
     #[arg(short, long)]
     input: PathBuf,
 
-    /// Output NDJSON file path (optional, defaults to stdout)
+    /// Output file path (optional, defaults to stdout)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// Output format: ndjson (one object per line) or json (a single array)
+    #[arg(long)]
+    output_format: Option<OutputFormat>,
+
+    /// Pretty-print records as an indented JSON array (implies --output-format json)
+    #[arg(long)]
+    pretty: bool,
+
     /// Override delimiter detection for CSV files (e.g., ',', ';', '\t')
     #[arg(short, long)]
     delimiter: Option<char>,
@@ -44,10 +61,213 @@ struct Args {
     #[arg(long, value_delimiter = ',')]
     string_fields: Vec<String>,
 
-    /// For XLSX files: specify which sheet to read (default: first sheet)
+    /// Values read as null besides empty fields (comma-separated, e.g. "NULL,N/A,\N");
+    /// write "column=-,n/a" to add values for one column. Can be repeated
+    #[arg(long)]
+    null_values: Vec<WordList>,
+
+    /// Values read as true besides "true" (comma-separated, e.g. "yes,y,1"); write
+    /// "column=ja" to add values for one column. Can be repeated
+    #[arg(long)]
+    true_values: Vec<WordList>,
+
+    /// Values read as false besides "false" (comma-separated, e.g. "no,n,0"); write
+    /// "column=nee" to add values for one column. Can be repeated
+    #[arg(long)]
+    false_values: Vec<WordList>,
+
+    /// Columns holding JSON to parse into objects and arrays (comma-separated, e.g.
+    /// "metadata,payload"); fields that are not valid JSON are converted as usual
+    #[arg(long, value_delimiter = ',')]
+    json_columns: Vec<String>,
+
+    /// Column holding lists to split into arrays: "tags" splits on commas, "tags=;" on
+    /// the given separator. Elements are typed like other fields. Can be repeated
+    #[arg(long)]
+    list_column: Vec<ListColumn>,
+
+    /// Normalize header names before using them as keys (comma-separated): trim, ascii
+    /// (drop accents), snake ("customer_name") or camel ("customerName")
+    #[arg(long, value_delimiter = ',')]
+    normalize_headers: Vec<HeaderNormalization>,
+
+    /// File with one "old name -> new_key" line per header to rename; renamed headers are
+    /// not normalized. Other options refer to columns by their new names
+    #[arg(long)]
+    rename_headers: Option<PathBuf>,
+
+    /// Build nested objects and lists from headers like "address.city" and
+    /// "items[0].sku". Splits headers on "." unless another separator is given
+    #[arg(long, num_args = 0..=1, default_missing_value = ".", value_name = "SEPARATOR")]
+    unflatten: Option<String>,
+
+    /// Columns to write, in this order (comma-separated): header names, zero-based
+    /// positions or regular expressions like "/^price_/". Can be repeated
+    #[arg(long)]
+    columns: Vec<ColumnList>,
+
+    /// Columns to leave out, written like --columns. Can be repeated
+    #[arg(long)]
+    exclude_columns: Vec<ColumnList>,
+
+    /// Only write records for which this expression is true, like
+    /// "country == 'NL' && amount > 100". Evaluated on the converted values; refer to
+    /// headers with spaces as `Order Date`
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    filter: Option<Expression>,
+
+    /// Add a column computed from the converted values, like "total=qty * price" or
+    /// "full_name=first + ' ' + last". Takes the same expressions as --where, plus
+    /// functions like upper(), substr(), year() and sha256(). Can be repeated
+    #[arg(long, value_name = "NAME=EXPRESSION")]
+    add_column: Vec<AddedColumn>,
+
+    /// Write quoted empty fields ("") as empty strings; only unquoted empty fields become
+    /// null. For XLSX files, empty text cells are kept as empty strings
+    #[arg(long)]
+    keep_empty_strings: bool,
+
+    /// Read numbers written in this locale, like "1.234,56" with "de" or "1,234.56" with
+    /// "en"; "auto" chooses decimal commas for semicolon-delimited files
+    #[arg(long)]
+    locale: Option<LocaleChoice>,
+
+    /// Decimal separator of numbers (overrides --locale)
+    #[arg(long)]
+    decimal_separator: Option<char>,
+
+    /// Thousands separator of numbers, like '.' or ' ' (overrides --locale)
+    #[arg(long)]
+    thousands_separator: Option<char>,
+
+    /// How to write numbers that 64-bit integers and floats cannot hold, like 20-digit IDs
+    /// or 1e400: exact (all digits as a JSON number), string, or float (rounded; strings
+    /// when too large for a float)
+    #[arg(long, default_value = "exact")]
+    big_numbers: BigNumbers,
+
+    /// Recognize dates and times like "2024-03-01", "03/01/2024 14:05" or "1 Mar 2024"
+    /// in text fields
+    #[arg(long)]
+    parse_dates: bool,
+
+    /// Date format in strftime syntax ("%d.%m.%Y"), tried before the common formats; write
+    /// "column=%d.%m.%Y" to use only that format for one column. Can be repeated
+    #[arg(long)]
+    date_format: Vec<DateFormatSpec>,
+
+    /// Read dates like "01/02/2024" as day/month when a column's values fit both orders
+    #[arg(long)]
+    day_first: bool,
+
+    /// How to write recognized dates (iso, epoch-ms or epoch-s)
+    #[arg(long, default_value = "iso")]
+    date_output: DateOutput,
+
+    /// Time zone for dates and times without an offset: UTC, an offset like +01:00 or a
+    /// name like Europe/Amsterdam (default: keep local times; UTC for epoch output)
+    #[arg(long)]
+    timezone: Option<TimeZoneSetting>,
+
+    /// For CSV files: number of threads converting records (0 = one per CPU)
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// For CSV files: with several threads, write records as soon as they are converted
+    /// instead of in input order
+    #[arg(long)]
+    unordered: bool,
+
+    /// For CSV files: how to read the input (auto, mmap or read; default auto). auto
+    /// memory-maps regular files and reads pipes as a stream. A mapped file that is
+    /// truncated while it is converted crashes the converter (SIGBUS), so use read for
+    /// files that other programs may rewrite
+    #[arg(long)]
+    input_mode: Option<InputMode>,
+
+    /// For CSV files: read buffer size in bytes (default 65536)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    buffer_size: Option<u64>,
+
+    /// For XLSX files: specify which sheet to read by name or zero-based index (default: first sheet)
     #[arg(short, long)]
     sheet: Option<String>,
 
+    /// For XLSX files: only read this cell range in A1 notation (e.g., "B3:H500" or "B3:H");
+    /// the first row of the range is used as headers
+    #[arg(long)]
+    range: Option<CellRange>,
+
+    /// For XLSX files: read an Excel table or defined name, using its header row and extent
+    #[arg(long, conflicts_with_all = ["sheet", "range", "all_sheets"])]
+    table: Option<String>,
+
+    /// For XLSX files: convert every sheet. Use "{sheet}" in --output to write one file per
+    /// sheet, otherwise all sheets go to one output with a "_sheet" field
+    #[arg(long, conflicts_with = "sheet")]
+    all_sheets: bool,
+
+    /// For XLSX files: print the sheet names, dimensions, visibility, tables and defined
+    /// names, then exit
+    #[arg(long)]
+    list_sheets: bool,
+
+    /// For XLSX files: how to write date, time and duration cells (iso, epoch-ms or serial)
+    #[arg(long, default_value = "iso")]
+    excel_dates: ExcelDateMode,
+
+    /// For XLSX files: how to handle cells with Excel errors like #DIV/0!
+    /// (null, object, quarantine or fail)
+    #[arg(long, default_value = "null")]
+    error_cells: ErrorCellPolicy,
+
+    /// For XLSX files: where to write rows with error cells when using --error-cells quarantine
+    #[arg(long, required_if_eq("error_cells", "quarantine"))]
+    quarantine_output: Option<PathBuf>,
+
+    /// For XLSX files: copy the value of merged cells into every cell they span, also
+    /// in data rows (merged header cells are always filled)
+    #[arg(long)]
+    fill_merged: bool,
+
+    /// For XLSX files: number of header rows; multi-row headers are joined like "Q1.Jan"
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    header_rows: u32,
+
+    /// For XLSX files: separator for joining multi-row headers
+    #[arg(long, default_value = ".")]
+    header_separator: String,
+
+    /// For XLSX files: write multi-row headers as nested objects, like {"Q1": {"Jan": 1}}
+    #[arg(long)]
+    nested_headers: bool,
+
+    /// For XLSX files: skip hidden rows, columns and sheets (same as the three options below)
+    #[arg(long)]
+    skip_hidden: bool,
+
+    /// For XLSX files: skip rows that are hidden in Excel
+    #[arg(long)]
+    skip_hidden_rows: bool,
+
+    /// For XLSX files: skip columns that are hidden in Excel
+    #[arg(long)]
+    skip_hidden_columns: bool,
+
+    /// For XLSX files: skip hidden sheets with --all-sheets and default to the first visible sheet
+    #[arg(long)]
+    skip_hidden_sheets: bool,
+
+    /// For XLSX files: export formulas, hyperlinks and comments, either as cell objects
+    /// ({"value": ..., "formula": ...}) or as sibling keys ("price__formula") (off, object or keys)
+    #[arg(long, default_value = "off")]
+    cell_details: CellDetailsMode,
+
+    /// For XLSX files: apply number formats, writing numbers as displayed in Excel
+    /// ("12.50%", "00123") or adding their format codes (off, display or metadata)
+    #[arg(long, default_value = "off")]
+    number_formats: NumberFormatMode,
+
     /// Force format type (csv or xlsx) instead of auto-detection
     #[arg(long)]
     format: Option<String>,
@@ -56,6 +276,69 @@ struct Args {
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    if args.normalize_headers.contains(&HeaderNormalization::Snake)
+        && args.normalize_headers.contains(&HeaderNormalization::Camel)
+    {
+        anyhow::bail!("--normalize-headers cannot use both snake and camel case");
+    }
+    if args.unflatten.as_deref() == Some("") {
+        anyhow::bail!("The --unflatten separator cannot be empty");
+    }
+
+    let output_format = match (args.output_format, args.pretty) {
+        (Some(OutputFormat::Ndjson), true) => {
+            anyhow::bail!("--pretty cannot be combined with --output-format ndjson")
+        }
+        (Some(format), _) => format,
+        (None, true) => OutputFormat::Json,
+        (None, false) => OutputFormat::Ndjson,
+    };
+
+    let mut options = ConvertOptions {
+        no_type_conversion: args.no_type_conversion,
+        string_fields: args.string_fields.clone(),
+        output_format,
+        pretty: args.pretty,
+        big_numbers: args.big_numbers,
+        dates: DateOptions {
+            detect: args.parse_dates,
+            formats: args.date_format.clone(),
+            day_first: args.day_first,
+            output: args.date_output,
+            timezone: args.timezone,
+        },
+        vocabulary: VocabularyOptions {
+            null_values: args.null_values.clone(),
+            true_values: args.true_values.clone(),
+            false_values: args.false_values.clone(),
+        },
+        keep_empty_strings: args.keep_empty_strings,
+        transforms: cell_transforms(&args)?,
+        unflatten: args.unflatten.clone(),
+        headers: HeaderOptions {
+            normalize: args.normalize_headers.clone(),
+            renames: match &args.rename_headers {
+                Some(path) => read_rename_file(path)?,
+                None => Default::default(),
+            },
+        },
+        columns: ColumnSelection {
+            include: args
+                .columns
+                .iter()
+                .flat_map(|list| list.0.clone())
+                .collect(),
+            exclude: args
+                .exclude_columns
+                .iter()
+                .flat_map(|list| list.0.clone())
+                .collect(),
+        },
+        added_columns: args.add_column.clone(),
+        filter: args.filter.clone(),
+        ..Default::default()
+    };
+
     // Detect file format
     let format = if let Some(format_str) = &args.format {
         match format_str.to_lowercase().as_str() {
@@ -74,6 +357,24 @@ fn main() -> Result<()> {
         FileFormat::Csv => {
             eprintln!("Detected format: CSV");
 
+            if args.list_sheets || args.all_sheets || args.table.is_some() {
+                anyhow::bail!(
+                    "--list-sheets, --all-sheets and --table are only supported for XLSX files"
+                );
+            }
+
+            warn_ignored_options("XLSX", &xlsx_only_options(&args), "CSV");
+
+            // Open the input once; detection and parsing share it, so pipes work too
+            let buffer_size = args
+                .buffer_size
+                .map_or(DEFAULT_BUFFER_SIZE, |size| size as usize);
+            let input = CsvInput::open(
+                &args.input,
+                args.input_mode.unwrap_or_default(),
+                buffer_size,
+            )?;
+
             // Detect or use specified CSV format
             let (delimiter, quote, escape, terminator) = if args.no_auto_detect {
                 (
@@ -84,7 +385,7 @@ fn main() -> Result<()> {
                 )
             } else {
                 let (detected_delim, detected_quote, detected_escape, detected_term) =
-                    detect_csv_format(&args.input)?;
+                    detect_csv_format_in(input.sample())?;
                 (
                     args.delimiter.map(|c| c as u8).unwrap_or(detected_delim),
                     args.quote.map(|c| c as u8).unwrap_or(detected_quote),
@@ -105,43 +406,211 @@ fn main() -> Result<()> {
                 );
             }
 
+            if delimiter == b';' && !has_number_style(&args) {
+                eprintln!(
+                    "Hint: semicolon-delimited files often use decimal commas like \"1.234,56\"; \
+                     add --locale auto to read them as numbers"
+                );
+            }
+            options.number_locale = number_locale(&args, delimiter)?;
+
             // Convert CSV to NDJSON
-            let parser = CsvParser::new(delimiter, quote, escape, terminator);
-            parser.convert_to_ndjson(
-                &args.input,
-                args.output.as_deref(),
-                args.no_type_conversion,
-                &args.string_fields,
-            )?;
+            let mut parser = CsvParser::new(delimiter, quote, escape, terminator)
+                .with_threads(args.threads)
+                .with_buffer_size(buffer_size);
+            if args.unordered {
+                parser = parser.with_unordered();
+            }
+            parser.convert_input(input, args.output.as_deref(), &options)?;
         }
         FileFormat::Xlsx => {
             eprintln!("Detected format: XLSX");
 
-            if args.delimiter.is_some()
-                || args.quote.is_some()
-                || args.escape.is_some()
-                || args.no_auto_detect
-            {
-                eprintln!(
-                    "Warning: CSV-specific options (delimiter, quote, escape, no-auto-detect) are ignored for XLSX files"
-                );
-            }
+            warn_ignored_options("CSV", &csv_only_options(&args), "XLSX");
+
+            options.number_locale = number_locale(&args, b',')?;
 
             // Convert XLSX to NDJSON
-            let parser = if let Some(sheet_name) = args.sheet {
+            let mut parser = if let Some(sheet_name) = args.sheet {
                 XlsxParser::with_sheet(sheet_name)
             } else {
                 XlsxParser::new()
-            };
+            }
+            .with_date_mode(args.excel_dates)
+            .with_error_cells(args.error_cells)
+            .with_cell_details(args.cell_details)
+            .with_number_formats(args.number_formats)
+            .with_header_rows(args.header_rows as usize, args.header_separator);
 
-            parser.convert_to_ndjson(
-                &args.input,
-                args.output.as_deref(),
-                args.no_type_conversion,
-                &args.string_fields,
-            )?;
+            if args.list_sheets {
+                for sheet in parser.list_sheets(&args.input)? {
+                    println!("{}", serde_json::to_string(&sheet)?);
+                }
+                return Ok(());
+            }
+
+            if args.all_sheets {
+                parser = parser.with_all_sheets();
+            }
+            if let Some(range) = args.range {
+                parser = parser.with_range(range);
+            }
+            if let Some(table) = args.table {
+                parser = parser.with_table(table);
+            }
+            if let Some(path) = args.quarantine_output {
+                parser = parser.with_quarantine_output(path);
+            }
+            if args.fill_merged {
+                parser = parser.with_fill_merged();
+            }
+            if args.nested_headers {
+                parser = parser.with_nested_headers();
+            }
+            if args.skip_hidden || args.skip_hidden_rows {
+                parser = parser.with_skip_hidden_rows();
+            }
+            if args.skip_hidden || args.skip_hidden_columns {
+                parser = parser.with_skip_hidden_columns();
+            }
+            if args.skip_hidden || args.skip_hidden_sheets {
+                parser = parser.with_skip_hidden_sheets();
+            }
+
+            parser.convert_to_ndjson(&args.input, args.output.as_deref(), &options)?;
         }
     }
 
     Ok(())
 }
+
+/// The transforms from --json-columns and --list-column, by column
+fn cell_transforms(args: &Args) -> Result<HashMap<String, CellTransform>> {
+    let json = args
+        .json_columns
+        .iter()
+        .map(|column| (column, CellTransform::Json));
+    let lists = args
+        .list_column
+        .iter()
+        .map(|list| (&list.column, CellTransform::List(list.separator.clone())));
+
+    let mut transforms = HashMap::new();
+    for (column, transform) in json.chain(lists) {
+        if transforms.insert(column.clone(), transform).is_some() {
+            anyhow::bail!(
+                "Column '{}' is given more than once in --json-columns and --list-column",
+                column
+            );
+        }
+    }
+    Ok(transforms)
+}
+
+/// The given options that only apply to CSV files
+fn csv_only_options(args: &Args) -> Vec<&'static str> {
+    [
+        ("--delimiter", args.delimiter.is_some()),
+        ("--quote", args.quote.is_some()),
+        ("--escape", args.escape.is_some()),
+        ("--no-auto-detect", args.no_auto_detect),
+        ("--threads", args.threads != 1),
+        ("--unordered", args.unordered),
+        ("--input-mode", args.input_mode.is_some()),
+        ("--buffer-size", args.buffer_size.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, given)| given.then_some(name))
+    .collect()
+}
+
+/// The given options that only apply to XLSX files. --list-sheets, --all-sheets and
+/// --table are left out, as CSV input fails with those.
+fn xlsx_only_options(args: &Args) -> Vec<&'static str> {
+    [
+        ("--sheet", args.sheet.is_some()),
+        ("--range", args.range.is_some()),
+        (
+            "--excel-dates",
+            args.excel_dates != ExcelDateMode::default(),
+        ),
+        (
+            "--error-cells",
+            args.error_cells != ErrorCellPolicy::default(),
+        ),
+        ("--quarantine-output", args.quarantine_output.is_some()),
+        ("--fill-merged", args.fill_merged),
+        ("--header-rows", args.header_rows != 1),
+        ("--header-separator", args.header_separator != "."),
+        ("--nested-headers", args.nested_headers),
+        ("--skip-hidden", args.skip_hidden),
+        ("--skip-hidden-rows", args.skip_hidden_rows),
+        ("--skip-hidden-columns", args.skip_hidden_columns),
+        ("--skip-hidden-sheets", args.skip_hidden_sheets),
+        (
+            "--cell-details",
+            args.cell_details != CellDetailsMode::default(),
+        ),
+        (
+            "--number-formats",
+            args.number_formats != NumberFormatMode::default(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, given)| given.then_some(name))
+    .collect()
+}
+
+/// Warns that options meant for one input format do nothing for another
+fn warn_ignored_options(option_format: &str, options: &[&str], input_format: &str) {
+    if !options.is_empty() {
+        eprintln!(
+            "Warning: {}-specific options ({}) are ignored for {} files",
+            option_format,
+            options.join(", "),
+            input_format
+        );
+    }
+}
+
+/// Whether any option sets how numbers are written
+fn has_number_style(args: &Args) -> bool {
+    args.locale.is_some() || args.decimal_separator.is_some() || args.thousands_separator.is_some()
+}
+
+/// The number locale from --locale and the separator options, choosing an automatic
+/// locale by the CSV delimiter
+fn number_locale(args: &Args, delimiter: u8) -> Result<NumberLocale> {
+    let mut locale = args
+        .locale
+        .map_or_else(NumberLocale::default, |choice| choice.resolve(delimiter));
+    if let Some(decimal) = args.decimal_separator {
+        locale.decimal_separator = decimal;
+    }
+    if let Some(thousands) = args.thousands_separator {
+        locale.thousands_separator = Some(thousands);
+    }
+
+    let separators = [Some(locale.decimal_separator), locale.thousands_separator];
+    if separators
+        .iter()
+        .flatten()
+        .any(|c| c.is_ascii_digit() || *c == '-' || *c == '+')
+    {
+        anyhow::bail!("Number separators cannot be digits or signs");
+    }
+    if locale.thousands_separator == Some(locale.decimal_separator) {
+        anyhow::bail!("The decimal and thousands separators must differ");
+    }
+
+    if locale != NumberLocale::default() {
+        eprintln!(
+            "Reading numbers with decimal separator '{}' and thousands separator {}",
+            locale.decimal_separator,
+            locale
+                .thousands_separator
+                .map_or("none".to_string(), |c| format!("'{}'", c))
+        );
+    }
+    Ok(locale)
+}
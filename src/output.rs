@@ -0,0 +1,204 @@
+use anyhow::{bail, Context, Result};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Shape of the converted output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// One JSON object per line (newline-delimited JSON)
+    #[default]
+    Ndjson,
+    /// A single JSON array containing every record
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "unknown output format '{}' (expected 'ndjson' or 'json')",
+                s
+            )),
+        }
+    }
+}
+
+/// Writes converted records to a file or stdout in the requested output format.
+///
+/// Records are written as they arrive, so JSON array output is streamed with
+/// separators instead of being buffered in memory.
+pub struct RecordWriter {
+    writer: Box<dyn Write>,
+    format: OutputFormat,
+    pretty: bool,
+    record_count: usize,
+    buffer: Vec<u8>,
+}
+
+impl RecordWriter {
+    /// Open the output writer (file or stdout)
+    pub fn create(output_path: Option<&Path>, format: OutputFormat, pretty: bool) -> Result<Self> {
+        if pretty && format == OutputFormat::Ndjson {
+            bail!("Pretty printing is only supported for JSON array output");
+        }
+
+        let writer: Box<dyn Write> = if let Some(output) = output_path {
+            Box::new(BufWriter::new(
+                File::create(output).context("Failed to create output file")?,
+            ))
+        } else {
+            Box::new(BufWriter::new(std::io::stdout()))
+        };
+
+        Ok(Self::new(writer, format, pretty))
+    }
+
+    /// Wrap an existing writer
+    pub fn new(writer: Box<dyn Write>, format: OutputFormat, pretty: bool) -> Self {
+        Self {
+            writer,
+            format,
+            pretty,
+            record_count: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Number of records written so far
+    pub fn record_count(&self) -> usize {
+        self.record_count
+    }
+
+    /// Write a single record
+    pub fn write_record(&mut self, record: &Map<String, Value>) -> Result<()> {
+        self.write_record_in_order(record, &[])
+    }
+
+    /// Write a single record with the keys in `order` first, see
+    /// `serialize_record_in_order`
+    pub fn write_record_in_order(
+        &mut self,
+        record: &Map<String, Value>,
+        order: &[String],
+    ) -> Result<()> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        serialize_record_in_order(&mut buffer, record, order, self.pretty)?;
+        let written = self.write_serialized(&buffer);
+        self.buffer = buffer;
+        written
+    }
+
+    /// Write a record that was already serialized with `serialize_record`, using the
+    /// same `pretty` setting as this writer
+    pub fn write_serialized(&mut self, json: &[u8]) -> Result<()> {
+        let write_err = "Failed to write output";
+        match self.format {
+            OutputFormat::Ndjson => {
+                self.writer.write_all(json).context(write_err)?;
+                self.writer.write_all(b"\n").context(write_err)?;
+            }
+            OutputFormat::Json => {
+                let separator: &[u8] = if self.record_count == 0 {
+                    b"[\n"
+                } else {
+                    b",\n"
+                };
+                self.writer.write_all(separator).context(write_err)?;
+
+                if self.pretty {
+                    // Indent the record one level so it nests inside the array.
+                    // Serialized strings never contain raw newlines, so every
+                    // newline in the buffer is a line break added by the formatter.
+                    for (i, line) in json.split(|&b| b == b'\n').enumerate() {
+                        let indent: &[u8] = if i == 0 { b"  " } else { b"\n  " };
+                        self.writer.write_all(indent).context(write_err)?;
+                        self.writer.write_all(line).context(write_err)?;
+                    }
+                } else {
+                    self.writer.write_all(json).context(write_err)?;
+                }
+            }
+        }
+
+        self.record_count += 1;
+        Ok(())
+    }
+
+    /// Close the output (terminating the JSON array if needed) and flush it
+    pub fn finish(mut self) -> Result<usize> {
+        if self.format == OutputFormat::Json {
+            let closing: &[u8] = if self.record_count == 0 {
+                b"[]\n"
+            } else {
+                b"\n]\n"
+            };
+            self.writer
+                .write_all(closing)
+                .context("Failed to write output")?;
+        }
+
+        self.writer.flush().context("Failed to flush output")?;
+        Ok(self.record_count)
+    }
+}
+
+/// Serialize a record as JSON, appending it to `buffer`
+pub fn serialize_record(
+    buffer: &mut Vec<u8>,
+    record: &Map<String, Value>,
+    pretty: bool,
+) -> Result<()> {
+    serialize_record_in_order(buffer, record, &[], pretty)
+}
+
+/// Serialize a record as JSON, appending it to `buffer`. The keys in `order` come
+/// first, in that order, followed by the other keys in sorted order.
+pub fn serialize_record_in_order(
+    buffer: &mut Vec<u8>,
+    record: &Map<String, Value>,
+    order: &[String],
+    pretty: bool,
+) -> Result<()> {
+    let record = OrderedRecord { record, order };
+    if pretty {
+        serde_json::to_writer_pretty(buffer, &record)
+    } else {
+        serde_json::to_writer(buffer, &record)
+    }
+    .context("Failed to serialize JSON")
+}
+
+struct OrderedRecord<'a> {
+    record: &'a Map<String, Value>,
+    order: &'a [String],
+}
+
+impl Serialize for OrderedRecord<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.order.is_empty() {
+            return self.record.serialize(serializer);
+        }
+
+        let mut map = serializer.serialize_map(Some(self.record.len()))?;
+        for key in self.order {
+            if let Some(value) = self.record.get(key) {
+                map.serialize_entry(key, value)?;
+            }
+        }
+        for (key, value) in self.record {
+            if !self.order.contains(key) {
+                map.serialize_entry(key, value)?;
+            }
+        }
+        map.end()
+    }
+}
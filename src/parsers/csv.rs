@@ -1,19 +1,44 @@
 use anyhow::{Context, Result};
-use csv::{ReaderBuilder, Terminator};
-use serde_json::Map;
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use csv::{ByteRecord, Reader, ReaderBuilder, StringRecord, Terminator};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::io::Read;
 use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
-use crate::value_conversion::convert_field_value;
+use crate::column_selection::SelectedColumns;
+use crate::date_recognition::DateColumn;
+use crate::input::{CsvInput, InputMode, DEFAULT_BUFFER_SIZE};
+use crate::output::{serialize_record_in_order, RecordWriter};
+use crate::unflatten::unflatten_record;
+use crate::value_conversion::{ColumnConverter, ColumnVocabularies};
 
-use super::Parser;
+use super::csv_quoting::{QuotedEmptyFields, QuotedEmptyReader};
+use super::{ConvertOptions, Parser, DATE_SCAN_ROWS};
+
+/// Number of records handed to a worker thread at a time
+const BATCH_SIZE: usize = 1024;
+
+type InputReader = Reader<Box<dyn Read + Send>>;
+
+/// Records along with the indexes of their quoted empty fields
+type RecordBatch = Vec<(ByteRecord, Vec<usize>)>;
 
 pub struct CsvParser {
     pub delimiter: u8,
     pub quote: u8,
     pub escape: Option<u8>,
     pub terminator: Terminator,
+    /// Threads converting records; with 1, records are converted as they are read
+    pub threads: usize,
+    /// With several threads, write records as soon as they are converted instead of
+    /// in input order
+    pub unordered: bool,
+    /// How the input file is read when converting from a path
+    pub input_mode: InputMode,
+    /// Size of the read buffer in bytes
+    pub buffer_size: usize,
 }
 
 impl CsvParser {
@@ -23,27 +48,69 @@ impl CsvParser {
             quote,
             escape,
             terminator,
+            threads: 1,
+            unordered: false,
+            input_mode: InputMode::Auto,
+            buffer_size: DEFAULT_BUFFER_SIZE,
         }
     }
-}
 
-impl Parser for CsvParser {
-    /// Converts CSV to NDJSON with streaming to handle large files
-    fn convert_to_ndjson(
+    /// Convert records on `threads` worker threads, or one per CPU with 0
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = if threads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+        self
+    }
+
+    /// Write records in the order they are converted rather than the input order
+    pub fn with_unordered(mut self) -> Self {
+        self.unordered = true;
+        self
+    }
+
+    /// Memory-map the input, read it as a stream, or choose by file type
+    pub fn with_input_mode(mut self, input_mode: InputMode) -> Self {
+        self.input_mode = input_mode;
+        self
+    }
+
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Opens a CSV reader with the detected/specified format, along with the quoted
+    /// empty fields it reads when those are kept
+    fn open_reader(
         &self,
-        input_path: &Path,
-        output_path: Option<&Path>,
-        no_type_conversion: bool,
-        string_fields: &[String],
-    ) -> Result<()> {
-        // Open input file
-        let file = File::open(input_path)
-            .context(format!("Failed to open input file: {:?}", input_path))?;
+        input: CsvInput,
+        options: &ConvertOptions,
+    ) -> (InputReader, Option<QuotedEmptyFields>) {
+        let mut reader = input.into_reader();
+        let mut quoted_empty = None;
+        if options.keep_empty_strings {
+            let finder = QuotedEmptyReader::new(
+                reader,
+                self.delimiter,
+                self.quote,
+                self.escape,
+                self.terminator,
+            );
+            quoted_empty = Some(finder.quoted_empty_fields());
+            reader = Box::new(finder);
+        }
+        (self.reader_builder().from_reader(reader), quoted_empty)
+    }
 
+    fn reader_builder(&self) -> ReaderBuilder {
         // Build CSV reader with detected/specified format
         let mut builder = ReaderBuilder::new();
         builder
             .delimiter(self.delimiter)
+            .buffer_capacity(self.buffer_size)
             .quote(self.quote)
             .flexible(true) // Handle varying column counts
             .has_headers(true);
@@ -57,58 +124,490 @@ impl Parser for CsvParser {
             builder.double_quote(true);
         }
 
-        let mut reader = builder.from_reader(BufReader::with_capacity(32 * 1024, file));
+        builder
+    }
+
+    /// Reads the first records of the input, as far as the format detection sample
+    /// goes. A record cut off at the end of the sample is read as it is.
+    fn sample_records(&self, input: &CsvInput) -> Vec<StringRecord> {
+        self.reader_builder()
+            .from_reader(input.sample())
+            .into_records()
+            .take(DATE_SCAN_ROWS)
+            .map_while(Result::ok)
+            .collect()
+    }
+
+    /// Builds the encoder, choosing date formats from the first records
+    fn encoder(
+        &self,
+        headers: &StringRecord,
+        sample: &[StringRecord],
+        options: &ConvertOptions,
+    ) -> RecordEncoder {
+        let encoder = RecordEncoder::new(headers, options);
+        if !options.dates.is_enabled() {
+            return encoder;
+        }
+
+        let (dates, ambiguous) = options.dates.scan(headers, sample);
+        options.dates.warn_ambiguous(&ambiguous);
+        encoder.with_date_columns(dates)
+    }
+
+    /// Converts an input that is already open, for example after detecting its format
+    /// from `CsvInput::sample`
+    pub fn convert_input(
+        &self,
+        input: CsvInput,
+        output_path: Option<&Path>,
+        options: &ConvertOptions,
+    ) -> Result<()> {
+        let sample = if options.dates.is_enabled() {
+            self.sample_records(&input)
+        } else {
+            Vec::new()
+        };
+        let (mut reader, quoted_empty) = self.open_reader(input, options);
 
         // Get headers
-        let headers = reader
+        let mut headers = reader
             .headers()
             .context("Failed to read CSV headers")?
             .clone();
+        if options.headers.is_enabled() {
+            let keys = options
+                .headers
+                .apply(headers.iter(), options.unflatten.as_deref());
+            headers = StringRecord::from(keys);
+        }
+        options.check_expressions(&headers.iter().collect::<Vec<_>>())?;
 
         // Open output writer (file or stdout)
-        let mut writer: Box<dyn Write> = if let Some(output) = output_path {
-            Box::new(BufWriter::new(
-                File::create(output).context("Failed to create output file")?,
-            ))
+        let mut writer = RecordWriter::create(output_path, options.output_format, options.pretty)?;
+
+        let encoder = self.encoder(&headers, &sample, options);
+        if self.threads > 1 {
+            self.convert_parallel(&mut reader, quoted_empty, &encoder, &mut writer)?;
         } else {
-            Box::new(BufWriter::new(std::io::stdout()))
-        };
+            // Stream through records, reusing one record and one JSON buffer
+            let mut record = ByteRecord::new();
+            let mut json = Vec::new();
+            let mut number = 0;
+            while reader
+                .read_byte_record(&mut record)
+                .context("Failed to read CSV record")?
+            {
+                number += 1;
+                let quoted_empty = quoted_empty
+                    .as_ref()
+                    .map_or_else(Vec::new, |found| found.take(number));
+                json.clear();
+                if encoder.encode(&record, &quoted_empty, &mut json)? {
+                    writer.write_serialized(&json)?;
+                    report_progress(&writer);
+                }
+            }
+        }
 
-        // Stream through records and convert each to JSON
-        let mut record_count = 0;
-        for result in reader.records() {
-            let record = result.context("Failed to read CSV record")?;
+        let record_count = writer.finish()?;
+        eprintln!("Conversion complete! Processed {} records.", record_count);
 
-            // Build JSON object from record
-            let mut json_obj = Map::new();
-            for (i, field) in record.iter().enumerate() {
-                // Get header name or create a default one
-                let header_name = headers
-                    .get(i)
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| format!("column_{}", i));
+        Ok(())
+    }
+
+    /// Converts records on worker threads.
+    ///
+    /// The reading thread splits the input into batches of records, the workers convert
+    /// and serialize each batch, and the calling thread writes the results. Channels are
+    /// bounded, so only a few batches per worker are in memory at any time.
+    fn convert_parallel(
+        &self,
+        reader: &mut InputReader,
+        quoted_empty: Option<QuotedEmptyFields>,
+        encoder: &RecordEncoder,
+        writer: &mut RecordWriter,
+    ) -> Result<()> {
+        let (batch_tx, batch_rx) = mpsc::sync_channel::<(usize, RecordBatch)>(self.threads);
+        // Taken out when writing stops, so the reader and the workers stop too
+        let batch_rx = Mutex::new(Some(batch_rx));
+        let (result_tx, result_rx) = mpsc::sync_channel(self.threads * 2);
 
-                let value =
-                    convert_field_value(field, &header_name, no_type_conversion, string_fields);
+        thread::scope(|scope| {
+            let reading = scope.spawn(move || read_batches(reader, quoted_empty, batch_tx));
 
-                json_obj.insert(header_name, value);
+            for _ in 0..self.threads {
+                let batch_rx = &batch_rx;
+                let result_tx = result_tx.clone();
+                scope.spawn(move || loop {
+                    let next = match batch_rx.lock().expect("batch queue lock poisoned").as_ref() {
+                        Some(batches) => batches.recv(),
+                        None => break,
+                    };
+                    let Ok((index, records)) = next else {
+                        break;
+                    };
+                    let batch = SerializedBatch::convert(index, &records, encoder);
+                    if result_tx.send(batch).is_err() {
+                        // The writer stopped on an error
+                        break;
+                    }
+                });
             }
+            drop(result_tx);
 
-            // Write JSON object as a single line
-            let json_line = serde_json::to_string(&json_obj).context("Failed to serialize JSON")?;
-            writeln!(writer, "{}", json_line).context("Failed to write output")?;
+            let written = self.write_batches(result_rx, writer);
+            // After an error the workers have stopped taking batches; dropping the queue
+            // makes the reader stop instead of waiting for room in it
+            batch_rx.lock().expect("batch queue lock poisoned").take();
+            let read = reading.join().expect("CSV reading thread panicked");
+            written.and(read)
+        })
+    }
+
+    /// Writes converted batches as they arrive, holding back batches that are ahead of
+    /// the next one in input order unless output may be unordered
+    fn write_batches(
+        &self,
+        results: mpsc::Receiver<Result<SerializedBatch>>,
+        writer: &mut RecordWriter,
+    ) -> Result<()> {
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
 
-            record_count += 1;
+        for batch in results {
+            let batch = batch?;
+            if self.unordered {
+                batch.write_to(writer)?;
+                continue;
+            }
 
-            // Progress indicator for large files (every 10k records)
-            if record_count % 10000 == 0 {
-                eprintln!("Processed {} records...", record_count);
+            pending.insert(batch.index, batch);
+            while let Some(batch) = pending.remove(&next_index) {
+                batch.write_to(writer)?;
+                next_index += 1;
             }
         }
 
-        writer.flush().context("Failed to flush output")?;
-        eprintln!("Conversion complete! Processed {} records.", record_count);
+        Ok(())
+    }
+}
+
+impl Parser for CsvParser {
+    /// Converts CSV to NDJSON with streaming to handle large files
+    fn convert_to_ndjson(
+        &self,
+        input_path: &Path,
+        output_path: Option<&Path>,
+        options: &ConvertOptions,
+    ) -> Result<()> {
+        let input = CsvInput::open(input_path, self.input_mode, self.buffer_size)?;
+        self.convert_input(input, output_path, options)
+    }
+}
+
+/// Records of one batch serialized as JSON, one after the other
+struct SerializedBatch {
+    /// Position of the batch in the input
+    index: usize,
+    json: Vec<u8>,
+    /// End offset of each record in `json`
+    ends: Vec<usize>,
+}
 
+impl SerializedBatch {
+    fn convert(index: usize, records: &RecordBatch, encoder: &RecordEncoder) -> Result<Self> {
+        let mut json = Vec::new();
+        let mut ends = Vec::with_capacity(records.len());
+        for (record, quoted_empty) in records {
+            if encoder.encode(record, quoted_empty, &mut json)? {
+                ends.push(json.len());
+            }
+        }
+
+        Ok(Self { index, json, ends })
+    }
+
+    fn write_to(&self, writer: &mut RecordWriter) -> Result<()> {
+        let mut start = 0;
+        for &end in &self.ends {
+            writer.write_serialized(&self.json[start..end])?;
+            report_progress(writer);
+            start = end;
+        }
         Ok(())
     }
 }
+
+/// Reads records in batches and sends them to the workers, until the input ends or
+/// the workers stop. Records read before an error are still sent.
+fn read_batches(
+    reader: &mut InputReader,
+    quoted_empty: Option<QuotedEmptyFields>,
+    batches: mpsc::SyncSender<(usize, RecordBatch)>,
+) -> Result<()> {
+    let mut index = 0;
+    let mut number = 0;
+    loop {
+        let mut records = Vec::with_capacity(BATCH_SIZE);
+        let mut more = Ok(true);
+        while records.len() < BATCH_SIZE {
+            let mut record = ByteRecord::new();
+            more = reader.read_byte_record(&mut record);
+            match more {
+                Ok(true) => {
+                    number += 1;
+                    let quoted_empty = quoted_empty
+                        .as_ref()
+                        .map_or_else(Vec::new, |found| found.take(number));
+                    records.push((record, quoted_empty));
+                }
+                _ => break,
+            }
+        }
+
+        if !records.is_empty() && batches.send((index, records)).is_err() {
+            // Writing failed and the batch queue is gone; the write error is reported
+            return Ok(());
+        }
+        if !more.context("Failed to read CSV record")? {
+            return Ok(());
+        }
+        index += 1;
+    }
+}
+
+/// Writes records as JSON objects straight into a byte buffer.
+///
+/// Header keys are escaped and sorted once and each column's converter is resolved
+/// once, so encoding a record only copies bytes. The output is identical to
+/// serializing the `Map` built by `to_json`: keys are sorted, or in the order of
+/// `--columns`, and with duplicate headers the value of the last column wins.
+pub struct RecordEncoder {
+    /// Keys in output order
+    keys: Vec<EncodedKey>,
+    /// Columns to write and the order of their keys
+    selected: SelectedColumns,
+    /// Whether each column is selected, by column index
+    written: Vec<bool>,
+    converters: Vec<ColumnConverter>,
+    /// Whether some column is parsed as JSON or split into lists
+    structured: bool,
+    vocabularies: ColumnVocabularies,
+    /// Used for records with more fields than headers, which get `column_N` keys
+    headers: StringRecord,
+    options: ConvertOptions,
+}
+
+struct EncodedKey {
+    /// The key as a JSON string followed by the separator before the value
+    prefix: Vec<u8>,
+    /// Columns with this header name, in ascending order
+    columns: Vec<usize>,
+}
+
+impl RecordEncoder {
+    pub fn new(headers: &StringRecord, options: &ConvertOptions) -> Self {
+        let selected = if options.columns.is_enabled() {
+            let names: Vec<&str> = headers.iter().collect();
+            options
+                .columns
+                .resolve(&options.column_names(&names), options.unflatten.as_deref())
+        } else {
+            SelectedColumns::all(headers.len())
+        };
+
+        let columns: Vec<(&str, Vec<usize>)> = if selected.key_order.is_empty() {
+            let mut columns: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+            for &i in selected.columns.iter().filter(|&&i| i < headers.len()) {
+                columns.entry(&headers[i]).or_default().push(i);
+            }
+            columns.into_iter().collect()
+        } else {
+            let mut columns: Vec<(&str, Vec<usize>)> = Vec::new();
+            for &i in selected.columns.iter().filter(|&&i| i < headers.len()) {
+                match columns.iter_mut().find(|(name, _)| *name == &headers[i]) {
+                    Some((_, same_name)) => same_name.push(i),
+                    None => columns.push((&headers[i], vec![i])),
+                }
+            }
+            for (_, same_name) in &mut columns {
+                same_name.sort_unstable();
+            }
+            columns
+        };
+
+        let separator: &[u8] = if options.pretty { b": " } else { b":" };
+        let keys = columns
+            .into_iter()
+            .map(|(name, columns)| {
+                let mut prefix =
+                    serde_json::to_vec(name).expect("serializing a string cannot fail");
+                prefix.extend_from_slice(separator);
+                EncodedKey { prefix, columns }
+            })
+            .collect();
+
+        let vocabularies = options.vocabulary.resolve();
+        let converters: Vec<_> = headers
+            .iter()
+            .map(|name| options.column_converter(name, &vocabularies))
+            .collect();
+
+        let mut written = vec![false; headers.len() + options.added_columns.len()];
+        for &i in &selected.columns {
+            written[i] = true;
+        }
+
+        let mut encoder = Self {
+            keys,
+            selected,
+            written,
+            structured: converters.iter().any(ColumnConverter::is_structured),
+            converters,
+            vocabularies,
+            headers: headers.clone(),
+            options: options.clone(),
+        };
+        if options.dates.is_enabled() {
+            let dates = options.dates.unscanned();
+            let columns = headers.iter().map(|name| dates.get(name)).collect();
+            encoder = encoder.with_date_columns(columns);
+        }
+        encoder
+    }
+
+    /// Uses the given date formats by column index, such as from `DateOptions::scan`
+    pub fn with_date_columns(mut self, dates: Vec<Option<Arc<DateColumn>>>) -> Self {
+        for (converter, dates) in self.converters.iter_mut().zip(dates) {
+            *converter = converter.clone().with_dates(dates);
+        }
+        self
+    }
+
+    /// Appends the JSON object for a record to `out`, writing the fields listed in
+    /// `quoted_empty` as empty strings. Returns false without writing anything for
+    /// records left out by the filter.
+    pub fn encode(
+        &self,
+        record: &ByteRecord,
+        quoted_empty: &[usize],
+        out: &mut Vec<u8>,
+    ) -> Result<bool> {
+        // Pretty-printed arrays and objects are indented by serde_json
+        let nested_pretty = self.options.pretty && self.structured;
+        // Fields past the headers are only written when no columns are selected
+        let extra_fields =
+            record.len() > self.converters.len() && !self.options.columns.is_enabled();
+        let expressions = self.options.has_expressions();
+        if extra_fields || nested_pretty || self.options.unflatten.is_some() || expressions {
+            let position = record.position().map_or(0, |p| p.line());
+            let record = StringRecord::from_byte_record(record.clone())
+                .context("Failed to read CSV record")?;
+            let mut json = self.to_json(&record, quoted_empty);
+            if expressions
+                && !self
+                    .options
+                    .evaluate_expressions(&mut json, || format!("on line {}", position))?
+            {
+                return Ok(false);
+            }
+            if expressions && self.options.columns.is_enabled() {
+                json = self.select(json);
+            }
+            if let Some(separator) = &self.options.unflatten {
+                json = unflatten_record(json, separator, true)
+                    .with_context(|| format!("Failed to nest CSV record on line {}", position))?;
+            }
+            serialize_record_in_order(out, &json, &self.selected.key_order, self.options.pretty)?;
+            return Ok(true);
+        }
+
+        let (open, separator, close): (&[u8], &[u8], &[u8]) = if self.options.pretty {
+            (b"{\n  ", b",\n  ", b"\n}")
+        } else {
+            (b"{", b",", b"}")
+        };
+
+        let mut first = true;
+        for key in &self.keys {
+            // Short records leave trailing columns out
+            let Some(&column) = key.columns.iter().rev().find(|&&i| i < record.len()) else {
+                continue;
+            };
+            let field = std::str::from_utf8(&record[column]).with_context(|| {
+                format!(
+                    "Failed to read CSV record: invalid UTF-8 in field {} on line {}",
+                    column + 1,
+                    record.position().map_or(0, |p| p.line())
+                )
+            })?;
+
+            out.extend_from_slice(if first { open } else { separator });
+            first = false;
+            out.extend_from_slice(&key.prefix);
+            if quoted_empty.contains(&column) {
+                out.extend_from_slice(b"\"\"");
+            } else {
+                self.converters[column].write_json(field, out)?;
+            }
+        }
+
+        out.extend_from_slice(if first { b"{}" } else { close });
+        Ok(true)
+    }
+
+    /// Builds the JSON object for a record, keyed by the header names. Expressions may
+    /// refer to any column, so with expressions every column is converted.
+    fn to_json(&self, record: &StringRecord, quoted_empty: &[usize]) -> Map<String, Value> {
+        let options = &self.options;
+        let mut json_obj = Map::new();
+        let every_column = !options.columns.is_enabled() || options.has_expressions();
+        for (i, field) in record.iter().enumerate() {
+            if !every_column && !self.written.get(i).copied().unwrap_or(false) {
+                continue;
+            }
+
+            // Get header name or create a default one
+            let header_name = self
+                .headers
+                .get(i)
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| format!("column_{}", i));
+
+            let value = match self.converters.get(i) {
+                _ if quoted_empty.contains(&i) => Value::String(String::new()),
+                Some(converter) => converter.convert(field),
+                None => options
+                    .column_converter(&header_name, &self.vocabularies)
+                    .convert(field),
+            };
+
+            json_obj.insert(header_name, value);
+        }
+        json_obj
+    }
+
+    /// Keeps the selected columns of a record built from every column, including the
+    /// added ones
+    fn select(&self, mut json: Map<String, Value>) -> Map<String, Value> {
+        let mut selected = Map::new();
+        for &i in &self.selected.columns {
+            let name = match self.headers.get(i) {
+                Some(header) => header,
+                None => &self.options.added_columns[i - self.headers.len()].name,
+            };
+            if let Some((key, value)) = json.remove_entry(name) {
+                selected.insert(key, value);
+            }
+        }
+        selected
+    }
+}
+
+/// Progress indicator for large files (every 10k records)
+fn report_progress(writer: &RecordWriter) {
+    if writer.record_count().is_multiple_of(10000) {
+        eprintln!("Processed {} records...", writer.record_count());
+    }
+}
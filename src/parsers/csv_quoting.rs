@@ -0,0 +1,267 @@
+//! Telling quoted empty fields (`""`) apart from missing ones.
+//!
+//! The csv crate reads both as an empty field, so `QuotedEmptyReader` watches the
+//! input on its way to the CSV reader and notes which fields of each record are quoted
+//! empty strings. The input passes through unchanged, so any field content, even a
+//! NUL character, is read as it is.
+
+use csv::Terminator;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+/// Where the lexer is within the current field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// At the start of a field, where a quote opens a quoted field
+    FieldStart,
+    Unquoted,
+    /// Right after the opening quote
+    OpenQuote,
+    /// After the opening quote and a second quote; the next byte shows whether the
+    /// field is empty
+    TwoQuotes,
+    Quoted,
+    /// After the escape character in a quoted field
+    Escaped,
+    /// After a quote in a quoted field: an escaped quote or the closing quote
+    QuoteInQuoted,
+}
+
+/// A record number and the indexes of its quoted empty fields
+type RecordFields = (u64, Vec<usize>);
+
+/// The quoted empty fields found by a `QuotedEmptyReader`, shared with the code
+/// reading records from the CSV reader. Records are numbered like the CSV reader
+/// returns them: the header row is record 0 and blank lines do not count.
+#[derive(Debug, Clone, Default)]
+pub struct QuotedEmptyFields {
+    /// Records with quoted empty fields that the CSV reader may not have returned yet
+    records: Arc<Mutex<VecDeque<RecordFields>>>,
+}
+
+impl QuotedEmptyFields {
+    fn push(&self, record: u64, fields: Vec<usize>) {
+        self.records
+            .lock()
+            .expect("quoted empty fields lock poisoned")
+            .push_back((record, fields));
+    }
+
+    /// Indexes of the quoted empty fields of a record, in field order. Records must be
+    /// taken in increasing order; earlier ones are dropped.
+    pub fn take(&self, record: u64) -> Vec<usize> {
+        let mut records = self
+            .records
+            .lock()
+            .expect("quoted empty fields lock poisoned");
+        while records.front().is_some_and(|&(number, _)| number < record) {
+            records.pop_front();
+        }
+        match records.front() {
+            Some(&(number, _)) if number == record => records
+                .pop_front()
+                .map(|(_, fields)| fields)
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A reader that passes CSV input through while noting its quoted empty fields,
+/// leaving out the header row
+pub struct QuotedEmptyReader<R> {
+    inner: R,
+    delimiter: u8,
+    quote: u8,
+    escape: Option<u8>,
+    terminator: Terminator,
+    state: State,
+    /// Number of the current record
+    record: u64,
+    /// Whether the current line has any bytes; blank lines are not records
+    in_record: bool,
+    /// Index of the current field within the record
+    field: usize,
+    /// Quoted empty fields of the current record
+    quoted_empty: Vec<usize>,
+    found: QuotedEmptyFields,
+    done: bool,
+}
+
+impl<R: Read> QuotedEmptyReader<R> {
+    pub fn new(
+        inner: R,
+        delimiter: u8,
+        quote: u8,
+        escape: Option<u8>,
+        terminator: Terminator,
+    ) -> Self {
+        Self {
+            inner,
+            delimiter,
+            quote,
+            escape,
+            terminator,
+            state: State::FieldStart,
+            record: 0,
+            in_record: false,
+            field: 0,
+            quoted_empty: Vec::new(),
+            found: QuotedEmptyFields::default(),
+            done: false,
+        }
+    }
+
+    /// Where the quoted empty fields are reported, as records are read
+    pub fn quoted_empty_fields(&self) -> QuotedEmptyFields {
+        self.found.clone()
+    }
+
+    fn is_terminator(&self, byte: u8) -> bool {
+        match self.terminator {
+            Terminator::CRLF => byte == b'\r' || byte == b'\n',
+            Terminator::Any(terminator) => byte == terminator,
+            _ => byte == b'\n',
+        }
+    }
+
+    /// Quotes only escape quotes when there is no escape character
+    fn double_quote(&self) -> bool {
+        self.escape.is_none()
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.state == State::FieldStart && !self.in_record {
+            if self.is_terminator(byte) {
+                return;
+            }
+            self.in_record = true;
+        }
+
+        self.state = match self.state {
+            State::FieldStart if byte == self.quote => State::OpenQuote,
+            State::FieldStart | State::Unquoted => self.outside_quotes(byte),
+            State::OpenQuote if byte == self.quote => State::TwoQuotes,
+            State::OpenQuote | State::Quoted => self.inside_quotes(byte),
+            State::Escaped => State::Quoted,
+            State::QuoteInQuoted if byte == self.quote && self.double_quote() => State::Quoted,
+            // The field was closed; anything up to the delimiter belongs to it
+            State::QuoteInQuoted => self.outside_quotes(byte),
+            State::TwoQuotes if byte == self.quote && self.double_quote() => State::Quoted,
+            State::TwoQuotes => {
+                if byte == self.delimiter || self.is_terminator(byte) {
+                    self.quoted_empty.push(self.field);
+                }
+                self.outside_quotes(byte)
+            }
+        };
+    }
+
+    fn outside_quotes(&mut self, byte: u8) -> State {
+        if byte == self.delimiter {
+            self.field += 1;
+            State::FieldStart
+        } else if self.is_terminator(byte) {
+            self.end_record();
+            State::FieldStart
+        } else {
+            State::Unquoted
+        }
+    }
+
+    fn inside_quotes(&self, byte: u8) -> State {
+        if Some(byte) == self.escape {
+            State::Escaped
+        } else if byte == self.quote {
+            State::QuoteInQuoted
+        } else {
+            State::Quoted
+        }
+    }
+
+    fn end_record(&mut self) {
+        if !self.quoted_empty.is_empty() {
+            let fields = std::mem::take(&mut self.quoted_empty);
+            if self.record > 0 {
+                self.found.push(self.record, fields);
+            }
+        }
+        self.record += 1;
+        self.in_record = false;
+        self.field = 0;
+    }
+}
+
+impl<R: Read> Read for QuotedEmptyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        for &byte in &buf[..read] {
+            self.push(byte);
+        }
+
+        // The last record may end without a terminator
+        if read == 0 && !buf.is_empty() && !self.done {
+            self.done = true;
+            if self.state == State::TwoQuotes {
+                self.quoted_empty.push(self.field);
+            }
+            if self.in_record {
+                self.end_record();
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The quoted empty fields of each record after the header, by record number
+    fn find(input: &str, escape: Option<u8>) -> Vec<RecordFields> {
+        let mut reader =
+            QuotedEmptyReader::new(input.as_bytes(), b',', b'"', escape, Terminator::CRLF);
+        let found = reader.quoted_empty_fields();
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, input);
+
+        let records = found.records.lock().unwrap();
+        records.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_finds_quoted_empty_fields() {
+        assert_eq!(
+            find("h,\"\",c\n\"\",,\"\"\r\n\n\"\"", None),
+            vec![(1, vec![0, 2]), (2, vec![0])]
+        );
+    }
+
+    #[test]
+    fn test_leaves_escaped_quotes() {
+        // Header, then fields starting with or holding escaped quotes
+        let input = "h\n\"\"\"x\"\"\",\"a\"\"\"\"\",\"\"x\n";
+        assert_eq!(find(input, None), vec![]);
+
+        let input = "h\n\"a\\\"\",\"\"\n";
+        assert_eq!(find(input, Some(b'\\')), vec![(1, vec![1])]);
+    }
+
+    #[test]
+    fn test_quoted_line_breaks() {
+        assert_eq!(find("h,\"\"\n\"x\n\"\"\",\"\"\n", None), vec![(1, vec![1])]);
+    }
+
+    #[test]
+    fn test_take_in_record_order() {
+        let found = QuotedEmptyFields::default();
+        found.push(2, vec![1]);
+        found.push(5, vec![0, 3]);
+        assert_eq!(found.take(1), Vec::<usize>::new());
+        assert_eq!(found.take(2), vec![1]);
+        assert_eq!(found.take(5), vec![0, 3]);
+        assert_eq!(found.take(6), Vec::<usize>::new());
+    }
+}
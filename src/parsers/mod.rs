@@ -1,17 +1,152 @@
 pub mod csv;
+mod csv_quoting;
 pub mod xlsx;
+mod xlsx_stream;
+mod xlsx_xml;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::path::Path;
 
+use crate::column_selection::ColumnSelection;
+use crate::date_recognition::DateOptions;
+use crate::expression::{AddedColumn, Expression};
+use crate::headers::HeaderOptions;
+use crate::input::SAMPLE_LINES;
+use crate::output::OutputFormat;
+use crate::value_conversion::{
+    BigNumbers, CellTransform, ColumnConverter, ColumnVocabularies, NumberLocale, VocabularyOptions,
+};
+
+/// Number of records scanned to choose the date formats of each column: the rows
+/// after the header in the format detection sample, for CSV and XLSX alike
+const DATE_SCAN_ROWS: usize = SAMPLE_LINES - 1;
+
+/// Options shared by all parsers that control value conversion and output
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Keep all values as strings (disable type conversion)
+    pub no_type_conversion: bool,
+    /// Field names to keep as strings
+    pub string_fields: Vec<String>,
+    /// Shape of the written output
+    pub output_format: OutputFormat,
+    /// Pretty-print records (JSON array output only)
+    pub pretty: bool,
+    /// Decimal and thousands separators of numbers in text fields
+    pub number_locale: NumberLocale,
+    /// How to write numbers too long for an i64, u64 or f64
+    pub big_numbers: BigNumbers,
+    /// Dates and times to recognize in text fields
+    pub dates: DateOptions,
+    /// Words read as null and as booleans
+    pub vocabulary: VocabularyOptions,
+    /// Write empty fields marked as text (quoted `""` in CSV, text cells in XLSX) as empty
+    /// strings instead of null
+    pub keep_empty_strings: bool,
+    /// Columns parsed as JSON or split into lists, by header name
+    pub transforms: HashMap<String, CellTransform>,
+    /// Normalization and renaming of header names, applied before everything else that
+    /// refers to columns by name
+    pub headers: HeaderOptions,
+    /// Build nested objects and lists from headers like `address.city` and
+    /// `items[0].sku`, splitting them on this separator
+    pub unflatten: Option<String>,
+    /// Columns to write and the order of their keys, by header name after normalization
+    pub columns: ColumnSelection,
+    /// Columns computed from the converted values, each able to use the ones before it
+    pub added_columns: Vec<AddedColumn>,
+    /// Only write records for which this expression is true, evaluated after the added
+    /// columns
+    pub filter: Option<Expression>,
+}
+
+impl ConvertOptions {
+    /// Options for the given type conversion settings with default output
+    pub fn new(no_type_conversion: bool, string_fields: &[String]) -> Self {
+        Self {
+            no_type_conversion,
+            string_fields: string_fields.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether records need their converted values to evaluate expressions on
+    pub fn has_expressions(&self) -> bool {
+        self.filter.is_some() || !self.added_columns.is_empty()
+    }
+
+    /// The header names followed by the names of the added columns
+    pub fn column_names<'a>(&'a self, headers: &[&'a str]) -> Vec<&'a str> {
+        let added = self.added_columns.iter().map(|column| column.name.as_str());
+        headers.iter().copied().chain(added).collect()
+    }
+
+    /// The converter for a column, before date formats are chosen
+    pub(crate) fn column_converter(
+        &self,
+        header_name: &str,
+        vocabularies: &ColumnVocabularies,
+    ) -> ColumnConverter {
+        ColumnConverter::for_column(header_name, self.no_type_conversion, &self.string_fields)
+            .with_locale(self.number_locale)
+            .with_big_numbers(self.big_numbers)
+            .with_vocabulary(vocabularies.get(header_name))
+            .with_transform(self.transforms.get(header_name).cloned())
+    }
+
+    /// Fails when an expression refers to a column that is not in `headers` or added
+    /// before it
+    pub fn check_expressions(&self, headers: &[&str]) -> Result<()> {
+        let mut columns = headers.to_vec();
+        for column in &self.added_columns {
+            column.expression.check_columns(&columns).with_context(|| {
+                format!(
+                    "Invalid --add-column expression for '{}': {}",
+                    column.name, column.expression
+                )
+            })?;
+            columns.push(&column.name);
+        }
+        if let Some(filter) = &self.filter {
+            filter
+                .check_columns(&columns)
+                .with_context(|| format!("Invalid --where expression \"{}\"", filter))?;
+        }
+        Ok(())
+    }
+
+    /// Adds the computed columns to a record and tells whether the filter keeps it.
+    /// `location` describes the record in errors, like "on line 12".
+    pub fn evaluate_expressions(
+        &self,
+        record: &mut Map<String, Value>,
+        location: impl Fn() -> String,
+    ) -> Result<bool> {
+        for column in &self.added_columns {
+            let value = column.expression.evaluate(record).with_context(|| {
+                format!("Failed to compute column '{}' {}", column.name, location())
+            })?;
+            record.insert(column.name.clone(), value);
+        }
+
+        match &self.filter {
+            Some(filter) => filter
+                .is_true(record)
+                .with_context(|| format!("Failed to evaluate --where {}", location())),
+            None => Ok(true),
+        }
+    }
+}
+
 /// Common trait for all file parsers
 pub trait Parser {
-    /// Convert the input file to NDJSON format
+    /// Convert the input file to NDJSON (or the configured output format)
     fn convert_to_ndjson(
         &self,
         input_path: &Path,
         output_path: Option<&Path>,
-        no_type_conversion: bool,
-        string_fields: &[String],
+        options: &ConvertOptions,
     ) -> Result<()>;
 }
@@ -1,28 +1,231 @@
 use anyhow::{Context, Result};
-use calamine::{open_workbook, DataType, Reader, Xlsx};
-use serde_json::Map;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use calamine::{CellErrorType, DataType, SheetVisible};
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use crate::value_conversion::convert_field_value;
+use crate::column_selection::SelectedColumns;
+use crate::date_recognition::DateOptions;
+use crate::excel_dates::{format_excel_datetime, format_excel_duration, ExcelDateMode};
+use crate::excel_number_format::{format_number, NumberFormatMode};
+use crate::excel_range::{cell_reference, parse_sheet_reference, CellRange};
+use crate::output::RecordWriter;
+use crate::unflatten::unflatten_record;
+use crate::value_conversion::{ColumnConverter, NumberLocale};
 
-use super::Parser;
+use super::xlsx_stream::{SheetReader, StreamedCell};
+use super::xlsx_xml::{
+    CellAnnotations, CellRegion, HiddenCells, SheetEntry, SheetLayout, TableEntry, XlsxPackage,
+};
+use super::{ConvertOptions, Parser, DATE_SCAN_ROWS};
+
+/// What to do with cells holding Excel errors like `#DIV/0!`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorCellPolicy {
+    /// Write the cell as null
+    #[default]
+    Null,
+    /// Write the cell as `{"$error": "#DIV/0!"}`
+    Object,
+    /// Move rows containing error cells to a separate quarantine output
+    Quarantine,
+    /// Stop the conversion at the first error cell
+    Fail,
+}
+
+impl FromStr for ErrorCellPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "null" => Ok(ErrorCellPolicy::Null),
+            "object" => Ok(ErrorCellPolicy::Object),
+            "quarantine" => Ok(ErrorCellPolicy::Quarantine),
+            "fail" => Ok(ErrorCellPolicy::Fail),
+            _ => Err(format!(
+                "unknown error cell policy '{}' (expected 'null', 'object', 'quarantine' or 'fail')",
+                s
+            )),
+        }
+    }
+}
+
+/// Whether cell formulas, hyperlinks and comments are written along with the values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CellDetailsMode {
+    /// Only write cell values
+    #[default]
+    Off,
+    /// Write every cell as `{"value": ..., "formula": ..., "hyperlink": ..., "comment": ...}`
+    Object,
+    /// Write the details next to the value under keys like `price__formula`
+    Keys,
+}
+
+impl FromStr for CellDetailsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" | "none" => Ok(CellDetailsMode::Off),
+            "object" => Ok(CellDetailsMode::Object),
+            "keys" => Ok(CellDetailsMode::Keys),
+            _ => Err(format!(
+                "unknown cell details mode '{}' (expected 'off', 'object' or 'keys')",
+                s
+            )),
+        }
+    }
+}
 
 pub struct XlsxParser {
     pub sheet_name: Option<String>,
+    pub all_sheets: bool,
+    /// Cells to read; the first row of the range is used as the header row
+    pub range: Option<CellRange>,
+    /// Excel table or defined name to read instead of a sheet
+    pub table: Option<String>,
+    pub date_mode: ExcelDateMode,
+    pub error_cells: ErrorCellPolicy,
+    /// Output for rows with error cells under the quarantine policy
+    pub quarantine_output: Option<PathBuf>,
+    /// Copy the value of merged cells into every cell of the merged region, also in
+    /// data rows; merged cells in the header rows are always filled
+    pub fill_merged: bool,
+    /// Number of rows at the top of the range that make up the headers
+    pub header_rows: usize,
+    /// Joins the parts of multi-row headers, like `Q1.Jan`
+    pub header_separator: String,
+    /// Write multi-row headers as nested objects instead of joined names
+    pub nested_headers: bool,
+    /// Leave out rows hidden in Excel
+    pub skip_hidden_rows: bool,
+    /// Leave out columns hidden in Excel
+    pub skip_hidden_columns: bool,
+    /// Leave out hidden sheets, and default to the first visible sheet
+    pub skip_hidden_sheets: bool,
+    /// Export formulas, hyperlinks and comments along with cell values
+    pub cell_details: CellDetailsMode,
+    /// Apply each cell's number format to its value
+    pub number_formats: NumberFormatMode,
 }
 
 impl XlsxParser {
     pub fn new() -> Self {
-        Self { sheet_name: None }
+        Self {
+            sheet_name: None,
+            all_sheets: false,
+            range: None,
+            table: None,
+            date_mode: ExcelDateMode::default(),
+            error_cells: ErrorCellPolicy::default(),
+            quarantine_output: None,
+            fill_merged: false,
+            header_rows: 1,
+            header_separator: ".".to_string(),
+            nested_headers: false,
+            skip_hidden_rows: false,
+            skip_hidden_columns: false,
+            skip_hidden_sheets: false,
+            cell_details: CellDetailsMode::default(),
+            number_formats: NumberFormatMode::default(),
+        }
     }
 
     pub fn with_sheet(sheet_name: String) -> Self {
         Self {
             sheet_name: Some(sheet_name),
+            ..Self::new()
         }
     }
+
+    /// Convert every sheet in the workbook instead of a single one
+    pub fn with_all_sheets(mut self) -> Self {
+        self.all_sheets = true;
+        self
+    }
+
+    /// Only read the cells inside the given range
+    pub fn with_range(mut self, range: CellRange) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Read an Excel table (or a defined name) with its own header row and extent
+    pub fn with_table(mut self, table: String) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    /// Set how date, time and duration cells are written
+    pub fn with_date_mode(mut self, date_mode: ExcelDateMode) -> Self {
+        self.date_mode = date_mode;
+        self
+    }
+
+    /// Set how cells holding Excel errors are handled
+    pub fn with_error_cells(mut self, policy: ErrorCellPolicy) -> Self {
+        self.error_cells = policy;
+        self
+    }
+
+    /// Write rows containing error cells to `path` (with the quarantine policy)
+    pub fn with_quarantine_output(mut self, path: PathBuf) -> Self {
+        self.quarantine_output = Some(path);
+        self
+    }
+
+    /// Fill merged regions in data rows with the value of their top-left cell
+    pub fn with_fill_merged(mut self) -> Self {
+        self.fill_merged = true;
+        self
+    }
+
+    /// Use the first `header_rows` rows as headers, joining their parts with `separator`
+    pub fn with_header_rows(mut self, header_rows: usize, separator: String) -> Self {
+        self.header_rows = header_rows;
+        self.header_separator = separator;
+        self
+    }
+
+    /// Write joined header names as nested objects
+    pub fn with_nested_headers(mut self) -> Self {
+        self.nested_headers = true;
+        self
+    }
+
+    /// Skip rows that are hidden in Excel
+    pub fn with_skip_hidden_rows(mut self) -> Self {
+        self.skip_hidden_rows = true;
+        self
+    }
+
+    /// Skip columns that are hidden in Excel
+    pub fn with_skip_hidden_columns(mut self) -> Self {
+        self.skip_hidden_columns = true;
+        self
+    }
+
+    /// Skip hidden sheets with `--all-sheets`, and pick the first visible sheet by default
+    pub fn with_skip_hidden_sheets(mut self) -> Self {
+        self.skip_hidden_sheets = true;
+        self
+    }
+
+    /// Export cell formulas, hyperlinks and comments as cell objects or sibling keys
+    pub fn with_cell_details(mut self, mode: CellDetailsMode) -> Self {
+        self.cell_details = mode;
+        self
+    }
+
+    /// Write numbers as displayed in Excel, or add their format codes
+    pub fn with_number_formats(mut self, mode: NumberFormatMode) -> Self {
+        self.number_formats = mode;
+        self
+    }
 }
 
 impl Default for XlsxParser {
@@ -31,111 +234,1050 @@ impl Default for XlsxParser {
     }
 }
 
-impl Parser for XlsxParser {
-    /// Converts XLSX to NDJSON with streaming-like behavior
-    fn convert_to_ndjson(
-        &self,
-        input_path: &Path,
-        output_path: Option<&Path>,
-        no_type_conversion: bool,
-        string_fields: &[String],
-    ) -> Result<()> {
-        // Open the workbook
-        let mut workbook: Xlsx<_> =
-            open_workbook(input_path).context("Failed to open XLSX file")?;
+/// Summary of a worksheet, as printed by `--list-sheets`
+#[derive(Debug, Clone, Serialize)]
+pub struct SheetInfo {
+    pub index: usize,
+    pub name: String,
+    pub visibility: String,
+    /// Used range in A1 notation, or None for an empty sheet
+    pub dimensions: Option<String>,
+    pub rows: usize,
+    pub columns: usize,
+    /// Hidden rows within the used range
+    pub hidden_rows: usize,
+    /// Hidden columns within the used range
+    pub hidden_columns: usize,
+    /// Excel tables defined on this sheet
+    pub tables: Vec<String>,
+    /// Defined names that refer to a range on this sheet
+    pub names: Vec<String>,
+}
+
+/// Separator between a column name and a cell detail with `CellDetailsMode::Keys`,
+/// as in `price__formula`
+pub const DETAIL_KEY_SEPARATOR: &str = "__";
+
+/// Placeholder in the output path that is replaced by the sheet name with `--all-sheets`
+pub const SHEET_PLACEHOLDER: &str = "{sheet}";
+
+/// Field added to every record when several sheets are written to one output
+pub const SHEET_FIELD: &str = "_sheet";
 
-        // Get the sheet to read from
+/// Fields of the cell objects written with `CellDetailsMode::Object`
+pub const VALUE_FIELD: &str = "value";
+pub const FORMULA_FIELD: &str = "formula";
+pub const HYPERLINK_FIELD: &str = "hyperlink";
+pub const COMMENT_FIELD: &str = "comment";
+/// Number format code, with `NumberFormatMode::Metadata`
+pub const FORMAT_FIELD: &str = "format";
+
+/// Field added to quarantined records with their one-based row number in the sheet
+pub const ROW_FIELD: &str = "_row";
+
+/// Key of the object written for error cells with the object policy
+pub const ERROR_FIELD: &str = "$error";
+
+impl XlsxParser {
+    /// Lists every sheet in the workbook with its dimensions and visibility
+    pub fn list_sheets(&self, input_path: &Path) -> Result<Vec<SheetInfo>> {
+        let mut book = OpenWorkbook::open(input_path)?;
+        let tables = book.package.tables()?;
+        let sheets = book.package.sheets().to_vec();
+
+        let mut infos = Vec::with_capacity(sheets.len());
+        for (index, sheet) in sheets.into_iter().enumerate() {
+            let hidden = book.package.sheet_layout(&sheet.name, false)?.hidden;
+            let bounds = book.reader.bounds(&mut book.package, &sheet.name)?;
+
+            let dimensions = bounds
+                .map(|(start, end)| format!("{}:{}", cell_reference(start), cell_reference(end)));
+            let (rows, columns) = match bounds {
+                Some((start, end)) => (
+                    (end.0 - start.0 + 1) as usize,
+                    (end.1 - start.1 + 1) as usize,
+                ),
+                None => (0, 0),
+            };
+            let (hidden_rows, hidden_columns) = match bounds {
+                Some((start, end)) => (
+                    (start.0..=end.0).filter(|&r| hidden.row_hidden(r)).count(),
+                    (start.1..=end.1)
+                        .filter(|&c| hidden.column_hidden(c))
+                        .count(),
+                ),
+                None => (0, 0),
+            };
+
+            let tables = tables
+                .iter()
+                .filter(|table| table.sheet_name == sheet.name)
+                .map(|table| table.name.clone())
+                .collect();
+            let names = book
+                .package
+                .defined_names()
+                .iter()
+                .filter(|(_, refers_to)| {
+                    parse_sheet_reference(refers_to).is_some_and(|(s, _)| s == sheet.name)
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+
+            infos.push(SheetInfo {
+                index,
+                name: sheet.name,
+                visibility: visibility_name(sheet.visible).to_string(),
+                dimensions,
+                rows,
+                columns,
+                hidden_rows,
+                hidden_columns,
+                tables,
+                names,
+            });
+        }
+
+        Ok(infos)
+    }
+
+    /// Names of the sheets to convert, in workbook order
+    fn selected_sheets(&self, sheets: &[SheetEntry]) -> Result<Vec<String>> {
+        if self.table.is_some() {
+            // Tables and defined names carry their own sheet
+            return Ok(Vec::new());
+        }
+        let visible_names: Vec<String> = sheets
+            .iter()
+            .filter(|sheet| !self.skip_hidden_sheets || sheet.visible == SheetVisible::Visible)
+            .map(|sheet| sheet.name.clone())
+            .collect();
+        if self.all_sheets {
+            return Ok(visible_names);
+        }
+
+        let sheet_names: Vec<String> = sheets.iter().map(|sheet| sheet.name.clone()).collect();
         let sheet_name = if let Some(name) = &self.sheet_name {
-            name.clone()
+            // Prefer an exact sheet name, then fall back to a zero-based sheet index
+            if sheet_names.contains(name) {
+                name.clone()
+            } else if let Ok(index) = name.parse::<usize>() {
+                sheet_names
+                    .get(index)
+                    .with_context(|| {
+                        format!(
+                            "Sheet index {} is out of range (workbook has {} sheets)",
+                            index,
+                            sheet_names.len()
+                        )
+                    })?
+                    .clone()
+            } else {
+                name.clone()
+            }
         } else {
-            // Use the first sheet if no sheet name is specified
-            workbook
-                .sheet_names()
+            // Use the first (visible) sheet if no sheet name is specified
+            visible_names
                 .first()
-                .context("No sheets found in workbook")?
+                .context(if sheet_names.is_empty() {
+                    "No sheets found in workbook"
+                } else {
+                    "No visible sheets found in workbook"
+                })?
                 .clone()
         };
 
+        Ok(vec![sheet_name])
+    }
+
+    /// Converts one sheet, writing its records to `sink`.
+    /// When `sheet_field` is set, every record gets a `_sheet` field with the sheet name.
+    fn convert_sheet(
+        &self,
+        book: &mut OpenWorkbook,
+        sheet_name: &str,
+        sink: &mut RowSink,
+        options: &ConvertOptions,
+        sheet_field: bool,
+    ) -> Result<()> {
         eprintln!("Reading from sheet: {}", sheet_name);
 
-        // Read the range from the sheet
-        let range = workbook
-            .worksheet_range(&sheet_name)
-            .ok_or_else(|| anyhow::anyhow!("Sheet '{}' not found", sheet_name))?
-            .context(format!("Failed to read sheet: {}", sheet_name))?;
-
-        // Open output writer (file or stdout)
-        let mut writer: Box<dyn Write> = if let Some(output) = output_path {
-            Box::new(BufWriter::new(
-                File::create(output).context("Failed to create output file")?,
-            ))
+        let sheet = self.sheet_context(book, sheet_name, sheet_field.then_some(sheet_name))?;
+        let cells = SheetCells::Used(self.range.as_ref());
+        self.convert_cells(book, sheet_name, cells, sink, options, &sheet)
+    }
+
+    /// Converts an Excel table or a defined name, using its own header row and extent
+    fn convert_table(
+        &self,
+        book: &mut OpenWorkbook,
+        name: &str,
+        sink: &mut RowSink,
+        options: &ConvertOptions,
+    ) -> Result<()> {
+        let tables = book.package.tables()?;
+        if let Some(table) = tables.iter().find(|table| table.name == name) {
+            eprintln!(
+                "Reading from table: {} (sheet {})",
+                table.name, table.sheet_name
+            );
+            let sheet = self.sheet_context(book, &table.sheet_name, None)?;
+            let cells = SheetCells::Table(table);
+            return self.convert_cells(book, &table.sheet_name, cells, sink, options, &sheet);
+        }
+
+        let refers_to = book
+            .package
+            .defined_names()
+            .iter()
+            .find(|(defined_name, _)| defined_name == name)
+            .map(|(_, refers_to)| refers_to.clone());
+
+        let Some(refers_to) = refers_to else {
+            let mut available: Vec<String> = tables.into_iter().map(|table| table.name).collect();
+            available.extend(book.package.defined_names().iter().map(|(n, _)| n.clone()));
+            anyhow::bail!(
+                "No table or defined name '{}' found (available: {})",
+                name,
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            );
+        };
+
+        let (sheet_name, cell_range) = parse_sheet_reference(&refers_to).with_context(|| {
+            format!(
+                "Defined name '{}' does not refer to a single cell range: {}",
+                name, refers_to
+            )
+        })?;
+        eprintln!("Reading from defined name: {} ({})", name, refers_to);
+
+        let sheet = self.sheet_context(book, &sheet_name, None)?;
+        let cells = SheetCells::Used(Some(&cell_range));
+        self.convert_cells(book, &sheet_name, cells, sink, options, &sheet)
+    }
+
+    /// Loads what is needed about a sheet besides its values: the merged regions to
+    /// fill, the hidden rows and columns to skip and the cell details to export, per
+    /// the parser options. These take one pass over the sheet, and none when no
+    /// option needs them.
+    fn sheet_context<'a>(
+        &self,
+        book: &mut OpenWorkbook,
+        sheet_name: &str,
+        sheet_field: Option<&'a str>,
+    ) -> Result<SheetContext<'a>> {
+        let details = self.cell_details != CellDetailsMode::Off;
+        let needs_layout = self.fill_merged
+            || self.header_rows > 1
+            || self.skip_hidden_rows
+            || self.skip_hidden_columns
+            || details;
+        let layout = if needs_layout {
+            book.package.sheet_layout(sheet_name, details)?
         } else {
-            Box::new(BufWriter::new(std::io::stdout()))
+            SheetLayout::default()
         };
 
-        // Get dimensions
-        let (rows, cols) = range.get_size();
+        let mut hidden = layout.hidden;
+        if !self.skip_hidden_rows {
+            hidden = hidden.without_rows();
+        }
+        if !self.skip_hidden_columns {
+            hidden = hidden.without_columns();
+        }
 
-        if rows == 0 {
+        Ok(SheetContext {
+            sheet_field,
+            merged_regions: layout.merged_regions,
+            hidden,
+            // Formulas are read along with the values
+            annotations: details.then_some(layout.annotations),
+        })
+    }
+
+    /// Streams the rows of a sheet within `cells`
+    fn convert_cells(
+        &self,
+        book: &mut OpenWorkbook,
+        sheet_name: &str,
+        cells: SheetCells,
+        sink: &mut RowSink,
+        options: &ConvertOptions,
+        sheet: &SheetContext,
+    ) -> Result<()> {
+        let (bounds, table_columns) = match cells {
+            SheetCells::Used(cell_range) => {
+                let bounds =
+                    book.reader
+                        .bounds(&mut book.package, sheet_name)?
+                        .and_then(|(start, end)| match cell_range {
+                            Some(cell_range) => cell_range.clamp_to(end),
+                            None => Some((start, end)),
+                        });
+                (bounds, None)
+            }
+            SheetCells::Table(table) => (table.data, Some(&table.columns)),
+        };
+        let Some((start, end)) = bounds else {
             eprintln!("Sheet is empty, no records to process.");
             return Ok(());
-        }
+        };
 
-        // First row is headers
-        let mut headers: Vec<String> = Vec::new();
-        for col in 0..cols {
-            let header = range
-                .get_value((0, col as u32))
-                .map(datatype_to_string)
-                .unwrap_or_else(|| format!("column_{}", col));
-            headers.push(header);
+        let width = (end.1 - start.1 + 1) as usize;
+        let height = (end.0 - start.0 + 1) as usize;
+        let mut headers = SheetHeaders::default();
+        let header_rows = match table_columns {
+            // Table data excludes the header row, whose names come from the table definition
+            Some(columns) => {
+                let mut names = columns.clone();
+                names.resize_with(width, String::new);
+                headers = self.sheet_headers(names, options)?;
+                0
+            }
+            None => self.header_rows.clamp(1, height),
+        };
+        let mut regions = if self.fill_merged || header_rows > 1 {
+            sheet.merged_regions.clone()
+        } else {
+            Vec::new()
+        };
+        if !self.fill_merged {
+            // Header rows are always filled, so a group label merged over several
+            // columns is part of each of their names
+            let data_start = start.0 + header_rows as u32;
+            regions.retain(|(first, _)| first.0 < data_start);
+            for (_, last) in &mut regions {
+                last.0 = last.0.min(data_start - 1);
+            }
         }
+        let mut merged = MergedCells::new(regions);
 
-        // Process each row (skip header row)
-        let mut record_count = 0;
-        for row in 1..rows {
-            let mut json_obj = Map::new();
-
-            for (col, header_name) in headers.iter().enumerate() {
-                let cell_value = range.get_value((row as u32, col as u32));
-
-                let value = match cell_value {
-                    Some(DataType::Empty) | None => serde_json::Value::Null,
-                    Some(cell) => {
-                        let str_value = datatype_to_string(cell);
-                        convert_field_value(
-                            &str_value,
-                            header_name,
-                            no_type_conversion,
-                            string_fields,
-                        )
+        let mut header_cells: Vec<Vec<DataType>> = Vec::with_capacity(header_rows);
+        // Data rows held back until the date formats of each column are chosen from them
+        let mut pending = options.dates.is_enabled().then(Vec::new);
+        let mut emit_row = |row| {
+            // Annotated here rather than on the parameter, so the number formats keep
+            // the lifetime of the reader
+            let row: PendingRow = row;
+            if header_cells.len() < header_rows {
+                header_cells.push(row.values);
+                if header_cells.len() == header_rows {
+                    headers = self.build_headers(&header_cells, width, options)?;
+                }
+                return Ok(());
+            }
+
+            if let Some(rows) = &mut pending {
+                rows.push(row);
+                if rows.len() < DATE_SCAN_ROWS {
+                    return Ok(());
+                }
+                let rows = pending.take().unwrap_or_default();
+                return self.write_scanned(rows, &mut headers, start.1, sink, options, sheet);
+            }
+
+            self.write_row(row.at(start.1), &headers, sink, options, sheet)
+        };
+
+        let reader = &book.reader;
+        let formulas = sheet.annotations.is_some();
+        // The dimension a sheet declares may take in blank rows above its first values;
+        // those are left out, while a cell range or table starts where it says
+        let mut next_row = match cells {
+            SheetCells::Used(None) => None,
+            _ => Some(start.0),
+        };
+        reader.read_rows(&mut book.package, sheet_name, formulas, |row, cells| {
+            // Merged regions may start above or left of the requested cells
+            merged.capture(row, cells);
+            if row < start.0 {
+                return Ok(true);
+            }
+
+            // Rows without values in between are written as empty records
+            let next = next_row.get_or_insert(row);
+            while *next < row.min(end.0 + 1) {
+                let mut values = vec![DataType::Empty; width];
+                merged.fill(*next, start.1, &mut values);
+                emit_row(PendingRow::new(*next, values))?;
+                *next += 1;
+            }
+            if row > end.0 {
+                return Ok(false);
+            }
+
+            let mut pending_row = PendingRow::new(row, vec![DataType::Empty; width]);
+            pending_row.formats = vec![None; width];
+            if formulas {
+                pending_row.formulas = vec![None; width];
+            }
+            for cell in cells.drain(..) {
+                if (start.1..=end.1).contains(&cell.col) {
+                    let index = (cell.col - start.1) as usize;
+                    pending_row.formats[index] = reader.format(cell.style);
+                    pending_row.values[index] = cell.value;
+                    if formulas {
+                        pending_row.formulas[index] = cell.formula;
                     }
-                };
+                }
+            }
+            merged.fill(row, start.1, &mut pending_row.values);
+            emit_row(pending_row)?;
+            next_row = Some(row + 1);
+            Ok(true)
+        })?;
+
+        // Tables keep their empty last rows; blank rows below the values of a sheet
+        // are left out like those above them
+        if let (SheetCells::Table(_), Some(mut row)) = (cells, next_row) {
+            while row <= end.0 {
+                let mut values = vec![DataType::Empty; width];
+                merged.fill(row, start.1, &mut values);
+                emit_row(PendingRow::new(row, values))?;
+                row += 1;
+            }
+        }
+
+        match pending {
+            Some(rows) => self.write_scanned(rows, &mut headers, start.1, sink, options, sheet),
+            None => Ok(()),
+        }
+    }
+
+    /// Chooses the date formats of each column from the rows held back at the start of
+    /// a sheet, then writes those rows
+    fn write_scanned(
+        &self,
+        rows: Vec<PendingRow>,
+        headers: &mut SheetHeaders,
+        first_column: u32,
+        sink: &mut RowSink,
+        options: &ConvertOptions,
+        sheet: &SheetContext,
+    ) -> Result<()> {
+        headers.scan_dates(rows.iter().map(|row| row.values.as_slice()), &options.dates);
+        for row in &rows {
+            self.write_row(row.at(first_column), headers, sink, options, sheet)?;
+        }
+        Ok(())
+    }
+
+    /// Header names from the header rows: the cells of a single header row as is,
+    /// or the parts of several rows joined per column
+    fn build_headers(
+        &self,
+        header_cells: &[Vec<DataType>],
+        width: usize,
+        options: &ConvertOptions,
+    ) -> Result<SheetHeaders> {
+        let names: Vec<String> = (0..width)
+            .map(|col| match header_cells {
+                [row] => datatype_to_string(&row[col], self.date_mode),
+                _ => self.compound_header(header_cells, col),
+            })
+            .collect();
+        self.sheet_headers(names, options)
+    }
+
+    /// Normalizes and renames header names, then resolves the selected columns and checks
+    /// the expressions against the final names
+    fn sheet_headers(
+        &self,
+        mut names: Vec<String>,
+        options: &ConvertOptions,
+    ) -> Result<SheetHeaders> {
+        if options.headers.is_enabled() {
+            names = options.headers.apply(
+                names.iter().map(String::as_str),
+                self.key_separator(options),
+            );
+        }
+
+        let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+        options.check_expressions(&refs)?;
+        let selected = if options.columns.is_enabled() {
+            options
+                .columns
+                .resolve(&options.column_names(&refs), self.key_separator(options))
+        } else {
+            SelectedColumns::all(names.len() + options.added_columns.len())
+        };
+
+        let mut written = vec![false; names.len() + options.added_columns.len()];
+        for &col in &selected.columns {
+            written[col] = true;
+        }
+
+        let dates = options.dates.unscanned();
+        let vocabularies = options.vocabulary.resolve();
+        let (converters, cell_converters) = names
+            .iter()
+            .map(|name| {
+                let converter = options.column_converter(name, &vocabularies);
+                (
+                    converter.clone().with_dates(dates.get(name)),
+                    converter.with_locale(NumberLocale::default()),
+                )
+            })
+            .unzip();
+        Ok(SheetHeaders {
+            names,
+            selected,
+            written,
+            converters,
+            cell_converters,
+        })
+    }
 
+    /// The separator that header names are split on to build nested objects, if any
+    fn key_separator<'a>(&'a self, options: &'a ConvertOptions) -> Option<&'a str> {
+        match &options.unflatten {
+            Some(separator) => Some(separator.as_str()),
+            None => self
+                .nested_headers
+                .then_some(self.header_separator.as_str()),
+        }
+    }
+
+    /// Joins the non-empty header cells of a column across several header rows,
+    /// like `Q1.Jan` for "Q1" above "Jan"
+    fn compound_header(&self, header_cells: &[Vec<DataType>], col: usize) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        for row in header_cells {
+            let part = datatype_to_string(&row[col], self.date_mode);
+            let part = part.trim();
+
+            // Skip blanks and repeats from cells merged vertically across header rows
+            if !part.is_empty() && parts.last().map(String::as_str) != Some(part) {
+                parts.push(part.to_string());
+            }
+        }
+
+        if parts.is_empty() {
+            format!("column_{}", col)
+        } else {
+            parts.join(&self.header_separator)
+        }
+    }
+
+    /// Inserts a cell value along with its details (formula, hyperlink, comment and
+    /// number format), as a cell object or as sibling keys depending on the cell details mode
+    fn insert_with_details(
+        &self,
+        json_obj: &mut Map<String, Value>,
+        header_name: &str,
+        value: Value,
+        cell_details: Vec<(&'static str, String)>,
+    ) {
+        if self.cell_details == CellDetailsMode::Object {
+            let mut cell = Map::new();
+            cell.insert(VALUE_FIELD.to_string(), value);
+            for (key, detail) in cell_details {
+                cell.insert(key.to_string(), Value::String(detail));
+            }
+            json_obj.insert(header_name.to_string(), Value::Object(cell));
+        } else {
+            json_obj.insert(header_name.to_string(), value);
+            for (key, detail) in cell_details {
+                json_obj.insert(
+                    format!("{}{}{}", header_name, DETAIL_KEY_SEPARATOR, key),
+                    Value::String(detail),
+                );
+            }
+        }
+    }
+
+    /// Writes the selected columns of a row as a record keyed by `headers`
+    fn write_row(
+        &self,
+        row: SheetRow,
+        headers: &SheetHeaders,
+        sink: &mut RowSink,
+        options: &ConvertOptions,
+        sheet: &SheetContext,
+    ) -> Result<()> {
+        let origin = row.position;
+        if sheet.hidden.row_hidden(origin.0) {
+            return Ok(());
+        }
+
+        let mut json_obj = Map::new();
+        let mut has_error = false;
+
+        if let Some(sheet_name) = sheet.sheet_field {
+            json_obj.insert(
+                SHEET_FIELD.to_string(),
+                serde_json::Value::String(sheet_name.to_string()),
+            );
+        }
+
+        // Expressions may refer to any column, so they get the values of every column
+        let mut values = options.has_expressions().then(Map::new);
+        let every_column: Vec<usize>;
+        let columns = if values.is_some() {
+            every_column = (0..headers.names.len()).collect();
+            &every_column
+        } else {
+            &headers.selected.columns
+        };
+
+        for &col in columns {
+            let header_name = &headers.names[col];
+            // Hidden columns are left out of the record, but expressions may use them
+            let hidden = sheet.hidden.column_hidden(origin.1 + col as u32);
+            let written = headers.written[col] && !hidden;
+            if hidden && values.is_none() {
+                continue;
+            }
+
+            let cell_value = row.values.get(col);
+            let position = (origin.0, origin.1 + col as u32);
+            let format = row.formats.get(col).copied().flatten();
+
+            let value = match cell_value {
+                Some(DataType::Empty) | None => serde_json::Value::Null,
+                Some(DataType::Error(_)) if !written => Value::Null,
+                Some(DataType::Error(error)) => {
+                    has_error = true;
+                    sink.errors.handle(error, header_name, position)?
+                }
+                // Displayed text is kept as is, so "00123" and "12.50%" stay strings
+                Some(cell @ (DataType::Float(_) | DataType::Int(_)))
+                    if self.number_formats == NumberFormatMode::Display && format.is_some() =>
+                {
+                    let number = cell.as_f64().unwrap_or_default();
+                    Value::String(format_number(number, format.unwrap_or_default()))
+                }
+                // Numbers typed as text may use the locale's separators, unlike numeric
+                // cells, which are written in Rust syntax
+                Some(DataType::String(text)) if text.is_empty() && options.keep_empty_strings => {
+                    Value::String(String::new())
+                }
+                Some(DataType::String(text)) => headers.converters[col].convert(text),
+                // Vocabulary words like "1" for true or "n/a" for null also apply to
+                // numeric, boolean, date and duration cells, as written
+                Some(cell) => {
+                    headers.cell_converters[col].convert(&datatype_to_string(cell, self.date_mode))
+                }
+            };
+
+            if let Some(values) = &mut values {
+                values.insert(header_name.clone(), value.clone());
+            }
+            if !written {
+                continue;
+            }
+
+            let mut cell_details = match &sheet.annotations {
+                Some(annotations) => {
+                    let formula = row.formulas.get(col).and_then(Option::as_deref);
+                    cell_details_at(formula, annotations, position)
+                }
+                None => Vec::new(),
+            };
+            if let (NumberFormatMode::Metadata, Some(format)) = (self.number_formats, format) {
+                cell_details.push((FORMAT_FIELD, format.to_string()));
+            }
+
+            if self.cell_details == CellDetailsMode::Object || !cell_details.is_empty() {
+                self.insert_with_details(&mut json_obj, header_name, value, cell_details);
+            } else {
                 json_obj.insert(header_name.clone(), value);
             }
+        }
+
+        if let Some(mut values) = values {
+            let location = || format!("on row {}", origin.0 + 1);
+            if !options.evaluate_expressions(&mut values, location)? {
+                return Ok(());
+            }
+            let width = headers.names.len();
+            for (i, column) in options.added_columns.iter().enumerate() {
+                if headers.written[width + i] {
+                    let value = values.remove(&column.name).unwrap_or(Value::Null);
+                    json_obj.insert(column.name.clone(), value);
+                }
+            }
+        }
+
+        if has_error {
+            if let Some(quarantine) = &mut sink.errors.quarantine {
+                json_obj.insert(
+                    ROW_FIELD.to_string(),
+                    serde_json::Value::from(origin.0 as usize + 1),
+                );
+                return quarantine.write_record(&json_obj);
+            }
+        }
+
+        if let Some(separator) = &options.unflatten {
+            json_obj = unflatten_record(json_obj, separator, true)?;
+        } else if self.nested_headers {
+            json_obj = unflatten_record(json_obj, &self.header_separator, false)?;
+        }
+
+        sink.writer
+            .write_record_in_order(&json_obj, &headers.selected.key_order)?;
+
+        // Progress indicator for large files (every 10k records)
+        if sink.writer.record_count().is_multiple_of(10000) {
+            eprintln!("Processed {} records...", sink.writer.record_count());
+        }
+
+        Ok(())
+    }
+}
+
+/// Header names of a sheet and the columns selected from them
+#[derive(Default)]
+struct SheetHeaders {
+    names: Vec<String>,
+    selected: SelectedColumns,
+    /// Whether each column is selected, by column index
+    written: Vec<bool>,
+    /// How the text cells of each column are converted, by column index
+    converters: Vec<ColumnConverter>,
+    /// How the other cells of each column (numbers, booleans, dates and durations) are
+    /// converted: they are written in Rust syntax or ISO form and hold no text dates
+    cell_converters: Vec<ColumnConverter>,
+}
+
+impl SheetHeaders {
+    /// Chooses the date formats of each column from the text cells of the first data
+    /// rows, like the CSV parser does with its first records
+    fn scan_dates<'r>(&mut self, rows: impl Iterator<Item = &'r [DataType]>, dates: &DateOptions) {
+        let names = StringRecord::from(self.names.clone());
+        let sample: Vec<StringRecord> = rows
+            .map(|cells| {
+                cells
+                    .iter()
+                    .map(|cell| match cell {
+                        DataType::String(text) => text.as_str(),
+                        _ => "",
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (columns, ambiguous) = dates.scan(&names, &sample);
+        dates.warn_ambiguous(&ambiguous);
+        for (converter, column) in self.converters.iter_mut().zip(columns) {
+            *converter = converter.clone().with_dates(column);
+        }
+    }
+}
+
+/// The cells of a sheet to convert
+#[derive(Clone, Copy)]
+enum SheetCells<'a> {
+    /// The used cells, narrowed down to a cell range when one is given, taking the
+    /// first rows as headers
+    Used(Option<&'a CellRange>),
+    /// The data rows of an Excel table, named by the table's columns
+    Table(&'a TableEntry),
+}
+
+/// A row read from a sheet, held back while the date formats are chosen
+struct PendingRow<'r> {
+    row: u32,
+    values: Vec<DataType>,
+    /// Number format code per cell, None for General; may be shorter than `values`
+    formats: Vec<Option<&'r str>>,
+    /// Formula per cell, when cell details are exported; may be shorter than `values`
+    formulas: Vec<Option<String>>,
+}
+
+impl<'r> PendingRow<'r> {
+    fn new(row: u32, values: Vec<DataType>) -> Self {
+        Self {
+            row,
+            values,
+            formats: Vec::new(),
+            formulas: Vec::new(),
+        }
+    }
+
+    /// The row as cells starting at `first_column`
+    fn at(&self, first_column: u32) -> SheetRow<'_> {
+        SheetRow {
+            position: (self.row, first_column),
+            values: &self.values,
+            formats: &self.formats,
+            formulas: &self.formulas,
+        }
+    }
+}
+
+/// Details of the sheet that rows are read from
+struct SheetContext<'a> {
+    /// Sheet name to add to every record as a `_sheet` field
+    sheet_field: Option<&'a str>,
+    /// Merged cell regions, when merged cells are filled or headers span several rows
+    merged_regions: Vec<CellRegion>,
+    /// Rows and columns to skip
+    hidden: HiddenCells,
+    /// Hyperlinks and comments, when cell details are exported
+    annotations: Option<CellAnnotations>,
+}
+
+/// The cells of one row, starting at an absolute (row, column) position
+struct SheetRow<'r> {
+    position: (u32, u32),
+    values: &'r [DataType],
+    /// Number format code per cell, None for General; may be shorter than `values`
+    formats: &'r [Option<&'r str>],
+    /// Formula per cell; may be shorter than `values`
+    formulas: &'r [Option<String>],
+}
+
+/// Merged regions of a sheet and the values of their top-left cells, captured as
+/// rows stream past so later rows of a region can be filled
+#[derive(Default)]
+struct MergedCells {
+    regions: Vec<CellRegion>,
+    values: HashMap<(u32, u32), DataType>,
+}
+
+impl MergedCells {
+    fn new(regions: Vec<CellRegion>) -> Self {
+        Self {
+            regions,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Remembers the values of regions whose top-left cell is in this row
+    fn capture(&mut self, row: u32, cells: &[StreamedCell]) {
+        for &(start, _) in self.regions.iter().filter(|(start, _)| start.0 == row) {
+            let value = cells.iter().find(|cell| cell.col == start.1);
+            if let Some(cell) = value.filter(|cell| cell.value != DataType::Empty) {
+                self.values.insert(start, cell.value.clone());
+            }
+        }
+    }
+
+    /// Copies region values into the other cells they cover in this row, where
+    /// `values` holds the row from column `first_col` on
+    fn fill(&self, row: u32, first_col: u32, values: &mut [DataType]) {
+        for &(start, end) in &self.regions {
+            if row < start.0 || row > end.0 {
+                continue;
+            }
+            let Some(value) = self.values.get(&start) else {
+                continue;
+            };
+            for col in start.1.max(first_col)..=end.1 {
+                if (row, col) == start {
+                    continue;
+                }
+                if let Some(cell) = values.get_mut((col - first_col) as usize) {
+                    *cell = value.clone();
+                }
+            }
+        }
+    }
+}
+
+/// The formula, hyperlink and comment of a cell at an absolute position, as
+/// (field name, text) pairs
+fn cell_details_at(
+    formula: Option<&str>,
+    annotations: &CellAnnotations,
+    position: (u32, u32),
+) -> Vec<(&'static str, String)> {
+    let mut details = Vec::new();
+    if let Some(formula) = formula {
+        details.push((FORMULA_FIELD, format!("={}", formula)));
+    }
+    if let Some(target) = annotations.hyperlinks.get(&position) {
+        details.push((HYPERLINK_FIELD, target.clone()));
+    }
+    if let Some(comment) = annotations.comments.get(&position) {
+        details.push((COMMENT_FIELD, comment.clone()));
+    }
+    details
+}
+
+/// Destination for converted rows: the record writer plus error cell handling
+struct RowSink<'a> {
+    writer: &'a mut RecordWriter,
+    errors: &'a mut ErrorCells,
+}
+
+/// Applies the error cell policy and counts error cells per column
+struct ErrorCells {
+    policy: ErrorCellPolicy,
+    /// Output for rows containing error cells, with the quarantine policy
+    quarantine: Option<RecordWriter>,
+    counts: BTreeMap<String, usize>,
+}
+
+impl ErrorCells {
+    fn new(policy: ErrorCellPolicy, quarantine: Option<RecordWriter>) -> Self {
+        Self {
+            policy,
+            quarantine,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Value to write for an error cell, or an error with the fail policy
+    fn handle(
+        &mut self,
+        error: &CellErrorType,
+        header_name: &str,
+        position: (u32, u32),
+    ) -> Result<serde_json::Value> {
+        *self.counts.entry(header_name.to_string()).or_insert(0) += 1;
+
+        match self.policy {
+            ErrorCellPolicy::Null => Ok(serde_json::Value::Null),
+            ErrorCellPolicy::Object | ErrorCellPolicy::Quarantine => {
+                let mut object = Map::new();
+                object.insert(
+                    ERROR_FIELD.to_string(),
+                    serde_json::Value::String(error.to_string()),
+                );
+                Ok(serde_json::Value::Object(object))
+            }
+            ErrorCellPolicy::Fail => anyhow::bail!(
+                "Error cell {} at {} (column '{}')",
+                error,
+                cell_reference(position),
+                header_name
+            ),
+        }
+    }
+
+    /// Close the quarantine output and report error cells per column
+    fn finish(self) -> Result<()> {
+        if let Some(quarantine) = self.quarantine {
+            let rejected = quarantine.finish()?;
+            eprintln!("Quarantined {} rows with error cells.", rejected);
+        }
+
+        if !self.counts.is_empty() {
+            let summary: Vec<String> = self
+                .counts
+                .iter()
+                .map(|(column, count)| format!("{}: {}", column, count))
+                .collect();
+            eprintln!("Error cells per column: {}", summary.join(", "));
+        }
 
-            // Write JSON object as a single line
-            let json_line = serde_json::to_string(&json_obj).context("Failed to serialize JSON")?;
-            writeln!(writer, "{}", json_line).context("Failed to write output")?;
+        Ok(())
+    }
+}
 
-            record_count += 1;
+/// An opened workbook: the package parts and a streaming reader for sheet rows
+struct OpenWorkbook {
+    package: XlsxPackage,
+    reader: SheetReader,
+}
 
-            // Progress indicator for large files (every 10k records)
-            if record_count % 10000 == 0 {
-                eprintln!("Processed {} records...", record_count);
+impl OpenWorkbook {
+    fn open(input_path: &Path) -> Result<Self> {
+        let mut package = XlsxPackage::open(input_path)?;
+        let reader = SheetReader::new(&mut package)?;
+        Ok(Self { package, reader })
+    }
+}
+
+impl Parser for XlsxParser {
+    /// Converts XLSX to NDJSON with streaming-like behavior
+    fn convert_to_ndjson(
+        &self,
+        input_path: &Path,
+        output_path: Option<&Path>,
+        options: &ConvertOptions,
+    ) -> Result<()> {
+        // Open the workbook
+        let mut book = OpenWorkbook::open(input_path)?;
+
+        let sheet_names = self.selected_sheets(book.package.sheets())?;
+
+        let quarantine = match (&self.error_cells, &self.quarantine_output) {
+            (ErrorCellPolicy::Quarantine, Some(path)) => Some(RecordWriter::create(
+                Some(path),
+                options.output_format,
+                options.pretty,
+            )?),
+            (ErrorCellPolicy::Quarantine, None) => {
+                anyhow::bail!("The quarantine error cell policy needs a quarantine output path")
+            }
+            _ => None,
+        };
+        let mut errors = ErrorCells::new(self.error_cells, quarantine);
+
+        // With --all-sheets and a templated output path, every sheet gets its own file
+        let per_sheet_template = output_path
+            .and_then(|path| path.to_str())
+            .filter(|path| self.all_sheets && path.contains(SHEET_PLACEHOLDER));
+
+        let mut record_count = 0;
+        if let Some(template) = per_sheet_template {
+            for sheet_name in &sheet_names {
+                let sheet_path =
+                    PathBuf::from(template.replace(SHEET_PLACEHOLDER, &file_safe_name(sheet_name)));
+                let mut writer =
+                    RecordWriter::create(Some(&sheet_path), options.output_format, options.pretty)?;
+                let mut sink = RowSink {
+                    writer: &mut writer,
+                    errors: &mut errors,
+                };
+                self.convert_sheet(&mut book, sheet_name, &mut sink, options, false)?;
+                let sheet_count = writer.finish()?;
+                eprintln!("Wrote {} records to {:?}", sheet_count, sheet_path);
+                record_count += sheet_count;
+            }
+        } else {
+            // Open output writer (file or stdout)
+            let mut writer =
+                RecordWriter::create(output_path, options.output_format, options.pretty)?;
+            let mut sink = RowSink {
+                writer: &mut writer,
+                errors: &mut errors,
+            };
+            if let Some(table) = &self.table {
+                self.convert_table(&mut book, table, &mut sink, options)?;
             }
+            for sheet_name in &sheet_names {
+                self.convert_sheet(&mut book, sheet_name, &mut sink, options, self.all_sheets)?;
+            }
+            record_count = writer.finish()?;
         }
 
-        writer.flush().context("Failed to flush output")?;
+        errors.finish()?;
         eprintln!("Conversion complete! Processed {} records.", record_count);
 
         Ok(())
     }
 }
 
+/// Replace characters that are not allowed in file names
+fn file_safe_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn visibility_name(visible: SheetVisible) -> &'static str {
+    match visible {
+        SheetVisible::Visible => "visible",
+        SheetVisible::Hidden => "hidden",
+        SheetVisible::VeryHidden => "very_hidden",
+    }
+}
+
 /// Convert calamine DataType to a string representation
-fn datatype_to_string(data: &DataType) -> String {
+fn datatype_to_string(data: &DataType, date_mode: ExcelDateMode) -> String {
     match data {
         DataType::Int(i) => i.to_string(),
         DataType::Float(f) => {
@@ -148,11 +1290,11 @@ fn datatype_to_string(data: &DataType) -> String {
         }
         DataType::String(s) => s.clone(),
         DataType::Bool(b) => b.to_string(),
-        DataType::DateTime(dt) => format!("{}", dt),
-        DataType::Duration(d) => format!("{}", d),
+        DataType::DateTime(dt) => format_excel_datetime(*dt, date_mode),
+        DataType::Duration(d) => format_excel_duration(*d, date_mode),
         DataType::DateTimeIso(dt) => dt.clone(),
         DataType::DurationIso(d) => d.clone(),
-        DataType::Error(e) => format!("ERROR: {:?}", e),
+        DataType::Error(e) => e.to_string(),
         DataType::Empty => String::new(),
     }
 }
@@ -0,0 +1,468 @@
+//! Row-by-row reading of worksheet cells straight from the XLSX package.
+//!
+//! calamine loads a whole sheet into a `Range` before any row can be written, so memory
+//! grows with the sheet. This reader decodes the sheet XML as it is decompressed and hands
+//! out one row at a time; only the shared strings and cell styles are kept in memory.
+
+use anyhow::{Context, Result};
+use calamine::{CellErrorType, DataType};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::borrow::Cow;
+use std::io::BufRead;
+
+use crate::excel_number_format::NumberFormatKind;
+
+use super::xlsx_xml::{CellRegion, CellStyle, XlsxPackage};
+
+/// Days between the 1900 and 1904 date systems
+const EXCEL_1900_1904_DIFF: f64 = 1462.0;
+
+/// A cell read from a worksheet
+#[derive(Debug, Clone)]
+pub struct StreamedCell {
+    /// Zero-based column
+    pub col: u32,
+    pub value: DataType,
+    /// Index of the cell style, if the cell has one
+    pub style: Option<usize>,
+    /// Formula text without the leading `=`, when formulas are read. Cells sharing
+    /// the formula of another cell have none.
+    pub formula: Option<String>,
+}
+
+/// Decodes worksheet rows using the workbook's shared strings and cell styles
+pub struct SheetReader {
+    shared_strings: Vec<String>,
+    styles: Vec<CellStyle>,
+    is_1904: bool,
+}
+
+impl SheetReader {
+    /// Loads the shared strings and cell styles of a package
+    pub fn new(package: &mut XlsxPackage) -> Result<Self> {
+        let mut shared_strings = Vec::new();
+        if let Some(mut reader) = package.part_reader("xl/sharedStrings.xml")? {
+            let mut buf = Vec::with_capacity(1024);
+            loop {
+                buf.clear();
+                match reader
+                    .read_event_into(&mut buf)
+                    .context("Failed to parse shared strings")?
+                {
+                    Event::Start(ref e) if e.local_name().as_ref() == b"si" => {
+                        let text = read_rich_text(&mut reader, b"si")
+                            .context("Failed to parse shared strings")?;
+                        shared_strings.push(text.unwrap_or_default());
+                    }
+                    Event::Empty(ref e) if e.local_name().as_ref() == b"si" => {
+                        shared_strings.push(String::new());
+                    }
+                    Event::Eof => break,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Self {
+            shared_strings,
+            styles: package.cell_styles()?,
+            is_1904: package.is_1904(),
+        })
+    }
+
+    /// Number format code of a cell style, or None for General
+    pub fn format(&self, style: Option<usize>) -> Option<&str> {
+        style
+            .and_then(|s| self.styles.get(s))
+            .and_then(|style| style.format.as_deref())
+    }
+
+    /// Zero-based (start, end) positions of the used cells, or None for an empty sheet.
+    /// These come from the sheet's `<dimension>` when it declares one; otherwise this
+    /// takes a pass over the whole sheet, without decoding values.
+    pub fn bounds(
+        &self,
+        package: &mut XlsxPackage,
+        sheet_name: &str,
+    ) -> Result<Option<CellRegion>> {
+        // Some writers declare `A1` whatever the sheet holds, so a single cell is checked
+        if let Some((start, end)) = package.sheet_dimension(sheet_name)? {
+            if start != end {
+                return Ok(Some((start, end)));
+            }
+        }
+
+        let mut bounds: Option<CellRegion> = None;
+        let content = CellContent {
+            values: false,
+            formulas: false,
+        };
+        self.scan_rows(package, sheet_name, content, |row, cells| {
+            for cell in cells.iter() {
+                bounds = Some(match bounds {
+                    None => ((row, cell.col), (row, cell.col)),
+                    Some((start, end)) => (
+                        (start.0.min(row), start.1.min(cell.col)),
+                        (end.0.max(row), end.1.max(cell.col)),
+                    ),
+                });
+            }
+            Ok(true)
+        })?;
+        Ok(bounds)
+    }
+
+    /// Streams the rows of a sheet that hold at least one value, in sheet order, as
+    /// (zero-based row, cells). Cells without a value are left out, unless `formulas`
+    /// is set and they hold a formula. `visit` may take the cells out of the vector,
+    /// and stops the reading by returning false.
+    pub fn read_rows<F>(
+        &self,
+        package: &mut XlsxPackage,
+        sheet_name: &str,
+        formulas: bool,
+        visit: F,
+    ) -> Result<()>
+    where
+        F: FnMut(u32, &mut Vec<StreamedCell>) -> Result<bool>,
+    {
+        let content = CellContent {
+            values: true,
+            formulas,
+        };
+        self.scan_rows(package, sheet_name, content, visit)
+    }
+
+    /// Streams the rows of a sheet as `read_rows` does. Without decoding values, cells
+    /// holding a value are passed as `DataType::Empty`, which saves the work.
+    fn scan_rows<F>(
+        &self,
+        package: &mut XlsxPackage,
+        sheet_name: &str,
+        content: CellContent,
+        mut visit: F,
+    ) -> Result<()>
+    where
+        F: FnMut(u32, &mut Vec<StreamedCell>) -> Result<bool>,
+    {
+        let path = package.sheet_path(sheet_name)?;
+        let mut reader = package
+            .part_reader(&path)?
+            .with_context(|| format!("Sheet '{}' not found", sheet_name))?;
+        let context = || format!("Failed to read sheet: {}", sheet_name);
+
+        let mut buf = Vec::with_capacity(1024);
+        let mut scratch = Scratch::default();
+        let mut cells: Vec<StreamedCell> = Vec::new();
+        let mut cells_row = 0;
+        // Position of the next cell, for cells without an `r` attribute
+        let mut row_index = 0;
+        let mut col_index = 0;
+
+        loop {
+            buf.clear();
+            match reader.read_event_into(&mut buf).with_context(context)? {
+                Event::Start(ref e) if e.local_name().as_ref() == b"row" => {
+                    if let Some(row) = raw_attribute(e, b"r").and_then(|r| parse_number::<u32>(&r))
+                    {
+                        row_index = row.saturating_sub(1);
+                    }
+                }
+                Event::End(ref e) if e.local_name().as_ref() == b"row" => {
+                    row_index += 1;
+                    col_index = 0;
+                }
+                Event::Start(ref e) if e.local_name().as_ref() == b"c" => {
+                    let cell = CellAttributes::read(e);
+                    let (row, col) = cell.position.unwrap_or((row_index, col_index));
+                    col_index = col + 1;
+
+                    let (value, formula) = self
+                        .read_cell(&mut reader, &cell, content, &mut scratch)
+                        .with_context(context)?;
+                    let value = match (value, &formula) {
+                        (Some(value), _) => value,
+                        (None, Some(_)) => DataType::Empty,
+                        (None, None) => continue,
+                    };
+
+                    if row != cells_row && !cells.is_empty() {
+                        if !visit(cells_row, &mut cells)? {
+                            return Ok(());
+                        }
+                        cells.clear();
+                    }
+                    cells_row = row;
+                    cells.push(StreamedCell {
+                        col,
+                        value,
+                        style: cell.style,
+                        formula,
+                    });
+                }
+                Event::Empty(ref e) if e.local_name().as_ref() == b"c" => {
+                    // A self-closing cell has a style but no value
+                    let position = CellAttributes::read(e).position;
+                    col_index = position.map_or(col_index, |(_, col)| col) + 1;
+                }
+                Event::End(ref e) if e.local_name().as_ref() == b"sheetData" => break,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if !cells.is_empty() {
+            visit(cells_row, &mut cells)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the children of a `<c>` element up to its end, decoding the cell value
+    /// and reading the formula as `content` asks
+    fn read_cell<R: BufRead>(
+        &self,
+        reader: &mut Reader<R>,
+        cell: &CellAttributes,
+        content: CellContent,
+        scratch: &mut Scratch,
+    ) -> Result<(Option<DataType>, Option<String>)> {
+        let mut value = None;
+        let mut formula = None;
+        loop {
+            scratch.events.clear();
+            match reader.read_event_into(&mut scratch.events)? {
+                Event::Start(ref e) => match e.local_name().as_ref() {
+                    b"v" => {
+                        read_text_into(reader, b"v", &mut scratch.text_events, &mut scratch.text)?;
+                        let text = scratch.text.as_str();
+                        value = if content.values {
+                            self.decode_value(text, cell.cell_type.as_deref(), cell.style)?
+                        } else if text.is_empty() && cell.cell_type.as_deref() == Some(b"n") {
+                            None
+                        } else {
+                            Some(DataType::Empty)
+                        };
+                    }
+                    b"is" => value = read_rich_text(reader, b"is")?.map(DataType::String),
+                    b"f" if content.formulas => {
+                        read_text_into(reader, b"f", &mut scratch.text_events, &mut scratch.text)?;
+                        formula = Some(scratch.text.clone()).filter(|text| !text.is_empty());
+                    }
+                    _ => {
+                        // Formulas and extensions are not values
+                        reader.read_to_end_into(e.name(), &mut scratch.text_events)?;
+                    }
+                },
+                Event::End(ref e) if e.local_name().as_ref() == b"c" => {
+                    return Ok((value, formula))
+                }
+                Event::Eof => anyhow::bail!("Unexpected end of sheet inside a cell"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Decodes the text of a `<v>` element according to the cell type and style,
+    /// the way calamine does. Returns None for an empty number.
+    fn decode_value(
+        &self,
+        text: &str,
+        cell_type: Option<&[u8]>,
+        style: Option<usize>,
+    ) -> Result<Option<DataType>> {
+        let value = match cell_type {
+            Some(b"s") => {
+                let index: usize = text
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid shared string index: {}", text))?;
+                let string = self
+                    .shared_strings
+                    .get(index)
+                    .with_context(|| format!("Shared string {} not found", index))?;
+                DataType::String(string.clone())
+            }
+            Some(b"b") => DataType::Bool(text != "0"),
+            Some(b"e") => DataType::Error(
+                text.parse::<CellErrorType>()
+                    .map_err(|_| anyhow::anyhow!("Unknown cell error: {}", text))?,
+            ),
+            Some(b"d") => DataType::DateTimeIso(text.to_string()),
+            // Formula results: numbers when they parse as one, otherwise text
+            Some(b"str") => text
+                .parse()
+                .map(DataType::Float)
+                .unwrap_or_else(|_| DataType::String(text.to_string())),
+            Some(b"n") if text.is_empty() => return Ok(None),
+            Some(b"n") => {
+                let number = text
+                    .parse()
+                    .with_context(|| format!("Invalid number: {}", text))?;
+                self.number_value(number, style)
+            }
+            None => match text.parse() {
+                Ok(number) => self.number_value(number, style),
+                Err(_) => DataType::String(text.to_string()),
+            },
+            Some(other) => anyhow::bail!("Unknown cell type: {}", String::from_utf8_lossy(other)),
+        };
+        Ok(Some(value))
+    }
+
+    /// A number as a date, duration or plain number depending on its style's format
+    fn number_value(&self, number: f64, style: Option<usize>) -> DataType {
+        let kind = match style {
+            Some(style) => self.styles.get(style).map(|style| style.kind),
+            None => None,
+        };
+        match kind {
+            Some(NumberFormatKind::DateTime) if self.is_1904 => {
+                DataType::DateTime(number + EXCEL_1900_1904_DIFF)
+            }
+            Some(NumberFormatKind::DateTime) => DataType::DateTime(number),
+            Some(NumberFormatKind::Duration) => DataType::Duration(number),
+            _ => DataType::Float(number),
+        }
+    }
+}
+
+/// What to read from each cell
+#[derive(Debug, Clone, Copy)]
+struct CellContent {
+    /// Decode the values, rather than only noting that there is one
+    values: bool,
+    formulas: bool,
+}
+
+/// Buffers reused across cells, so reading a cell does not allocate
+#[derive(Default)]
+struct Scratch {
+    events: Vec<u8>,
+    text_events: Vec<u8>,
+    text: String,
+}
+
+/// The attributes of a `<c>` element
+struct CellAttributes<'a> {
+    /// Zero-based (row, column) from the `r` attribute
+    position: Option<(u32, u32)>,
+    cell_type: Option<Cow<'a, [u8]>>,
+    style: Option<usize>,
+}
+
+impl<'a> CellAttributes<'a> {
+    /// Reads the attributes of a cell. References, types and style indices never
+    /// contain entities, so they are used without unescaping.
+    fn read(element: &'a BytesStart) -> Self {
+        let mut cell = Self {
+            position: None,
+            cell_type: None,
+            style: None,
+        };
+        for attribute in element.attributes().with_checks(false).flatten() {
+            match attribute.key.as_ref() {
+                b"r" => cell.position = parse_position(&attribute.value),
+                b"t" => cell.cell_type = Some(attribute.value),
+                b"s" => cell.style = parse_number(&attribute.value),
+                _ => {}
+            }
+        }
+        cell
+    }
+}
+
+/// Zero-based (row, column) of a cell reference like `B3`
+fn parse_position(reference: &[u8]) -> Option<(u32, u32)> {
+    let split = reference.iter().position(u8::is_ascii_digit)?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() {
+        return None;
+    }
+
+    let mut column: u32 = 0;
+    for &c in letters {
+        if !c.is_ascii_alphabetic() {
+            return None;
+        }
+        column = column
+            .checked_mul(26)?
+            .checked_add((c.to_ascii_uppercase() - b'A') as u32 + 1)?;
+    }
+    let row: u32 = parse_number(digits)?;
+
+    Some((row.checked_sub(1)?, column - 1))
+}
+
+/// Raw value of an unprefixed attribute
+fn raw_attribute<'a>(element: &'a BytesStart, name: &[u8]) -> Option<Cow<'a, [u8]>> {
+    element
+        .attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .map(|a| a.value)
+}
+
+fn parse_number<T: std::str::FromStr>(bytes: &[u8]) -> Option<T> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Reads the text content of an element up to its end into `text`
+fn read_text_into<R: BufRead>(
+    reader: &mut Reader<R>,
+    end: &[u8],
+    buf: &mut Vec<u8>,
+    text: &mut String,
+) -> Result<()> {
+    text.clear();
+    loop {
+        buf.clear();
+        match reader.read_event_into(buf)? {
+            Event::Text(ref t) => text.push_str(&t.unescape()?),
+            Event::CData(ref t) => text.push_str(&String::from_utf8_lossy(t)),
+            Event::End(ref e) if e.local_name().as_ref() == end => return Ok(()),
+            Event::Eof => anyhow::bail!(
+                "Unexpected end of part inside <{}>",
+                String::from_utf8_lossy(end)
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Reads a string item (`<si>` or `<is>`) up to its end, joining the text of its runs
+/// and leaving out phonetic hints. Returns None if it has no text element.
+fn read_rich_text<R: BufRead>(reader: &mut Reader<R>, end: &[u8]) -> Result<Option<String>> {
+    let mut buf = Vec::new();
+    let mut run_buf = Vec::new();
+    let mut run = String::new();
+    let mut text: Option<String> = None;
+    let mut in_phonetic = false;
+    loop {
+        buf.clear();
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(ref e) => match e.local_name().as_ref() {
+                b"rPh" => in_phonetic = true,
+                b"t" if !in_phonetic => {
+                    read_text_into(reader, b"t", &mut run_buf, &mut run)?;
+                    text.get_or_insert_with(String::new).push_str(&run);
+                }
+                _ => {}
+            },
+            Event::Empty(ref e) if e.local_name().as_ref() == b"t" && !in_phonetic => {
+                text.get_or_insert_with(String::new);
+            }
+            Event::End(ref e) => match e.local_name().as_ref() {
+                b"rPh" => in_phonetic = false,
+                name if name == end => return Ok(text),
+                _ => {}
+            },
+            Event::Eof => anyhow::bail!(
+                "Unexpected end of part inside <{}>",
+                String::from_utf8_lossy(end)
+            ),
+            _ => {}
+        }
+    }
+}
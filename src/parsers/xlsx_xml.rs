@@ -0,0 +1,568 @@
+//! Direct access to the parts of an XLSX package that calamine does not expose,
+//! such as merged cells, hidden rows, hyperlinks, comments, tables and number formats.
+
+use anyhow::{Context, Result};
+use calamine::SheetVisible;
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use zip::read::ZipFile;
+use zip::ZipArchive;
+
+use crate::excel_number_format::{
+    builtin_format, builtin_format_kind, is_general_format, number_format_kind, NumberFormatKind,
+};
+use crate::excel_range::CellRange;
+
+/// A block of cells given as zero-based (start, end) positions, inclusive
+pub type CellRegion = ((u32, u32), (u32, u32));
+
+/// A simplified XML event handed to part visitors
+pub enum PartEvent<'a> {
+    /// An opening or self-closing element
+    Start(&'a BytesStart<'a>),
+    /// A closing element (also sent after self-closing elements), by local name
+    End(&'a [u8]),
+    /// Unescaped text content
+    Text(&'a str),
+}
+
+/// Hyperlink targets and comment text of a sheet, keyed by zero-based (row, column)
+#[derive(Debug, Clone, Default)]
+pub struct CellAnnotations {
+    pub hyperlinks: HashMap<(u32, u32), String>,
+    pub comments: HashMap<(u32, u32), String>,
+}
+
+/// Rows and columns of a sheet that are hidden, as zero-based indexes
+#[derive(Debug, Clone, Default)]
+pub struct HiddenCells {
+    rows: HashSet<u32>,
+    /// Inclusive column spans, as declared by `<col min max>` elements
+    columns: Vec<(u32, u32)>,
+}
+
+impl HiddenCells {
+    pub fn row_hidden(&self, row: u32) -> bool {
+        self.rows.contains(&row)
+    }
+
+    pub fn column_hidden(&self, column: u32) -> bool {
+        self.columns
+            .iter()
+            .any(|&(first, last)| (first..=last).contains(&column))
+    }
+
+    /// Drop the hidden rows, so only hidden columns are reported
+    pub fn without_rows(mut self) -> Self {
+        self.rows.clear();
+        self
+    }
+
+    /// Drop the hidden columns, so only hidden rows are reported
+    pub fn without_columns(mut self) -> Self {
+        self.columns.clear();
+        self
+    }
+}
+
+/// What a sheet declares besides its cell values
+#[derive(Debug, Clone, Default)]
+pub struct SheetLayout {
+    pub merged_regions: Vec<CellRegion>,
+    pub hidden: HiddenCells,
+    /// Hyperlinks and comments, when they were asked for
+    pub annotations: CellAnnotations,
+}
+
+/// A worksheet listed in the workbook
+#[derive(Debug, Clone)]
+pub struct SheetEntry {
+    pub name: String,
+    pub visible: SheetVisible,
+    /// Path of the worksheet part inside the archive
+    path: String,
+}
+
+/// An Excel table defined on a worksheet
+#[derive(Debug, Clone)]
+pub struct TableEntry {
+    pub name: String,
+    pub sheet_name: String,
+    /// Column names from the table definition
+    pub columns: Vec<String>,
+    /// Cells of the data rows, without the header, totals and insert rows; None when
+    /// the table has no data rows
+    pub data: Option<CellRegion>,
+}
+
+/// The number format of a cell style (an entry of `cellXfs`)
+#[derive(Debug, Clone, Default)]
+pub struct CellStyle {
+    /// Format code, or None for General
+    pub format: Option<String>,
+    pub kind: NumberFormatKind,
+}
+
+/// An opened XLSX package with its sheet names resolved to worksheet parts
+pub struct XlsxPackage {
+    archive: ZipArchive<File>,
+    /// Worksheets in workbook order
+    sheets: Vec<SheetEntry>,
+    /// Defined names and the formulas they refer to
+    defined_names: Vec<(String, String)>,
+    /// Whether dates are counted from 1904 instead of 1900
+    is_1904: bool,
+    /// Cell styles, loaded on first use
+    cell_styles: Option<Vec<CellStyle>>,
+}
+
+impl XlsxPackage {
+    pub fn open(input_path: &Path) -> Result<Self> {
+        let file = File::open(input_path).context("Failed to open XLSX file")?;
+        let archive = ZipArchive::new(file).context("Failed to read XLSX archive")?;
+        let mut package = Self {
+            archive,
+            sheets: Vec::new(),
+            defined_names: Vec::new(),
+            is_1904: false,
+            cell_styles: None,
+        };
+
+        // Relationship id -> worksheet part
+        let mut targets = HashMap::new();
+        package.visit_part("xl/_rels/workbook.xml.rels", |event| {
+            let PartEvent::Start(e) = event else {
+                return Ok(());
+            };
+            if e.local_name().as_ref() == b"Relationship" {
+                if let (Some(id), Some(target)) = (attribute(e, "Id"), attribute(e, "Target")) {
+                    targets.insert(id, resolve_target("xl", &target));
+                }
+            }
+            Ok(())
+        })?;
+
+        let mut sheets = Vec::new();
+        let mut defined_names = Vec::new();
+        let mut defined_name: Option<(String, String)> = None;
+        let mut is_1904 = false;
+        package.visit_part("xl/workbook.xml", |event| {
+            match event {
+                PartEvent::Start(e) => match e.local_name().as_ref() {
+                    b"sheet" => {
+                        let path = attribute(e, "id").and_then(|id| targets.get(&id));
+                        if let (Some(name), Some(path)) = (attribute(e, "name"), path) {
+                            let visible = match attribute(e, "state").as_deref() {
+                                Some("hidden") => SheetVisible::Hidden,
+                                Some("veryHidden") => SheetVisible::VeryHidden,
+                                _ => SheetVisible::Visible,
+                            };
+                            sheets.push(SheetEntry {
+                                name,
+                                visible,
+                                path: path.clone(),
+                            });
+                        }
+                    }
+                    b"workbookPr" => {
+                        is_1904 = attribute(e, "date1904").is_some_and(|d| d == "1" || d == "true");
+                    }
+                    b"definedName" => {
+                        defined_name = attribute(e, "name").map(|name| (name, String::new()));
+                    }
+                    _ => {}
+                },
+                PartEvent::Text(text) => {
+                    if let Some((_, refers_to)) = &mut defined_name {
+                        refers_to.push_str(text);
+                    }
+                }
+                PartEvent::End(b"definedName") => defined_names.extend(defined_name.take()),
+                _ => {}
+            }
+            Ok(())
+        })?;
+        package.sheets = sheets;
+        package.defined_names = defined_names;
+        package.is_1904 = is_1904;
+
+        Ok(package)
+    }
+
+    /// Worksheets in workbook order
+    pub fn sheets(&self) -> &[SheetEntry] {
+        &self.sheets
+    }
+
+    /// Defined names as (name, refers to) pairs
+    pub fn defined_names(&self) -> &[(String, String)] {
+        &self.defined_names
+    }
+
+    /// Whether the workbook uses the 1904 date system
+    pub fn is_1904(&self) -> bool {
+        self.is_1904
+    }
+
+    /// Path of the worksheet part for a sheet
+    pub fn sheet_path(&self, sheet_name: &str) -> Result<String> {
+        self.sheets
+            .iter()
+            .find(|sheet| sheet.name == sheet_name)
+            .map(|sheet| sheet.path.clone())
+            .with_context(|| format!("Sheet '{}' not found", sheet_name))
+    }
+
+    /// The used cells a sheet declares in its `<dimension>` element, if it has one.
+    /// Only the start of the sheet, up to its cells, is read.
+    pub fn sheet_dimension(&mut self, sheet_name: &str) -> Result<Option<CellRegion>> {
+        let path = self.sheet_path(sheet_name)?;
+        let Some(mut reader) = self.part_reader(&path)? else {
+            return Ok(None);
+        };
+
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader
+                .read_event_into(&mut buf)
+                .with_context(|| format!("Failed to parse XLSX part: {}", path))?
+            {
+                Event::Start(ref e) | Event::Empty(ref e) => match e.local_name().as_ref() {
+                    b"dimension" => {
+                        let range = attribute(e, "ref").and_then(|r| r.parse::<CellRange>().ok());
+                        return Ok(range.and_then(|range| {
+                            Some((range.start, (range.end_row?, range.end_column)))
+                        }));
+                    }
+                    b"sheetData" => return Ok(None),
+                    _ => {}
+                },
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads the merged regions and hidden rows and columns of a sheet, plus its
+    /// hyperlinks and comments when `annotations` is set, in one pass over the sheet
+    pub fn sheet_layout(&mut self, sheet_name: &str, annotations: bool) -> Result<SheetLayout> {
+        let path = self.sheet_path(sheet_name)?;
+        let relationships = if annotations {
+            self.sheet_relationships(&path)?
+        } else {
+            HashMap::new()
+        };
+
+        let mut layout = SheetLayout::default();
+        self.visit_part(&path, |event| {
+            let PartEvent::Start(e) = event else {
+                return Ok(());
+            };
+            let is_hidden = || attribute(e, "hidden").is_some_and(|h| h == "1" || h == "true");
+            match e.local_name().as_ref() {
+                b"row" if is_hidden() => {
+                    if let Some(row) = attribute(e, "r").and_then(|r| r.parse::<u32>().ok()) {
+                        layout.hidden.rows.insert(row.saturating_sub(1));
+                    }
+                }
+                b"col" if is_hidden() => {
+                    let min = attribute(e, "min").and_then(|m| m.parse::<u32>().ok());
+                    let max = attribute(e, "max").and_then(|m| m.parse::<u32>().ok());
+                    if let (Some(min), Some(max)) = (min, max) {
+                        layout
+                            .hidden
+                            .columns
+                            .push((min.saturating_sub(1), max.saturating_sub(1)));
+                    }
+                }
+                b"mergeCell" => {
+                    let range = attribute(e, "ref").and_then(|r| r.parse::<CellRange>().ok());
+                    if let Some(CellRange {
+                        start,
+                        end_column,
+                        end_row: Some(end_row),
+                    }) = range
+                    {
+                        layout.merged_regions.push((start, (end_row, end_column)));
+                    }
+                }
+                b"hyperlink" if annotations => {
+                    let external = attribute(e, "id").and_then(|id| relationships.get(&id));
+                    let target = match (external, attribute(e, "location")) {
+                        (Some((_, url)), Some(location)) => format!("{}#{}", url, location),
+                        (Some((_, url)), None) => url.clone(),
+                        (None, Some(location)) => format!("#{}", location),
+                        (None, None) => return Ok(()),
+                    };
+                    let range = attribute(e, "ref").and_then(|r| r.parse::<CellRange>().ok());
+                    if let Some(range) = range {
+                        let end_row = range.end_row.unwrap_or(range.start.0);
+                        for row in range.start.0..=end_row {
+                            for col in range.start.1..=range.end_column {
+                                let hyperlinks = &mut layout.annotations.hyperlinks;
+                                hyperlinks.insert((row, col), target.clone());
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+
+        let comments_path = relationships
+            .values()
+            .find(|(kind, _)| kind.ends_with("/comments"))
+            .map(|(_, target)| target.clone());
+        if let Some(comments_path) = comments_path {
+            let comments = &mut layout.annotations.comments;
+            let mut current: Option<((u32, u32), String)> = None;
+            self.visit_part(&comments_path, |event| {
+                match event {
+                    PartEvent::Start(e) if e.local_name().as_ref() == b"comment" => {
+                        current = attribute(e, "ref")
+                            .and_then(|r| r.parse::<CellRange>().ok())
+                            .map(|range| (range.start, String::new()));
+                    }
+                    PartEvent::Text(text) => {
+                        if let Some((_, comment)) = &mut current {
+                            comment.push_str(text);
+                        }
+                    }
+                    PartEvent::End(b"comment") => {
+                        if let Some((position, comment)) = current.take() {
+                            comments.insert(position, comment);
+                        }
+                    }
+                    _ => {}
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(layout)
+    }
+
+    /// Excel tables of all sheets, in sheet order
+    pub fn tables(&mut self) -> Result<Vec<TableEntry>> {
+        let mut tables = Vec::new();
+        for sheet in self.sheets.clone() {
+            let relationships = self.sheet_relationships(&sheet.path)?;
+            let mut parts: Vec<&String> = relationships
+                .values()
+                .filter(|(kind, _)| kind.ends_with("/table"))
+                .map(|(_, target)| target)
+                .collect();
+            parts.sort();
+
+            for part in parts {
+                let mut name = None;
+                let mut reference = None;
+                let mut header_rows = 1;
+                let mut totals_rows = 0;
+                let mut insert_row = false;
+                let mut columns = Vec::new();
+                self.visit_part(part, |event| {
+                    let PartEvent::Start(e) = event else {
+                        return Ok(());
+                    };
+                    let count = |key| attribute(e, key).and_then(|c| c.parse::<u32>().ok());
+                    match e.local_name().as_ref() {
+                        b"table" => {
+                            name = attribute(e, "displayName").or_else(|| attribute(e, "name"));
+                            reference = attribute(e, "ref");
+                            header_rows = count("headerRowCount").unwrap_or(1);
+                            totals_rows = count("totalsRowCount").unwrap_or(0);
+                            insert_row =
+                                attribute(e, "insertRow").is_some_and(|i| i == "1" || i == "true");
+                        }
+                        b"tableColumn" => columns.push(attribute(e, "name").unwrap_or_default()),
+                        _ => {}
+                    }
+                    Ok(())
+                })?;
+
+                let range = reference.and_then(|r| r.parse::<CellRange>().ok());
+                let (Some(name), Some(range)) = (name, range) else {
+                    continue;
+                };
+                let end_row = range.end_row.unwrap_or(range.start.0);
+                let first = range.start.0 + header_rows;
+                let last = end_row.checked_sub(totals_rows + insert_row as u32);
+                let data = last
+                    .filter(|&last| first <= last)
+                    .map(|last| ((first, range.start.1), (last, range.end_column)));
+                tables.push(TableEntry {
+                    name,
+                    sheet_name: sheet.name.clone(),
+                    columns,
+                    data,
+                });
+            }
+        }
+        Ok(tables)
+    }
+
+    /// Relationships of a worksheet part, as relationship id -> (type, target part or
+    /// external URL)
+    fn sheet_relationships(&mut self, path: &str) -> Result<HashMap<String, (String, String)>> {
+        let (folder, file) = path.rsplit_once('/').unwrap_or(("", path));
+
+        let mut relationships = HashMap::new();
+        self.visit_part(&format!("{}/_rels/{}.rels", folder, file), |event| {
+            let PartEvent::Start(e) = event else {
+                return Ok(());
+            };
+            if e.local_name().as_ref() == b"Relationship" {
+                let external = attribute(e, "TargetMode").is_some_and(|m| m == "External");
+                if let (Some(id), Some(kind), Some(target)) = (
+                    attribute(e, "Id"),
+                    attribute(e, "Type"),
+                    attribute(e, "Target"),
+                ) {
+                    let target = if external {
+                        target
+                    } else {
+                        resolve_target(folder, &target)
+                    };
+                    relationships.insert(id, (kind, target));
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(relationships)
+    }
+
+    /// Number formats of the cell styles (`cellXfs`) in the styles part, by style index
+    pub fn cell_styles(&mut self) -> Result<Vec<CellStyle>> {
+        if let Some(cell_styles) = &self.cell_styles {
+            return Ok(cell_styles.clone());
+        }
+
+        let mut custom_formats = HashMap::new();
+        let mut format_ids = Vec::new();
+        let mut in_cell_xfs = false;
+        self.visit_part("xl/styles.xml", |event| {
+            match event {
+                PartEvent::Start(e) => match e.local_name().as_ref() {
+                    b"numFmt" => {
+                        let id = attribute(e, "numFmtId").and_then(|id| id.parse::<u32>().ok());
+                        if let (Some(id), Some(code)) = (id, attribute(e, "formatCode")) {
+                            custom_formats.insert(id, code);
+                        }
+                    }
+                    b"cellXfs" => in_cell_xfs = true,
+                    b"xf" if in_cell_xfs => {
+                        let id = attribute(e, "numFmtId").and_then(|id| id.parse::<u32>().ok());
+                        format_ids.push(id.unwrap_or(0));
+                    }
+                    _ => {}
+                },
+                PartEvent::End(b"cellXfs") => in_cell_xfs = false,
+                _ => {}
+            }
+            Ok(())
+        })?;
+
+        let cell_styles: Vec<CellStyle> = format_ids
+            .into_iter()
+            .map(|id| match custom_formats.get(&id) {
+                Some(code) => CellStyle {
+                    format: Some(code.clone()).filter(|code| !is_general_format(code)),
+                    kind: number_format_kind(code),
+                },
+                None => CellStyle {
+                    format: builtin_format(id)
+                        .filter(|code| !is_general_format(code))
+                        .map(str::to_string),
+                    kind: builtin_format_kind(id),
+                },
+            })
+            .collect();
+        self.cell_styles = Some(cell_styles.clone());
+        Ok(cell_styles)
+    }
+
+    /// Opens an XML part for reading, or returns None if it does not exist
+    pub fn part_reader(&mut self, path: &str) -> Result<Option<Reader<BufReader<ZipFile<'_>>>>> {
+        match self.archive.by_name(path) {
+            Ok(part) => Ok(Some(Reader::from_reader(BufReader::new(part)))),
+            Err(zip::result::ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e).context(format!("Failed to read XLSX part: {}", path)),
+        }
+    }
+
+    /// Streams the events of an XML part to `visit`. Returns false if the part does not exist.
+    pub fn visit_part<F>(&mut self, path: &str, mut visit: F) -> Result<bool>
+    where
+        F: FnMut(PartEvent<'_>) -> Result<()>,
+    {
+        let Some(mut reader) = self.part_reader(path)? else {
+            return Ok(false);
+        };
+        let mut buf = Vec::new();
+        loop {
+            match reader
+                .read_event_into(&mut buf)
+                .with_context(|| format!("Failed to parse XLSX part: {}", path))?
+            {
+                Event::Start(ref e) => visit(PartEvent::Start(e))?,
+                Event::Empty(ref e) => {
+                    visit(PartEvent::Start(e))?;
+                    visit(PartEvent::End(e.local_name().as_ref()))?;
+                }
+                Event::End(ref e) => visit(PartEvent::End(e.local_name().as_ref()))?,
+                Event::Text(ref t) => {
+                    let text = t
+                        .unescape()
+                        .with_context(|| format!("Failed to parse XLSX part: {}", path))?;
+                    visit(PartEvent::Text(&text))?;
+                }
+                Event::CData(ref t) => visit(PartEvent::Text(&String::from_utf8_lossy(t)))?,
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        Ok(true)
+    }
+}
+
+/// Value of the attribute with the given local name (ignoring any namespace prefix)
+pub fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name.as_bytes())
+        .and_then(|a| {
+            let raw = std::str::from_utf8(&a.value).ok()?;
+            unescape(raw).ok().map(|v| v.into_owned())
+        })
+}
+
+/// Resolve a relationship target relative to the folder of the part that declares it
+pub fn resolve_target(base_folder: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut parts: Vec<&str> = base_folder.split('/').filter(|p| !p.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            segment => parts.push(segment),
+        }
+    }
+    parts.join("/")
+}
@@ -0,0 +1,213 @@
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Turns compound keys into nested objects, like `{"address.city": "Oslo"}` into
+/// `{"address": {"city": "Oslo"}}`.
+///
+/// With `indexes`, parts ending in list indexes like `items[0]` or `grid[1][2]` build
+/// arrays; gaps between indexes are filled with null. Fails when a key is used both for
+/// a value and for a group of keys, or both as a list and as an object.
+pub fn unflatten_record(
+    flat: Map<String, Value>,
+    separator: &str,
+    indexes: bool,
+) -> Result<Map<String, Value>> {
+    let mut root = Node::Object(BTreeMap::new());
+
+    for (key, value) in flat {
+        let mut node = &mut root;
+        for segment in parse_path(&key, separator, indexes) {
+            node = node.child(&segment, &key)?;
+        }
+
+        if !matches!(node, Node::Empty) {
+            bail!("Header '{}' is both a value and a group of columns", key);
+        }
+        *node = Node::Leaf(value);
+    }
+
+    match root.into_value() {
+        Value::Object(record) => Ok(record),
+        _ => unreachable!("the root is an object"),
+    }
+}
+
+/// Largest list index; larger ones are kept in the name, so a header cannot make
+/// the converter allocate huge lists
+const MAX_INDEX: usize = 9_999;
+
+/// A step in the path of a key: an object member or a list element
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// Splits a key like `items[0].sku` into `items`, `0` and `sku`; paths always start
+/// with a key. Parts whose brackets hold anything but an index, like `price[EUR]`, are
+/// kept as they are.
+fn parse_path<'a>(key: &'a str, separator: &str, indexes: bool) -> Vec<Segment<'a>> {
+    let mut path = Vec::new();
+    for part in key.split(separator) {
+        match indexes.then(|| split_indexes(part)).flatten() {
+            Some((name, part_indexes)) => {
+                path.push(Segment::Key(name));
+                path.extend(part_indexes.into_iter().map(Segment::Index));
+            }
+            None => path.push(Segment::Key(part)),
+        }
+    }
+    path
+}
+
+/// Splits `grid[1][2]` into `grid` and `[1, 2]`
+pub(crate) fn split_indexes(part: &str) -> Option<(&str, Vec<usize>)> {
+    let start = part.find('[').filter(|&start| start > 0)?;
+    let mut rest = &part[start..];
+    let mut indexes = Vec::new();
+    while !rest.is_empty() {
+        let (index, after) = rest.strip_prefix('[')?.split_once(']')?;
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        indexes.push(index.parse().ok().filter(|&index| index <= MAX_INDEX)?);
+        rest = after;
+    }
+    Some((&part[..start], indexes))
+}
+
+/// The top-level key that `key` nests under, like `items` for `items[0].sku`
+pub(crate) fn top_level_key<'a>(key: &'a str, separator: &str) -> &'a str {
+    let first = key.split(separator).next().unwrap_or(key);
+    match split_indexes(first) {
+        Some((name, _)) => name,
+        None => first,
+    }
+}
+
+/// A value being built; `Empty` marks members not set yet, like gaps in lists
+enum Node {
+    Empty,
+    Leaf(Value),
+    Object(BTreeMap<String, Node>),
+    Array(Vec<Node>),
+}
+
+impl Node {
+    /// The member or element for `segment`, turning an empty node into an object or list
+    fn child(&mut self, segment: &Segment, key: &str) -> Result<&mut Node> {
+        if matches!(self, Node::Empty) {
+            *self = match segment {
+                Segment::Key(_) => Node::Object(BTreeMap::new()),
+                Segment::Index(_) => Node::Array(Vec::new()),
+            };
+        }
+
+        match (self, segment) {
+            (Node::Object(members), Segment::Key(name)) => {
+                Ok(members.entry(name.to_string()).or_insert(Node::Empty))
+            }
+            (Node::Array(elements), Segment::Index(index)) => {
+                if elements.len() <= *index {
+                    elements.resize_with(index + 1, || Node::Empty);
+                }
+                Ok(&mut elements[*index])
+            }
+            (Node::Leaf(_), _) => bail!("Header '{}' is both a value and a group of columns", key),
+            _ => bail!(
+                "Header '{}' uses a name both as a list and as an object",
+                key
+            ),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Node::Empty => Value::Null,
+            Node::Leaf(value) => value,
+            Node::Object(members) => Value::Object(
+                members
+                    .into_iter()
+                    .map(|(name, node)| (name, node.into_value()))
+                    .collect(),
+            ),
+            Node::Array(elements) => {
+                Value::Array(elements.into_iter().map(Node::into_value).collect())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn unflatten(flat: Value, separator: &str) -> Result<Value> {
+        let Value::Object(flat) = flat else {
+            panic!("records are objects")
+        };
+        unflatten_record(flat, separator, true).map(Value::Object)
+    }
+
+    #[test]
+    fn test_unflatten_objects_and_arrays() {
+        let record = json!({
+            "id": 1,
+            "address.city": "Oslo",
+            "address.zip": "0150",
+            "items[0].sku": "A1",
+            "items[0].qty": 2,
+            "items[2].sku": "C3",
+            "grid[1][0]": true,
+            "price[EUR]": 5,
+            "big[10000]": 6,
+        });
+
+        assert_eq!(
+            unflatten(record, ".").unwrap(),
+            json!({
+                "id": 1,
+                "address": {"city": "Oslo", "zip": "0150"},
+                "items": [{"qty": 2, "sku": "A1"}, null, {"sku": "C3"}],
+                "grid": [null, [true]],
+                "price[EUR]": 5,
+                "big[10000]": 6,
+            })
+        );
+    }
+
+    #[test]
+    fn test_unflatten_separator() {
+        assert_eq!(
+            unflatten(json!({"a/b": 1, "a/c[1]": 2, "x.y": 3}), "/").unwrap(),
+            json!({"a": {"b": 1, "c": [null, 2]}, "x.y": 3})
+        );
+
+        // Without indexes, brackets are part of the name
+        let Value::Object(flat) = json!({"a.b[0]": 1}) else {
+            unreachable!()
+        };
+        assert_eq!(
+            Value::Object(unflatten_record(flat, ".", false).unwrap()),
+            json!({"a": {"b[0]": 1}})
+        );
+    }
+
+    #[test]
+    fn test_unflatten_conflicts() {
+        let error = unflatten(json!({"price": 1, "price.net": 2}), ".").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Header 'price.net' is both a value and a group of columns"
+        );
+
+        let error = unflatten(json!({"a[0]": 1, "a[0].b": 2}), ".").unwrap_err();
+        assert!(error.to_string().contains("'a[0].b' is both a value"));
+
+        let error = unflatten(json!({"a.b": 1, "a[0]": 2}), ".").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("both as a list and as an object"));
+    }
+}
@@ -1,4 +1,9 @@
 use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::date_recognition::{DateColumn, DateValue};
 
 /// Converts a field value to appropriate JSON Value based on type inference
 pub fn convert_field_value(
@@ -7,47 +12,656 @@ pub fn convert_field_value(
     no_type_conversion: bool,
     string_fields: &[String],
 ) -> Value {
-    if no_type_conversion {
-        // No type conversion - keep everything as strings except empty fields
-        if field.is_empty() {
-            Value::Null
-        } else {
-            Value::String(field.to_string())
+    ColumnConverter::for_column(header_name, no_type_conversion, string_fields).convert(field)
+}
+
+/// How the fields of a column are converted, resolved once from its header name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnConverter {
+    /// Keep fields as strings (empty fields still become null)
+    text: bool,
+    /// Separators of numbers
+    locale: NumberLocale,
+    /// Date formats recognized in the column
+    dates: Option<Arc<DateColumn>>,
+    /// Words read as null and as booleans besides empty fields and "true"/"false"
+    vocabulary: Option<Arc<Vocabulary>>,
+    /// How to write numbers that do not fit an i64, u64 or f64 exactly
+    big_numbers: BigNumbers,
+    /// Turns fields into JSON structures, like lists
+    transform: Option<CellTransform>,
+}
+
+impl ColumnConverter {
+    /// Keeps fields as strings
+    pub fn text() -> Self {
+        Self {
+            text: true,
+            locale: NumberLocale::default(),
+            dates: None,
+            vocabulary: None,
+            big_numbers: BigNumbers::default(),
+            transform: None,
+        }
+    }
+
+    /// Infers numbers and booleans
+    pub fn infer() -> Self {
+        Self {
+            text: false,
+            ..Self::text()
         }
-    } else if string_fields.iter().any(|f| f == header_name) {
-        // Field is in the string_fields list - always keep as string
-        if field.is_empty() {
-            Value::Null
+    }
+
+    pub fn for_column(
+        header_name: &str,
+        no_type_conversion: bool,
+        string_fields: &[String],
+    ) -> Self {
+        if no_type_conversion || string_fields.iter().any(|f| f == header_name) {
+            Self::text()
         } else {
-            Value::String(field.to_string())
+            Self::infer()
         }
-    } else {
+    }
+
+    /// Read numbers with the decimal and thousands separators of `locale`
+    pub fn with_locale(mut self, locale: NumberLocale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Recognize dates in the given formats, unless fields are kept as strings
+    pub fn with_dates(mut self, dates: Option<Arc<DateColumn>>) -> Self {
+        self.dates = dates;
+        self
+    }
+
+    /// Read the given words as null and as booleans; null words also apply to columns
+    /// kept as strings
+    pub fn with_vocabulary(mut self, vocabulary: Option<Arc<Vocabulary>>) -> Self {
+        self.vocabulary = vocabulary;
+        self
+    }
+
+    /// Write numbers that do not fit an i64, u64 or f64 exactly as `big_numbers` says
+    pub fn with_big_numbers(mut self, big_numbers: BigNumbers) -> Self {
+        self.big_numbers = big_numbers;
+        self
+    }
+
+    /// Parse fields as JSON or split them into lists. Fields that are not valid JSON
+    /// are converted as usual.
+    pub fn with_transform(mut self, transform: Option<CellTransform>) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Whether fields may become arrays or objects rather than single values
+    pub fn is_structured(&self) -> bool {
+        self.transform.is_some()
+    }
+
+    /// Converts a field to a JSON value
+    pub fn convert(&self, field: &str) -> Value {
+        self.scalar(field).into_value()
+    }
+
+    /// Writes a field as JSON, exactly as the value from `convert` would be serialized,
+    /// without building the value first
+    pub fn write_json(&self, field: &str, out: &mut Vec<u8>) -> serde_json::Result<()> {
+        match self.scalar(field) {
+            Scalar::Null => out.extend_from_slice(b"null"),
+            Scalar::Bool(true) => out.extend_from_slice(b"true"),
+            Scalar::Bool(false) => out.extend_from_slice(b"false"),
+            Scalar::Int(n) => serde_json::to_writer(out, &n)?,
+            Scalar::UInt(n) => serde_json::to_writer(out, &n)?,
+            Scalar::Float(n) => serde_json::to_writer(out, &n)?,
+            Scalar::Text(s) => serde_json::to_writer(out, s)?,
+            Scalar::Date(s) => serde_json::to_writer(out, &s)?,
+            Scalar::Literal(number) => out.extend_from_slice(number.as_bytes()),
+            Scalar::Structured(value) => serde_json::to_writer(out, &value)?,
+        }
+        Ok(())
+    }
+
+    fn scalar<'a>(&self, field: &'a str) -> Scalar<'a> {
+        match &self.transform {
+            Some(transform) if !self.is_null(field) => match transform {
+                CellTransform::Json => serde_json::from_str(field)
+                    .map_or_else(|_| self.infer_scalar(field), Scalar::Structured),
+                // Elements are typed like fields of the column
+                CellTransform::List(separator) => Scalar::Structured(Value::Array(
+                    field
+                        .split(separator.as_str())
+                        .map(|element| self.infer_scalar(element.trim()).into_value())
+                        .collect(),
+                )),
+            },
+            _ => self.infer_scalar(field),
+        }
+    }
+
+    fn is_null(&self, field: &str) -> bool {
+        field.is_empty() || self.vocabulary.as_ref().is_some_and(|v| v.is_null(field))
+    }
+
+    /// Infers the type of a single value
+    fn infer_scalar<'a>(&self, field: &'a str) -> Scalar<'a> {
+        let vocabulary = self.vocabulary.as_deref();
+        if self.is_null(field) {
+            return Scalar::Null;
+        }
+        if self.text {
+            return Scalar::Text(field);
+        }
+        let locale = self.locale;
+
+        // Dates come first, since "03/01/2024" would otherwise keep its leading zero
+        if let Some(date) = self.dates.as_ref().and_then(|dates| dates.parse(field)) {
+            return match date {
+                DateValue::Text(text) => Scalar::Date(text),
+                DateValue::Epoch(epoch) => Scalar::Int(epoch),
+            };
+        }
+
         // Smart type conversion, but preserve leading zeros (zipcodes, phone numbers, etc)
-        let has_leading_zero =
-            field.starts_with('0') && field.len() > 1 && !field.starts_with("0.");
-
-        if field.is_empty() {
-            Value::Null
-        } else if field.eq_ignore_ascii_case("true") {
-            Value::Bool(true)
-        } else if field.eq_ignore_ascii_case("false") {
-            Value::Bool(false)
-        } else if !has_leading_zero {
+        let has_leading_zero = field.starts_with('0')
+            && field.len() > 1
+            && !field[1..].starts_with(locale.decimal_separator);
+
+        if field.eq_ignore_ascii_case("true") || vocabulary.is_some_and(|v| v.is_true(field)) {
+            Scalar::Bool(true)
+        } else if field.eq_ignore_ascii_case("false")
+            || vocabulary.is_some_and(|v| v.is_false(field))
+        {
+            Scalar::Bool(false)
+        } else if has_leading_zero {
+            // Has leading zero - keep as string to preserve it
+            Scalar::Text(field)
+        } else if let Some(number) = locale.normalize_number(field) {
+            self.parse_number(&number, field)
+        } else if locale.decimal_separator == '.' {
             // Only try to parse as number if no leading zero
-            if let Ok(num) = field.parse::<i64>() {
-                Value::Number(num.into())
-            } else if let Ok(num) = field.parse::<f64>() {
-                if let Some(n) = serde_json::Number::from_f64(num) {
-                    Value::Number(n)
-                } else {
-                    Value::String(field.to_string())
+            self.parse_number(field, field)
+        } else {
+            Scalar::Text(field)
+        }
+    }
+
+    /// Parses `number`, in Rust syntax ("42", "-1.5", "1e3"), as the value of `field`.
+    ///
+    /// Integers beyond u64, decimals with more digits than an f64 holds and numbers
+    /// beyond the f64 range either way (like "1e400" and "1e-400") are written as
+    /// `big_numbers` says, so IDs and amounts are not rounded unless asked for.
+    fn parse_number<'a>(&self, number: &str, field: &'a str) -> Scalar<'a> {
+        if let Ok(num) = number.parse::<i64>() {
+            return Scalar::Int(num);
+        }
+        if let Ok(num) = number.parse::<u64>() {
+            return Scalar::UInt(num);
+        }
+        let Ok(num) = number.parse::<f64>() else {
+            return Scalar::Text(field);
+        };
+        // Words like "NaN" and "inf" are not numbers in JSON
+        let Some(plain) = PlainNumber::parse(number) else {
+            return Scalar::Text(field);
+        };
+
+        // Too large a number overflows to infinity, which cannot be rounded to an f64
+        if num.is_finite() && (self.big_numbers == BigNumbers::Float || plain.is_exactly(num)) {
+            return Scalar::Float(num);
+        }
+        match self.big_numbers {
+            BigNumbers::Exact => Scalar::Literal(plain.to_json()),
+            BigNumbers::String | BigNumbers::Float => Scalar::Text(field),
+        }
+    }
+}
+
+/// What to do with numbers that an i64, u64 or f64 cannot hold exactly, like
+/// "12345678901234567890123" or "0.1000000000000000055"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BigNumbers {
+    /// Write the number with all of its digits
+    #[default]
+    Exact,
+    /// Write the number as a string, as it appears in the input
+    String,
+    /// Round to the nearest f64; numbers too large for one are written as strings
+    Float,
+}
+
+impl FromStr for BigNumbers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exact" => Ok(BigNumbers::Exact),
+            "string" => Ok(BigNumbers::String),
+            "float" => Ok(BigNumbers::Float),
+            _ => Err(format!(
+                "unknown big number policy '{}' (expected 'exact', 'string' or 'float')",
+                s
+            )),
+        }
+    }
+}
+
+/// The parts of a finite number in Rust syntax, like "-12.50e3"
+struct PlainNumber<'a> {
+    negative: bool,
+    integer: &'a str,
+    fraction: &'a str,
+    exponent: i64,
+}
+
+impl<'a> PlainNumber<'a> {
+    fn parse(number: &'a str) -> Option<Self> {
+        let negative = number.starts_with('-');
+        let unsigned = number.strip_prefix(['-', '+']).unwrap_or(number);
+        let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse().ok()?),
+            None => (unsigned, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+        let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if (integer.is_empty() && fraction.is_empty()) || !digits(integer) || !digits(fraction) {
+            return None;
+        }
+        Some(Self {
+            negative,
+            integer,
+            fraction,
+            exponent,
+        })
+    }
+
+    /// The significant digits and the power of ten of the first of them, so "1.50",
+    /// "15e-1" and "0.0015e3" have the same value. Zero has no significant digits.
+    fn significand(&self) -> (String, i64) {
+        let digits = format!("{}{}", self.integer, self.fraction);
+        let significant = digits.trim_start_matches('0');
+        let leading_zeros = (digits.len() - significant.len()) as i64;
+        let significant = significant.trim_end_matches('0');
+        if significant.is_empty() {
+            return (String::new(), 0);
+        }
+        let power = self
+            .exponent
+            .saturating_add(self.integer.len() as i64 - leading_zeros - 1);
+        (significant.to_string(), power)
+    }
+
+    /// Whether `value` is exactly this number, comparing its shortest decimal form
+    fn is_exactly(&self, value: f64) -> bool {
+        let shortest = format!("{:e}", value);
+        let Some(shortest) = PlainNumber::parse(&shortest) else {
+            return false;
+        };
+        let (digits, power) = self.significand();
+        (digits.is_empty() || shortest.negative == self.negative)
+            && shortest.significand() == (digits, power)
+    }
+
+    /// The number as a JSON literal, like "-12.5e3" for "-012.5E+3"
+    fn to_json(&self) -> String {
+        let integer = self.integer.trim_start_matches('0');
+        let mut json = String::with_capacity(self.integer.len() + self.fraction.len() + 8);
+        if self.negative {
+            json.push('-');
+        }
+        json.push_str(if integer.is_empty() { "0" } else { integer });
+        if !self.fraction.is_empty() {
+            json.push('.');
+            json.push_str(self.fraction);
+        }
+        if self.exponent != 0 {
+            json.push('e');
+            json.push_str(&self.exponent.to_string());
+        }
+        json
+    }
+}
+
+/// A field value as inferred from its text
+enum Scalar<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Text(&'a str),
+    /// A recognized date written as ISO-8601
+    Date(String),
+    /// A number too long for the numeric types, as a JSON literal
+    Literal(String),
+    /// An array or object from a transformed field
+    Structured(Value),
+}
+
+impl Scalar<'_> {
+    fn into_value(self) -> Value {
+        match self {
+            Scalar::Null => Value::Null,
+            Scalar::Bool(b) => Value::Bool(b),
+            Scalar::Int(n) => Value::Number(n.into()),
+            Scalar::UInt(n) => Value::Number(n.into()),
+            Scalar::Float(n) => serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number),
+            Scalar::Text(s) => Value::String(s.to_string()),
+            Scalar::Date(s) => Value::String(s),
+            Scalar::Literal(number) => {
+                Value::Number(number.parse().expect("literals are valid JSON numbers"))
+            }
+            Scalar::Structured(value) => value,
+        }
+    }
+}
+
+/// How `--json-columns` and `--list-column` turn a column's fields into structures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CellTransform {
+    /// Parse fields as JSON, like '{"source":"web"}'
+    Json,
+    /// Split fields on the separator into an array, like "a;b;c" on ";"
+    List(String),
+}
+
+/// A `--list-column` argument: "tags" splits the column on commas, "tags=;" on the
+/// given separator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListColumn {
+    pub column: String,
+    pub separator: String,
+}
+
+impl FromStr for ListColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, separator) = s.split_once('=').unwrap_or((s, ","));
+        if column.is_empty() || separator.is_empty() {
+            return Err(format!(
+                "invalid list column '{}' (expected 'column' or 'column=separator')",
+                s
+            ));
+        }
+        Ok(Self {
+            column: column.to_string(),
+            separator: separator.to_string(),
+        })
+    }
+}
+
+/// Words read as null and as booleans in a column, like "NULL" and "\N" or "yes"/"no"
+///
+/// Null words must match exactly; boolean words are matched ignoring ASCII case, like
+/// "true" and "false", which are always recognized.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Vocabulary {
+    pub null_values: Vec<String>,
+    pub true_values: Vec<String>,
+    pub false_values: Vec<String>,
+}
+
+impl Vocabulary {
+    fn is_null(&self, field: &str) -> bool {
+        self.null_values.iter().any(|word| word == field)
+    }
+
+    fn is_true(&self, field: &str) -> bool {
+        self.true_values
+            .iter()
+            .any(|word| word.eq_ignore_ascii_case(field))
+    }
+
+    fn is_false(&self, field: &str) -> bool {
+        self.false_values
+            .iter()
+            .any(|word| word.eq_ignore_ascii_case(field))
+    }
+}
+
+/// Comma-separated words for `--null-values`, `--true-values` or `--false-values`,
+/// either for all columns ("NULL,N/A") or for one column ("score=-,n/a")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordList {
+    pub column: Option<String>,
+    pub words: Vec<String>,
+}
+
+impl FromStr for WordList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, words) = match s.split_once('=') {
+            Some((column, words)) => (Some(column.to_string()), words),
+            None => (None, s),
+        };
+        let words: Vec<String> = words
+            .split(',')
+            .map(str::trim)
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if words.is_empty() {
+            return Err(format!(
+                "no words in '{}' (expected a list like 'NULL,N/A')",
+                s
+            ));
+        }
+        Ok(Self { column, words })
+    }
+}
+
+/// The null and boolean words configured for all columns and for single columns
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VocabularyOptions {
+    pub null_values: Vec<WordList>,
+    pub true_values: Vec<WordList>,
+    pub false_values: Vec<WordList>,
+}
+
+impl VocabularyOptions {
+    /// Builds the vocabulary of each column. Words given for a column are used in
+    /// addition to the words for all columns.
+    pub fn resolve(&self) -> ColumnVocabularies {
+        let words = |lists: &[WordList], column: Option<&str>| -> Vec<String> {
+            lists
+                .iter()
+                .filter(|list| list.column.is_none() || list.column.as_deref() == column)
+                .flat_map(|list| list.words.iter().cloned())
+                .collect()
+        };
+        let collect = |column: Option<&str>| Vocabulary {
+            null_values: words(&self.null_values, column),
+            true_values: words(&self.true_values, column),
+            false_values: words(&self.false_values, column),
+        };
+
+        let default = Some(collect(None))
+            .filter(|vocabulary| *vocabulary != Vocabulary::default())
+            .map(Arc::new);
+        let columns = [&self.null_values, &self.true_values, &self.false_values]
+            .into_iter()
+            .flatten()
+            .filter_map(|list| list.column.as_deref())
+            .map(|column| (column.to_string(), Arc::new(collect(Some(column)))))
+            .collect();
+
+        ColumnVocabularies { default, columns }
+    }
+}
+
+/// The vocabulary of each column, resolved from `VocabularyOptions`
+#[derive(Debug, Clone, Default)]
+pub struct ColumnVocabularies {
+    default: Option<Arc<Vocabulary>>,
+    columns: HashMap<String, Arc<Vocabulary>>,
+}
+
+impl ColumnVocabularies {
+    /// The words for a column, or None if only the defaults apply
+    pub fn get(&self, header_name: &str) -> Option<Arc<Vocabulary>> {
+        self.columns
+            .get(header_name)
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+/// Decimal and thousands separators used to write numbers, like "1.234,56" in German.
+///
+/// The default reads numbers in Rust syntax only, so "1,234.56" stays a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberLocale {
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+}
+
+impl Default for NumberLocale {
+    fn default() -> Self {
+        Self::new('.', None)
+    }
+}
+
+impl NumberLocale {
+    pub fn new(decimal_separator: char, thousands_separator: Option<char>) -> Self {
+        Self {
+            decimal_separator,
+            thousands_separator,
+        }
+    }
+
+    /// The usual number style of files with this delimiter: semicolon-delimited files
+    /// come from locales that write decimal commas, since the comma is taken
+    pub fn for_delimiter(delimiter: u8) -> Self {
+        if delimiter == b';' {
+            Self::new(',', Some('.'))
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Rewrites a number written with these separators, like "-1.234,5" or "1 234", in
+    /// Rust syntax ("-1234.5", "1234").
+    ///
+    /// Thousands separators must split the integer part into groups of three digits,
+    /// so "1.23" is not read as 123 in a locale with decimal commas. Returns None for
+    /// anything else, including the default locale, which leaves numbers to Rust syntax.
+    fn normalize_number(self, field: &str) -> Option<String> {
+        if self == Self::default() {
+            return None;
+        }
+
+        let unsigned = field.strip_prefix(['-', '+']).unwrap_or(field);
+        let negative = field.starts_with('-');
+        let (integer, fraction) = match unsigned.split_once(self.decimal_separator) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (unsigned, None),
+        };
+
+        let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        let mut number = String::with_capacity(field.len());
+        if negative {
+            number.push('-');
+        }
+
+        match self.thousands_separator {
+            Some(separator) if integer.contains(|c| is_separator(c, separator)) => {
+                let mut groups = integer.split(|c| is_separator(c, separator));
+                let first = groups.next()?;
+                if !all_digits(first) || first.len() > 3 {
+                    return None;
+                }
+                number.push_str(first);
+                for group in groups {
+                    if !all_digits(group) || group.len() != 3 {
+                        return None;
+                    }
+                    number.push_str(group);
                 }
-            } else {
-                Value::String(field.to_string())
             }
+            _ if all_digits(integer) => number.push_str(integer),
+            _ => return None,
+        }
+
+        match fraction {
+            Some(fraction) if all_digits(fraction) => {
+                number.push('.');
+                number.push_str(fraction);
+                Some(number)
+            }
+            Some(_) => None,
+            None => Some(number),
+        }
+    }
+}
+
+/// Whether `c` is the thousands separator, treating the no-break spaces some locales
+/// use as a space and the typographic apostrophe as an apostrophe. Locales grouping
+/// with dots, like German, also write "1 234,56" with spaces.
+fn is_separator(c: char, separator: char) -> bool {
+    c == separator
+        || (matches!(separator, ' ' | '.') && matches!(c, ' ' | '\u{a0}' | '\u{202f}'))
+        || (separator == '\'' && c == '\u{2019}')
+}
+
+impl FromStr for NumberLocale {
+    type Err = String;
+
+    /// Parses a locale name like "de", "nl-NL", "fr_FR" or "de-CH"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase().replace('_', "-");
+        let language = name.split('-').next().unwrap_or_default();
+
+        if name.ends_with("-ch") {
+            return Ok(Self::new('.', Some('\'')));
+        }
+        match language {
+            "c" | "posix" => Ok(Self::default()),
+            "en" | "ja" | "zh" | "ko" | "th" | "he" => Ok(Self::new('.', Some(','))),
+            "de" | "nl" | "es" | "it" | "pt" | "da" | "id" | "tr" | "el" => {
+                Ok(Self::new(',', Some('.')))
+            }
+            "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu" => {
+                Ok(Self::new(',', Some(' ')))
+            }
+            _ => Err(format!(
+                "unknown locale '{}' (expected a language like 'en', 'de' or 'fr', or 'auto')",
+                s
+            )),
+        }
+    }
+}
+
+/// The `--locale` setting: a fixed locale, or one chosen from the CSV delimiter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocaleChoice {
+    Auto,
+    Fixed(NumberLocale),
+}
+
+impl LocaleChoice {
+    pub fn resolve(self, delimiter: u8) -> NumberLocale {
+        match self {
+            LocaleChoice::Auto => NumberLocale::for_delimiter(delimiter),
+            LocaleChoice::Fixed(locale) => locale,
+        }
+    }
+}
+
+impl FromStr for LocaleChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("auto") {
+            Ok(LocaleChoice::Auto)
         } else {
-            // Has leading zero - keep as string to preserve it
-            Value::String(field.to_string())
+            s.parse().map(LocaleChoice::Fixed)
         }
     }
 }
@@ -131,4 +745,285 @@ mod tests {
             assert_eq!(n.as_i64(), Some(-42));
         }
     }
+
+    #[test]
+    fn test_write_json_matches_converted_value() {
+        let fields = [
+            "",
+            "42",
+            "-42",
+            "+7",
+            "-0",
+            "3.14",
+            "1e3",
+            "0.5",
+            "02134",
+            "0",
+            "00",
+            "true",
+            "FALSE",
+            "NaN",
+            "inf",
+            "-infinity",
+            "1.0",
+            "9223372036854775808",
+            "say \"hi\"\n",
+            "naïve",
+            "tab\there",
+        ];
+        let converters = [
+            ColumnConverter::infer(),
+            ColumnConverter::infer().with_locale(NumberLocale::new(',', Some('.'))),
+            ColumnConverter::text(),
+        ];
+        for converter in converters {
+            for field in fields {
+                let mut written = Vec::new();
+                converter.write_json(field, &mut written).unwrap();
+                let expected = serde_json::to_vec(&converter.convert(field)).unwrap();
+                assert_eq!(
+                    String::from_utf8(written).unwrap(),
+                    String::from_utf8(expected).unwrap(),
+                    "field {:?}",
+                    field
+                );
+            }
+        }
+    }
+
+    fn convert_with(field: &str, locale: &str) -> Value {
+        let locale: NumberLocale = locale.parse().unwrap();
+        ColumnConverter::infer().with_locale(locale).convert(field)
+    }
+
+    #[test]
+    fn test_decimal_comma_locale() {
+        assert_eq!(convert_with("1.234,56", "de"), serde_json::json!(1234.56));
+        assert_eq!(
+            convert_with("-1.234.567", "de-DE"),
+            serde_json::json!(-1234567)
+        );
+        assert_eq!(convert_with("0,5", "nl"), serde_json::json!(0.5));
+        assert_eq!(convert_with("42", "nl"), serde_json::json!(42));
+        assert_eq!(convert_with("1 234,5", "fr"), serde_json::json!(1234.5));
+        assert_eq!(
+            convert_with("1\u{202f}234", "fr_FR"),
+            serde_json::json!(1234)
+        );
+        assert_eq!(convert_with("1 234,56", "de"), serde_json::json!(1234.56));
+        assert_eq!(
+            convert_with("-1\u{a0}234\u{a0}567", "nl"),
+            serde_json::json!(-1234567)
+        );
+
+        // Bad grouping, the wrong decimal point and leading zeros stay strings
+        assert_eq!(convert_with("1.23", "de"), serde_json::json!("1.23"));
+        assert_eq!(convert_with("12.5", "de"), serde_json::json!("12.5"));
+        assert_eq!(convert_with("1,2,3", "de"), serde_json::json!("1,2,3"));
+        assert_eq!(convert_with("01,5", "de"), serde_json::json!("01,5"));
+        assert_eq!(convert_with("1e3", "de"), serde_json::json!("1e3"));
+    }
+
+    #[test]
+    fn test_thousands_separator_locale() {
+        assert_eq!(convert_with("1,234.56", "en"), serde_json::json!(1234.56));
+        assert_eq!(
+            convert_with("1,234,567", "en-US"),
+            serde_json::json!(1234567)
+        );
+        assert_eq!(convert_with("1'234.5", "de-CH"), serde_json::json!(1234.5));
+
+        // Numbers in Rust syntax still work with a decimal point
+        assert_eq!(convert_with("1e3", "en"), serde_json::json!(1000.0));
+        assert_eq!(convert_with("1,23", "en"), serde_json::json!("1,23"));
+    }
+
+    #[test]
+    fn test_locale_names() {
+        assert_eq!("C".parse(), Ok(NumberLocale::default()));
+        assert!("xx".parse::<NumberLocale>().is_err());
+        assert_eq!(
+            "auto".parse::<LocaleChoice>().unwrap().resolve(b';'),
+            NumberLocale::new(',', Some('.'))
+        );
+        let auto = "auto".parse::<LocaleChoice>().unwrap().resolve(b';');
+        assert_eq!(
+            ColumnConverter::infer()
+                .with_locale(auto)
+                .convert("1 234,56"),
+            serde_json::json!(1234.56)
+        );
+        assert_eq!(
+            "auto".parse::<LocaleChoice>().unwrap().resolve(b','),
+            NumberLocale::default()
+        );
+    }
+
+    #[test]
+    fn test_vocabulary() {
+        let options = VocabularyOptions {
+            null_values: vec!["NULL,\\N".parse().unwrap(), "score=-".parse().unwrap()],
+            true_values: vec!["yes, Y".parse().unwrap()],
+            false_values: vec!["active=0".parse().unwrap()],
+        };
+        let vocabularies = options.resolve();
+        let convert = |header: &str, field: &str| {
+            ColumnConverter::infer()
+                .with_vocabulary(vocabularies.get(header))
+                .convert(field)
+        };
+
+        assert_eq!(convert("note", "NULL"), Value::Null);
+        assert_eq!(convert("note", "\\N"), Value::Null);
+        assert_eq!(convert("note", "null"), serde_json::json!("null"));
+        assert_eq!(convert("note", "-"), serde_json::json!("-"));
+        assert_eq!(convert("score", "-"), Value::Null);
+        assert_eq!(convert("score", "NULL"), Value::Null);
+
+        assert_eq!(convert("note", "YES"), Value::Bool(true));
+        assert_eq!(convert("note", "y"), Value::Bool(true));
+        assert_eq!(convert("note", "True"), Value::Bool(true));
+        assert_eq!(convert("note", "0"), serde_json::json!(0));
+        assert_eq!(convert("active", "0"), Value::Bool(false));
+
+        // Null words also apply to columns kept as strings, boolean words do not
+        let text = ColumnConverter::text().with_vocabulary(vocabularies.get("note"));
+        assert_eq!(text.convert("NULL"), Value::Null);
+        assert_eq!(text.convert("yes"), serde_json::json!("yes"));
+
+        assert!(VocabularyOptions::default().resolve().get("note").is_none());
+        assert!(" , ".parse::<WordList>().is_err());
+    }
+
+    #[test]
+    fn test_big_numbers_at_type_boundaries() {
+        let write = |field: &str, big_numbers: BigNumbers| {
+            let converter = ColumnConverter::infer().with_big_numbers(big_numbers);
+            let mut json = Vec::new();
+            converter.write_json(field, &mut json).unwrap();
+            let json = String::from_utf8(json).unwrap();
+            assert_eq!(converter.convert(field).to_string(), json);
+            json
+        };
+
+        // i64 and u64 limits fit their types under every policy
+        for policy in [BigNumbers::Exact, BigNumbers::String, BigNumbers::Float] {
+            assert_eq!(write("9223372036854775807", policy), "9223372036854775807");
+            assert_eq!(
+                write("-9223372036854775808", policy),
+                "-9223372036854775808"
+            );
+            assert_eq!(write("9223372036854775808", policy), "9223372036854775808");
+            assert_eq!(
+                write("18446744073709551615", policy),
+                "18446744073709551615"
+            );
+            assert_eq!(write("0.1", policy), "0.1");
+            assert_eq!(write("1.50", policy), "1.5");
+            assert_eq!(write("125e-5", policy), "0.00125");
+        }
+
+        let exact = BigNumbers::Exact;
+        assert_eq!(write("18446744073709551616", exact), "18446744073709551616");
+        assert_eq!(write("-9223372036854775809", exact), "-9223372036854775809");
+        assert_eq!(
+            write("0.1000000000000000055", exact),
+            "0.1000000000000000055"
+        );
+        assert_eq!(write("9007199254740993.0", exact), "9007199254740993.0");
+
+        // Beyond the f64 range, large and small numbers are handled alike
+        assert_eq!(write("1e400", exact), "1e400");
+        assert_eq!(write("-1E+400", exact), "-1e400");
+        assert_eq!(write("1E-400", exact), "1e-400");
+        assert_eq!(write("1.8e308", exact), "1.8e308");
+        assert_eq!(write("2e-324", exact), "2e-324");
+        assert_eq!(
+            write("1.7976931348623157e308", exact),
+            "1.7976931348623157e308"
+        );
+        assert_eq!(write("5e-324", exact), "5e-324");
+        assert_eq!(
+            write("0.01e-9223372036854775808", exact),
+            "0.01e-9223372036854775808"
+        );
+        assert_eq!(write("inf", exact), "\"inf\"");
+        assert_eq!(write("NaN", exact), "\"NaN\"");
+
+        let string = BigNumbers::String;
+        assert_eq!(
+            write("18446744073709551616", string),
+            "\"18446744073709551616\""
+        );
+        assert_eq!(
+            write("0.1000000000000000055", string),
+            "\"0.1000000000000000055\""
+        );
+        assert_eq!(write("1e400", string), "\"1e400\"");
+        assert_eq!(write("1E-400", string), "\"1E-400\"");
+
+        let float = BigNumbers::Float;
+        assert_eq!(
+            write("18446744073709551616", float),
+            "1.8446744073709552e19"
+        );
+        assert_eq!(write("0.1000000000000000055", float), "0.1");
+        // Small numbers round to zero, but there is no f64 to round large ones to
+        assert_eq!(write("1E-400", float), "0.0");
+        assert_eq!(write("1e400", float), "\"1e400\"");
+
+        // Numbers in a locale keep their original text as strings
+        let converter = ColumnConverter::infer()
+            .with_locale(NumberLocale::new(',', Some('.')))
+            .with_big_numbers(string);
+        assert_eq!(
+            converter.convert("12.345.678.901.234.567.890.123"),
+            serde_json::json!("12.345.678.901.234.567.890.123")
+        );
+        assert_eq!(
+            converter
+                .with_big_numbers(exact)
+                .convert("0,1000000000000000055")
+                .to_string(),
+            "0.1000000000000000055"
+        );
+    }
+
+    #[test]
+    fn test_cell_transforms() {
+        let json = ColumnConverter::infer().with_transform(Some(CellTransform::Json));
+        assert_eq!(
+            json.convert(r#"{"source": "web", "ids": [1, 2]}"#),
+            serde_json::json!({"source": "web", "ids": [1, 2]})
+        );
+        assert_eq!(json.convert("not json"), serde_json::json!("not json"));
+        assert_eq!(json.convert("42"), serde_json::json!(42));
+        assert_eq!(json.convert(""), Value::Null);
+
+        let list = ColumnConverter::infer().with_transform(Some(CellTransform::List(";".into())));
+        assert_eq!(
+            list.convert("a; 2 ;2.5;;true;007"),
+            serde_json::json!(["a", 2, 2.5, null, true, "007"])
+        );
+        assert_eq!(list.convert("single"), serde_json::json!(["single"]));
+        assert_eq!(list.convert(""), Value::Null);
+
+        // Elements of columns kept as strings stay strings
+        let text = ColumnConverter::text().with_transform(Some(CellTransform::List("|".into())));
+        assert_eq!(text.convert("1|2"), serde_json::json!(["1", "2"]));
+
+        let mut written = Vec::new();
+        list.write_json("x;1", &mut written).unwrap();
+        assert_eq!(written, br#"["x",1]"#);
+
+        assert_eq!(
+            "tags=|".parse(),
+            Ok(ListColumn {
+                column: "tags".into(),
+                separator: "|".into()
+            })
+        );
+        assert_eq!("tags".parse::<ListColumn>().unwrap().separator, ",");
+        assert!("tags=".parse::<ListColumn>().is_err());
+    }
 }
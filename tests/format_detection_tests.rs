@@ -1,6 +1,6 @@
-use csv_converter::detect_csv_format;
+use csv_converter::{detect_csv_format, detect_csv_format_in, CsvInput, InputMode};
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::PathBuf;
 
 /// Helper to create a temporary CSV file for testing
@@ -220,3 +220,29 @@ fn test_large_sample_detection() {
     assert_eq!(delimiter, b',');
     cleanup_temp_file(&path);
 }
+
+#[test]
+fn test_detect_from_shared_input() {
+    let csv_content = "name;note\nAlice;\"say \\\"hi\\\" now\"\nBob;x\n";
+    let path = create_temp_csv("shared_input.csv", csv_content);
+
+    for mode in [InputMode::Mmap, InputMode::Read] {
+        // A tiny buffer makes the read-ahead stop after the first chunks with enough lines
+        let input = CsvInput::open(&path, mode, 4).unwrap();
+        assert_eq!(input.is_mapped(), mode == InputMode::Mmap);
+        let (delimiter, quote, escape, _) = detect_csv_format_in(input.sample()).unwrap();
+        let from_path = detect_csv_format(&path).unwrap();
+        assert_eq!(
+            (delimiter, quote, escape),
+            (from_path.0, from_path.1, from_path.2)
+        );
+        assert_eq!((delimiter, escape), (b';', Some(b'\\')));
+
+        // Bytes read ahead for detection are still parsed
+        let mut content = String::new();
+        input.into_reader().read_to_string(&mut content).unwrap();
+        assert_eq!(content, csv_content);
+    }
+
+    cleanup_temp_file(&path);
+}
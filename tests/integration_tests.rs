@@ -1,7 +1,9 @@
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Helper to create a temporary CSV file for testing
 fn create_temp_csv(name: &str, content: &str) -> PathBuf {
@@ -44,6 +46,28 @@ fn test_basic_csv_conversion() {
     cleanup_temp_file(&input);
 }
 
+#[test]
+fn test_duplicate_headers_and_ragged_rows() {
+    let csv_content = "b,a,b,\"say \"\"hi\"\"\"\n1,02,x,\"two\nlines\"\n5\n1,2,3,4,5\n";
+    let input = create_temp_csv("ragged.csv", csv_content);
+
+    let output = run_converter(&["--input", input.to_str().unwrap()]);
+    let lines: Vec<&str> = output.lines().collect();
+
+    // Keys are sorted, the last of duplicate columns wins, short rows leave keys
+    // out and extra fields get positional names
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"a":"02","b":"x","say \"hi\"":"two\nlines"}"#,
+            r#"{"b":5}"#,
+            r#"{"a":2,"b":3,"column_4":5,"say \"hi\"":4}"#,
+        ]
+    );
+
+    cleanup_temp_file(&input);
+}
+
 #[test]
 fn test_semicolon_delimiter_detection() {
     let csv_content = "name;price;quantity\nWidget A;19.99;100\nWidget B;29.50;50\n";
@@ -321,3 +345,864 @@ Charlie,00501,5551234567,0.99,,true
 
     cleanup_temp_file(&input);
 }
+
+#[test]
+fn test_json_array_output() {
+    let csv_content = "name,age\nAlice,30\nBob,25\n";
+    let input = create_temp_csv("json_array.csv", csv_content);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--output-format",
+        "json",
+    ]);
+
+    let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let records = parsed.as_array().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["name"], "Alice");
+    assert_eq!(records[1]["age"], 25);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_json_array_output_empty() {
+    let csv_content = "name,age\n";
+    let input = create_temp_csv("json_array_empty.csv", csv_content);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--output-format",
+        "json",
+    ]);
+
+    assert_eq!(output.trim(), "[]");
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_pretty_json_output() {
+    let csv_content = "name,age\nAlice,30\nBob,25\n";
+    let input = create_temp_csv("pretty_json.csv", csv_content);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--pretty"]);
+
+    assert!(output.starts_with("[\n  {\n"));
+    assert!(output.contains(r#"    "name": "Alice""#));
+    let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(parsed.as_array().unwrap().len(), 2);
+
+    cleanup_temp_file(&input);
+}
+
+/// Builds a CSV with enough rows for several batches, including quoted fields
+/// with delimiters and line breaks
+fn large_csv_content(rows: usize) -> String {
+    let mut content = String::from("id,name,note,amount\n");
+    for i in 0..rows {
+        content.push_str(&format!(
+            "{},\"Customer, {}\",\"line one\nline \"\"{}\"\"\",{}.5\n",
+            i, i, i, i
+        ));
+    }
+    content
+}
+
+#[test]
+fn test_multithreaded_output_matches_single_threaded() {
+    let input = create_temp_csv("threads_ordered.csv", &large_csv_content(5000));
+
+    let single = run_converter(&["--input", input.to_str().unwrap()]);
+    let threaded = run_converter(&["--input", input.to_str().unwrap(), "--threads", "4"]);
+
+    assert_eq!(single.lines().count(), 5000);
+    assert!(single.starts_with(
+        r#"{"amount":0.5,"id":0,"name":"Customer, 0","note":"line one\nline \"0\""}"#
+    ));
+    assert_eq!(threaded, single);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_multithreaded_unordered_output_has_every_record() {
+    let input = create_temp_csv("threads_unordered.csv", &large_csv_content(5000));
+
+    let single = run_converter(&["--input", input.to_str().unwrap()]);
+    let unordered = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--threads",
+        "3",
+        "--unordered",
+    ]);
+
+    let mut expected: Vec<&str> = single.lines().collect();
+    let mut actual: Vec<&str> = unordered.lines().collect();
+    expected.sort_unstable();
+    actual.sort_unstable();
+    assert_eq!(actual, expected);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_multithreaded_json_array_output() {
+    let input = create_temp_csv("threads_pretty.csv", &large_csv_content(2500));
+
+    let single = run_converter(&["--input", input.to_str().unwrap(), "--pretty"]);
+    let threaded = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--pretty",
+        "--threads",
+        "2",
+    ]);
+
+    let records: serde_json::Value = serde_json::from_str(&threaded).unwrap();
+    assert_eq!(records.as_array().unwrap().len(), 2500);
+    assert_eq!(threaded, single);
+
+    cleanup_temp_file(&input);
+}
+
+/// Waits for the converter to exit, failing the test if it hangs
+fn wait_or_kill(mut child: Child) -> Output {
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > Duration::from_secs(30) {
+            child.kill().unwrap();
+            panic!("converter did not exit");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_multithreaded_conversion_stops_on_failing_record() {
+    let input = create_temp_csv("threads_failing.csv", &large_csv_content(50000));
+    let mut invalid = b"id,name\n1,\xff\n".to_vec();
+    for i in 0..50000 {
+        invalid.extend_from_slice(format!("{},x\n", i).as_bytes());
+    }
+    let invalid_input = PathBuf::from("tests/fixtures/threads_invalid_utf8.csv");
+    fs::write(&invalid_input, invalid).unwrap();
+
+    let runs = [
+        vec![
+            "--input",
+            input.to_str().unwrap(),
+            "--threads",
+            "4",
+            "--add-column",
+            "x=substr(name, 1, 2) / 0",
+        ],
+        vec![
+            "--input",
+            invalid_input.to_str().unwrap(),
+            "--no-auto-detect",
+            "--threads",
+            "2",
+        ],
+    ];
+    for args in runs {
+        let child = Command::new("./target/release/csv-converter")
+            .args(&args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let output = wait_or_kill(child);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("Failed to compute column 'x'") || stderr.contains("invalid UTF-8"),
+            "unexpected error: {}",
+            stderr
+        );
+    }
+
+    cleanup_temp_file(&input);
+    cleanup_temp_file(&invalid_input);
+}
+
+#[test]
+fn test_multithreaded_conversion_stops_when_output_closes() {
+    let input = create_temp_csv("threads_closed_output.csv", &large_csv_content(50000));
+
+    let mut child = Command::new("./target/release/csv-converter")
+        .args(["--input", input.to_str().unwrap(), "--threads", "4"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first)
+        .unwrap();
+    assert!(first.starts_with(r#"{"amount":0.5,"id":0"#));
+
+    // Like `| head -1`: the reading end is closed after the first record
+    let output = wait_or_kill(child);
+    assert!(!output.status.success());
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_input_modes_produce_identical_output() {
+    let input = create_temp_csv("input_modes.csv", &large_csv_content(3000));
+    let path = input.to_str().unwrap();
+
+    let mapped = run_converter(&["--input", path, "--input-mode", "mmap"]);
+    let read = run_converter(&[
+        "--input",
+        path,
+        "--input-mode",
+        "read",
+        "--buffer-size",
+        "100",
+    ]);
+
+    assert_eq!(mapped.lines().count(), 3000);
+    assert_eq!(mapped, read);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_mmap_and_read_scan_the_same_dates() {
+    // Day and month order is only settled by a date after the format detection sample
+    let mut csv_content = String::from("id,shipped\n");
+    for i in 1..=400 {
+        let day = if i == 300 { 13 } else { i % 12 + 1 };
+        csv_content.push_str(&format!("{},{:02}/04/2024\n", i, day));
+    }
+    let input = create_temp_csv("mmap_dates.csv", &csv_content);
+    let path = input.to_str().unwrap();
+
+    let mapped = run_converter(&["--input", path, "--input-mode", "mmap", "--parse-dates"]);
+    let read = run_converter(&[
+        "--input",
+        path,
+        "--input-mode",
+        "read",
+        "--buffer-size",
+        "100",
+        "--parse-dates",
+    ]);
+
+    assert_eq!(mapped.lines().count(), 400);
+    assert_eq!(mapped, read);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_csv_from_pipe() {
+    let csv_content = large_csv_content(1000);
+    let input = create_temp_csv("pipe_expected.csv", &csv_content);
+    let expected = run_converter(&["--input", input.to_str().unwrap()]);
+
+    // Detection reads ahead from the pipe; those lines must not be lost
+    let mut child = Command::new("./target/release/csv-converter")
+        .args(["--input", "/dev/stdin", "--buffer-size", "512"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run converter");
+    let mut stdin = child.stdin.take().unwrap();
+    let writing = std::thread::spawn(move || stdin.write_all(csv_content.as_bytes()));
+    let output = child.wait_with_output().unwrap();
+    writing.join().unwrap().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_mmap_mode_rejects_pipe() {
+    let output = Command::new("./target/release/csv-converter")
+        .args(["--input", "/dev/stdin", "--input-mode", "mmap"])
+        .stdin(Stdio::piped())
+        .output()
+        .expect("Failed to run converter");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("not a regular file"), "{}", stderr);
+}
+
+#[test]
+fn test_csv_warns_about_xlsx_options() {
+    let input = create_temp_csv("xlsx_options.csv", "name,qty\nWidget,4\n");
+    let path = input.to_str().unwrap();
+
+    let output = Command::new("./target/release/csv-converter")
+        .args([
+            "--input",
+            path,
+            "--skip-hidden-rows",
+            "--cell-details",
+            "keys",
+            "--number-formats",
+            "display",
+            "--error-cells",
+            "object",
+            "--header-rows",
+            "2",
+            "--range",
+            "A1:B2",
+        ])
+        .output()
+        .expect("Failed to run converter");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"name\":\"Widget\",\"qty\":4}\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "XLSX-specific options (--range, --error-cells, --header-rows, \
+             --skip-hidden-rows, --cell-details, --number-formats) are ignored for CSV files"
+        ),
+        "{}",
+        stderr
+    );
+
+    let output = Command::new("./target/release/csv-converter")
+        .args(["--input", path])
+        .output()
+        .expect("Failed to run converter");
+    assert!(!String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("are ignored"));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_locale_auto_reads_decimal_commas() {
+    let csv_content = "name;price;qty\nWidget;1.234,56;1.000\nGadget;19,99;5\nZip;12.5;007\n";
+    let input = create_temp_csv("decimal_comma.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&["--input", path, "--locale", "auto"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"name":"Widget","price":1234.56,"qty":1000}"#,
+            r#"{"name":"Gadget","price":19.99,"qty":5}"#,
+            // Not valid with decimal commas, and leading zeros are kept
+            r#"{"name":"Zip","price":"12.5","qty":"007"}"#,
+        ]
+    );
+
+    // Without a locale, decimal commas stay strings
+    let output = run_converter(&["--input", path]);
+    assert!(output.contains(r#""price":"1.234,56""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_separator_options() {
+    let csv_content = "amount,note\n\"1 234,5\",x\n\"12 000\",y\n";
+    let input = create_temp_csv("separators.csv", csv_content);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--decimal-separator",
+        ",",
+        "--thousands-separator",
+        " ",
+    ]);
+
+    assert!(output.contains(r#""amount":1234.5"#));
+    assert!(output.contains(r#""amount":12000"#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_parse_dates_resolves_columns() {
+    let csv_content = "us,eu,unclear,stamp,note\n\
+                       03/01/2024,25/12/2024,03/01/2024,2024-03-01 14:05,1 Mar 2024\n\
+                       12/25/2024,03/01/2024,04/05/2024,2024-03-01T14:05:00+01:00,n/a\n";
+    let input = create_temp_csv("dates.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&["--input", path, "--parse-dates"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"eu":"2024-12-25","note":"2024-03-01","stamp":"2024-03-01T14:05:00","unclear":"2024-03-01","us":"2024-03-01"}"#,
+            r#"{"eu":"2024-01-03","note":"n/a","stamp":"2024-03-01T14:05:00+01:00","unclear":"2024-04-05","us":"2024-12-25"}"#,
+        ]
+    );
+
+    // The ambiguous column follows --day-first, the others are decided by their values
+    let output = run_converter(&["--input", path, "--parse-dates", "--day-first"]);
+    assert!(output.contains(r#""unclear":"2024-01-03""#));
+    assert!(output.contains(r#""us":"2024-03-01""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_date_formats_and_epoch_output() {
+    let csv_content = "day,stamp\n20240301,01.03.2024 01:00\n";
+    let input = create_temp_csv("date_formats.csv", csv_content);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--date-format",
+        "day=%Y%m%d",
+        "--date-format",
+        "%d.%m.%Y %H:%M",
+        "--date-output",
+        "epoch-ms",
+        "--timezone",
+        "+01:00",
+    ]);
+
+    assert_eq!(
+        output.trim(),
+        r#"{"day":1709247600000,"stamp":1709251200000}"#
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_null_and_boolean_vocabularies() {
+    let csv_content = "id,active,score,note\n1,yes,-,NULL\n2,N,5,\\N\n3,ja,n/a,ok\n";
+    let input = create_temp_csv("vocabulary.csv", csv_content);
+    let path = input.to_str().unwrap();
+    let args = [
+        "--input",
+        path,
+        "--null-values",
+        "NULL,\\N",
+        "--null-values",
+        "score=-,n/a",
+        "--true-values",
+        "yes,ja",
+        "--false-values",
+        "active=n",
+    ];
+
+    let output = run_converter(&args);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"active":true,"id":1,"note":null,"score":null}"#,
+            r#"{"active":false,"id":2,"note":null,"score":5}"#,
+            r#"{"active":true,"id":3,"note":"ok","score":null}"#,
+        ]
+    );
+
+    // Worker threads convert with the same vocabulary
+    let mut threaded = args.to_vec();
+    threaded.extend(["--threads", "2"]);
+    assert_eq!(run_converter(&threaded), output);
+
+    // Without the options only empty fields and true/false are special
+    let output = run_converter(&["--input", path]);
+    assert!(output.contains(r#"{"active":"yes","id":1,"note":"NULL","score":"-"}"#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_keep_empty_strings() {
+    let csv_content = "a,\"\",c\n\"\",,\"x\"\"\"\n\"\"\"\"\"\",\"\",\n";
+    let input = create_temp_csv("empty_strings.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&["--input", path, "--keep-empty-strings"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"":null,"a":"","c":"x\""}"#,
+            r#"{"":"","a":"\"\"","c":null}"#,
+        ]
+    );
+
+    // By default quoted and unquoted empty fields are both null
+    let output = run_converter(&["--input", path]);
+    assert!(output.starts_with(r#"{"":null,"a":null,"c":"x\""}"#));
+
+    // Fields holding a NUL character are kept as they are, also past blank lines and
+    // when converting on several threads
+    let nul_input = create_temp_csv("nul_fields.csv", "a,b\n\n\"\0\",\"\"\n\0,x\n\"\",\n");
+    let nul_path = nul_input.to_str().unwrap();
+    for threads in ["1", "2"] {
+        let output = run_converter(&[
+            "--input",
+            nul_path,
+            "--keep-empty-strings",
+            "--threads",
+            threads,
+        ]);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"a":"\u0000","b":""}"#,
+                r#"{"a":"\u0000","b":"x"}"#,
+                r#"{"a":"","b":null}"#,
+            ]
+        );
+    }
+
+    cleanup_temp_file(&input);
+    cleanup_temp_file(&nul_input);
+}
+
+#[test]
+fn test_big_numbers_policy() {
+    let csv_content =
+        "account,amount\n12345678901234567890123,0.1000000000000000055\n18446744073709551615,2.5\n";
+    let input = create_temp_csv("big_numbers.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    // Digits are kept by default, in NDJSON and in a JSON array
+    let output = run_converter(&["--input", path]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"account":12345678901234567890123,"amount":0.1000000000000000055}"#,
+            r#"{"account":18446744073709551615,"amount":2.5}"#,
+        ]
+    );
+    let output = run_converter(&["--input", path, "--output-format", "json"]);
+    assert!(output.contains("12345678901234567890123"));
+
+    let output = run_converter(&["--input", path, "--big-numbers", "string"]);
+    assert!(output
+        .starts_with(r#"{"account":"12345678901234567890123","amount":"0.1000000000000000055"}"#));
+
+    let output = run_converter(&["--input", path, "--big-numbers", "float"]);
+    assert!(output.starts_with(r#"{"account":1.2345678901234568e22,"amount":0.1}"#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_json_and_list_columns() {
+    let csv_content = "id,tags,meta,scores\n\
+                       1,\"a; b;c\",\"{\"\"source\"\":\"\"web\"\"}\",1|2.5|x\n\
+                       2,,not json,7\n";
+    let input = create_temp_csv("transforms.csv", csv_content);
+    let path = input.to_str().unwrap();
+    let args = [
+        "--input",
+        path,
+        "--json-columns",
+        "meta",
+        "--list-column",
+        "tags=;",
+        "--list-column",
+        "scores=|",
+    ];
+
+    let output = run_converter(&args);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"id":1,"meta":{"source":"web"},"scores":[1,2.5,"x"],"tags":["a","b","c"]}"#,
+            r#"{"id":2,"meta":"not json","scores":[7],"tags":null}"#,
+        ]
+    );
+
+    // Pretty output indents the nested values
+    let mut pretty = args.to_vec();
+    pretty.push("--pretty");
+    let output = run_converter(&pretty);
+    let records: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+    assert_eq!(records[0]["tags"], serde_json::json!(["a", "b", "c"]));
+    assert!(output.contains("    \"tags\": [\n      \"a\","));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_unflatten_headers() {
+    let csv_content = "id,address.city,address.zip,items[0].sku,items[1].sku,items[1].qty\n\
+                       1,Oslo,0150,A1,B2,3\n\
+                       2,Bergen,,C3\n";
+    let input = create_temp_csv("unflatten.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&["--input", path, "--unflatten"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"address":{"city":"Oslo","zip":"0150"},"id":1,"items":[{"sku":"A1"},{"qty":3,"sku":"B2"}]}"#,
+            r#"{"address":{"city":"Bergen","zip":null},"id":2,"items":[{"sku":"C3"}]}"#,
+        ]
+    );
+
+    // Without --unflatten the keys stay flat
+    let output = run_converter(&["--input", path]);
+    assert!(output.starts_with(r#"{"address.city":"Oslo""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_unflatten_separator_and_conflicts() {
+    let input = create_temp_csv("unflatten_separator.csv", "a/b,a/c,x.y\n1,2,3\n");
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--unflatten", "/"]);
+    assert_eq!(output.trim(), r#"{"a":{"b":1,"c":2},"x.y":3}"#);
+    cleanup_temp_file(&input);
+
+    let input = create_temp_csv("unflatten_conflict.csv", "price,price.net\n12,10\n");
+    let output = Command::new("./target/release/csv-converter")
+        .args(["--input", input.to_str().unwrap(), "--unflatten"])
+        .output()
+        .expect("Failed to execute converter");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Header 'price.net' is both a value and a group of columns"));
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_normalize_and_rename_headers() {
+    let csv_content = "Customer Name ,E-mail,Zip Code,Home Address.City\nAnn,a@x.io,0150,Oslo\n";
+    let input = create_temp_csv("headers.csv", csv_content);
+    let renames = create_temp_csv("headers_renames.txt", "# renames\nE-mail -> contactEmail\n");
+    let path = input.to_str().unwrap();
+
+    // --string-fields refers to the normalized name
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--normalize-headers",
+        "snake",
+        "--rename-headers",
+        renames.to_str().unwrap(),
+        "--string-fields",
+        "zip_code",
+    ]);
+    assert_eq!(
+        output.trim(),
+        r#"{"contactEmail":"a@x.io","customer_name":"Ann","home_address_city":"Oslo","zip_code":"0150"}"#
+    );
+
+    // With --unflatten, the parts of nested keys are normalized one by one
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--normalize-headers",
+        "camel",
+        "--unflatten",
+    ]);
+    assert_eq!(
+        output.trim(),
+        r#"{"customerName":"Ann","eMail":"a@x.io","homeAddress":{"city":"Oslo"},"zipCode":"0150"}"#
+    );
+
+    cleanup_temp_file(&input);
+    cleanup_temp_file(&renames);
+}
+
+#[test]
+fn test_select_and_exclude_columns() {
+    let csv_content = "id,name,price_net,price_gross,note\n1,Ann,2,3,x\n4,Bob,5,6,y,extra\n";
+    let input = create_temp_csv("columns.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    // Keys follow --columns; regexes add columns in header order; fields past the
+    // headers are dropped
+    let output = run_converter(&["--input", path, "--columns", "price_gross,/^price_/,0"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"price_gross":3,"price_net":2,"id":1}"#,
+            r#"{"price_gross":6,"price_net":5,"id":4}"#,
+        ]
+    );
+
+    // Exclusions alone keep the sorted keys
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--exclude-columns",
+        "/^price/",
+        "--exclude-columns",
+        "note",
+    ]);
+    assert_eq!(output.lines().next().unwrap(), r#"{"id":1,"name":"Ann"}"#);
+
+    // Names refer to normalized headers, and nested keys keep the selection order
+    let csv_content = "ID,Address.City,Address.Zip,Name\n1,Oslo,0150,Ann\n";
+    let nested = create_temp_csv("columns_nested.csv", csv_content);
+    let output = run_converter(&[
+        "--input",
+        nested.to_str().unwrap(),
+        "--normalize-headers",
+        "snake",
+        "--unflatten",
+        "--columns",
+        "name,address.zip,id,address.city",
+    ]);
+    assert_eq!(
+        output.trim(),
+        r#"{"name":"Ann","address":{"city":"Oslo","zip":"0150"},"id":1}"#
+    );
+
+    cleanup_temp_file(&input);
+    cleanup_temp_file(&nested);
+}
+
+#[test]
+fn test_where_filters_records() {
+    let csv_content =
+        "country,amount,Order Date,name\nNL,150,2024-01-02,Ann\nDE,300,2024-02-03,Bob\nNL,50,,Cy\n";
+    let input = create_temp_csv("where.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--where",
+        "country == 'NL' && amount > 100",
+    ]);
+    assert_eq!(
+        output.trim(),
+        r#"{"Order Date":"2024-01-02","amount":150,"country":"NL","name":"Ann"}"#
+    );
+
+    // The filter sees columns left out by --columns, on every thread
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--where",
+        "`Order Date` is null or name =~ '^B'",
+        "--columns",
+        "name",
+        "--threads",
+        "2",
+    ]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines, [r#"{"name":"Bob"}"#, r#"{"name":"Cy"}"#]);
+
+    let output = Command::new("./target/release/csv-converter")
+        .args(["--input", path, "--where", "countyr == 'NL'"])
+        .output()
+        .expect("Failed to execute converter");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "Unknown column 'countyr' (the columns are: country, amount, Order Date, name)"
+        ),
+        "{}",
+        stderr
+    );
+
+    let output = Command::new("./target/release/csv-converter")
+        .args(["--input", path, "--where", "name * 2 > 1"])
+        .output()
+        .expect("Failed to execute converter");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Failed to evaluate --where on line 2"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Cannot apply '*' to \"Ann\" and 2"),
+        "{}",
+        stderr
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_add_computed_columns() {
+    let csv_content = "first,last,qty,price,created\nAnn,Smith,3,2.5,2024-03-01\nBob,,2,10,\n";
+    let input = create_temp_csv("add_column.csv", csv_content);
+    let path = input.to_str().unwrap();
+
+    // Added columns can use the ones before them, and --where can use them all
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--parse-dates",
+        "--add-column",
+        "full_name=upper(first) + ' ' + coalesce(last, '?')",
+        "--add-column",
+        "total=qty * price",
+        "--add-column",
+        "label=full_name + ': ' + total",
+        "--add-column",
+        "created_year=year(created)",
+        "--add-column",
+        "key=substr(md5(lower(first)), 0, 8)",
+        "--where",
+        "total > 7",
+        "--columns",
+        "label,total,created_year,key",
+    ]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"label":"ANN Smith: 7.5","total":7.5,"created_year":2024,"key":"7e0d7f8a"}"#,
+            r#"{"label":"BOB ?: 20","total":20,"created_year":null,"key":"9f9d51bc"}"#,
+        ]
+    );
+
+    // Without --columns the added keys are sorted in, and dotted names nest
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--add-column",
+        "order.total=qty * price",
+        "--columns",
+        "/^(first|order)/",
+        "--unflatten",
+    ]);
+    assert_eq!(
+        output.lines().next().unwrap(),
+        r#"{"first":"Ann","order":{"total":7.5}}"#
+    );
+
+    let output = Command::new("./target/release/csv-converter")
+        .args(["--input", path, "--add-column", "x=first * 2"])
+        .output()
+        .expect("Failed to execute converter");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Failed to compute column 'x' on line 2"),
+        "{}",
+        stderr
+    );
+
+    cleanup_temp_file(&input);
+}
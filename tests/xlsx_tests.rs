@@ -0,0 +1,1378 @@
+use rust_xlsxwriter::{ExcelDateTime, Format, Formula, Note, Table, TableColumn, Url, Workbook};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Helper to build a temporary XLSX file for testing
+fn create_temp_xlsx(name: &str, build: impl FnOnce(&mut Workbook)) -> PathBuf {
+    let path = PathBuf::from(format!("tests/fixtures/{}", name));
+    fs::create_dir_all("tests/fixtures").unwrap();
+    let mut workbook = Workbook::new();
+    build(&mut workbook);
+    workbook.save(&path).unwrap();
+    path
+}
+
+/// Helper to run the converter and get output
+fn run_converter(args: &[&str]) -> String {
+    let output = Command::new("./target/release/csv-converter")
+        .args(args)
+        .output()
+        .expect("Failed to run converter");
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Helper to run the converter and get the exit status and stderr as well
+fn run_converter_full(args: &[&str]) -> (bool, String, String) {
+    let output = Command::new("./target/release/csv-converter")
+        .args(args)
+        .output()
+        .expect("Failed to run converter");
+
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+/// Helper to clean up a specific temp file
+fn cleanup_temp_file(path: &PathBuf) {
+    let _ = fs::remove_file(path);
+}
+
+/// Writes a small sheet with a date, a datetime, a time and a duration column
+fn write_date_sheet(workbook: &mut Workbook) {
+    let sheet = workbook.add_worksheet();
+    let date = Format::new().set_num_format("yyyy-mm-dd");
+    let datetime = Format::new().set_num_format("yyyy-mm-dd hh:mm");
+    let time = Format::new().set_num_format("hh:mm:ss");
+    let duration = Format::new().set_num_format("[h]:mm:ss");
+
+    sheet.write(0, 0, "date").unwrap();
+    sheet.write(0, 1, "datetime").unwrap();
+    sheet.write(0, 2, "time").unwrap();
+    sheet.write(0, 3, "elapsed").unwrap();
+
+    let day = ExcelDateTime::from_ymd(2024, 3, 1).unwrap();
+    let moment = ExcelDateTime::parse_from_str("2024-03-01T12:30:00").unwrap();
+    sheet.write_datetime_with_format(1, 0, &day, &date).unwrap();
+    sheet
+        .write_datetime_with_format(1, 1, &moment, &datetime)
+        .unwrap();
+    sheet.write_number_with_format(1, 2, 0.75, &time).unwrap();
+    sheet
+        .write_number_with_format(1, 3, 1.5, &duration)
+        .unwrap();
+}
+
+#[test]
+fn test_xlsx_dates_as_iso() {
+    let input = create_temp_xlsx("dates_iso.xlsx", write_date_sheet);
+
+    let output = run_converter(&["--input", input.to_str().unwrap()]);
+
+    assert!(output.contains(r#""date":"2024-03-01""#));
+    assert!(output.contains(r#""datetime":"2024-03-01T12:30:00""#));
+    assert!(output.contains(r#""time":"18:00:00""#));
+    assert!(output.contains(r#""elapsed":"PT36H""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_warns_about_csv_read_options() {
+    let input = create_temp_xlsx("csv_read_options.xlsx", write_date_sheet);
+    let path = input.to_str().unwrap();
+
+    let (success, stdout, stderr) = run_converter_full(&[
+        "--input",
+        path,
+        "--input-mode",
+        "read",
+        "--buffer-size",
+        "16",
+        "--threads",
+        "4",
+        "--unordered",
+    ]);
+    assert!(success);
+    assert!(stdout.contains(r#""date":"2024-03-01""#));
+    assert!(stderr.contains(
+        "CSV-specific options (--threads, --unordered, --input-mode, --buffer-size) \
+         are ignored for XLSX files"
+    ));
+
+    let (_, _, stderr) = run_converter_full(&["--input", path]);
+    assert!(!stderr.contains("are ignored"));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_dates_as_epoch_ms() {
+    let input = create_temp_xlsx("dates_epoch.xlsx", write_date_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--excel-dates",
+        "epoch-ms",
+    ]);
+
+    assert!(output.contains(r#""date":1709251200000"#));
+    assert!(output.contains(r#""datetime":1709296200000"#));
+    assert!(output.contains(r#""elapsed":129600000"#));
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes two named sheets with a name/qty table each, plus a hidden third sheet
+fn write_multi_sheet(workbook: &mut Workbook) {
+    let orders = workbook.add_worksheet().set_name("Orders").unwrap();
+    orders.write(0, 0, "name").unwrap();
+    orders.write(0, 1, "qty").unwrap();
+    orders.write(1, 0, "Widget").unwrap();
+    orders.write(1, 1, 3).unwrap();
+
+    let returns = workbook.add_worksheet().set_name("Returns").unwrap();
+    returns.write(0, 0, "name").unwrap();
+    returns.write(0, 1, "qty").unwrap();
+    returns.write(1, 0, "Gadget").unwrap();
+    returns.write(1, 1, 1).unwrap();
+    returns.write(2, 0, "Gizmo").unwrap();
+    returns.write(2, 1, 2).unwrap();
+
+    let helper = workbook.add_worksheet().set_name("Helper").unwrap();
+    helper.write(0, 0, "lookup").unwrap();
+    helper.set_hidden(true);
+}
+
+#[test]
+fn test_xlsx_all_sheets_single_stream() {
+    let input = create_temp_xlsx("all_sheets.xlsx", write_multi_sheet);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--all-sheets"]);
+
+    assert!(output.contains(r#""_sheet":"Orders""#));
+    assert!(output.contains(r#""name":"Widget""#));
+    assert!(output.contains(r#""_sheet":"Returns""#));
+    assert!(output.contains(r#""name":"Gizmo""#));
+    assert_eq!(output.lines().count(), 3);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_all_sheets_per_sheet_files() {
+    let input = create_temp_xlsx("all_sheets_files.xlsx", write_multi_sheet);
+
+    let _ = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--all-sheets",
+        "--output",
+        "tests/fixtures/all_sheets_{sheet}.ndjson",
+    ]);
+
+    let orders = fs::read_to_string("tests/fixtures/all_sheets_Orders.ndjson").unwrap();
+    let returns = fs::read_to_string("tests/fixtures/all_sheets_Returns.ndjson").unwrap();
+    assert_eq!(orders.lines().count(), 1);
+    assert!(orders.contains(r#""name":"Widget""#));
+    assert!(!orders.contains("_sheet"));
+    assert_eq!(returns.lines().count(), 2);
+
+    cleanup_temp_file(&input);
+    for sheet in ["Orders", "Returns", "Helper"] {
+        cleanup_temp_file(&PathBuf::from(format!(
+            "tests/fixtures/all_sheets_{}.ndjson",
+            sheet
+        )));
+    }
+}
+
+#[test]
+fn test_xlsx_list_sheets() {
+    let input = create_temp_xlsx("list_sheets.xlsx", write_multi_sheet);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--list-sheets"]);
+    let sheets: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(sheets.len(), 3);
+    assert_eq!(sheets[0]["name"], "Orders");
+    assert_eq!(sheets[0]["dimensions"], "A1:B2");
+    assert_eq!(sheets[1]["rows"], 3);
+    assert_eq!(sheets[1]["visibility"], "visible");
+    assert_eq!(sheets[2]["visibility"], "hidden");
+
+    cleanup_temp_file(&input);
+}
+
+/// Rewrites one XML part of an XLSX package in place
+fn edit_xlsx_part(path: &PathBuf, part: &str, edit: impl Fn(String) -> String) {
+    use std::io::{Read, Write};
+
+    let mut archive = zip::ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+    let edited_path = path.with_extension("edited.xlsx");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&edited_path).unwrap());
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).unwrap();
+        let name = file.name().to_string();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        if name == part {
+            content = edit(content);
+        }
+        writer
+            .start_file(name, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+    fs::rename(&edited_path, path).unwrap();
+}
+
+#[test]
+fn test_xlsx_sheet_without_dimension() {
+    let input = create_temp_xlsx("no_dimension.xlsx", write_report_sheet);
+    let path = input.to_str().unwrap();
+    let convert = || run_converter(&["--input", path, "--sheet", "Report"]);
+    let list = || run_converter(&["--input", path, "--list-sheets"]);
+    let (declared, declared_list) = (convert(), list());
+    assert!(declared_list.contains(r#""dimensions":"A1:C9""#));
+
+    // Without a declared dimension the used cells are found by reading the sheet
+    edit_xlsx_part(&input, "xl/worksheets/sheet2.xml", |xml| {
+        let start = xml.find("<dimension").unwrap();
+        let end = start + xml[start..].find("/>").unwrap() + 2;
+        format!("{}{}", &xml[..start], &xml[end..])
+    });
+    assert_eq!(convert(), declared);
+    assert_eq!(list(), declared_list);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_dimension_with_blank_cells() {
+    let input = create_temp_xlsx("blank_dimension.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        let shaded = Format::new().set_background_color("#DDDDDD");
+        sheet.write_blank(0, 0, &shaded).unwrap();
+        sheet.write(2, 0, "item").unwrap();
+        sheet.write(2, 1, "qty").unwrap();
+        sheet.write(3, 0, "Widget").unwrap();
+        sheet.write(3, 1, 4).unwrap();
+        sheet.write_blank(8, 1, &shaded).unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    // The declared dimension takes in the blank cells, but records start at the first
+    // values and end at the last
+    let output = run_converter(&["--input", path, "--list-sheets"]);
+    assert!(output.contains(r#""dimensions":"A1:B9""#));
+    let output = run_converter(&["--input", path]);
+    assert_eq!(output, "{\"item\":\"Widget\",\"qty\":4}\n");
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes a report with a title row, a table at B3:C5 and a totals row below it
+fn write_report_sheet(workbook: &mut Workbook) {
+    workbook.add_worksheet().set_name("Cover").unwrap();
+    let report = workbook.add_worksheet().set_name("Report").unwrap();
+    report.write(0, 0, "Quarterly report").unwrap();
+    report.write(2, 1, "region").unwrap();
+    report.write(2, 2, "sales").unwrap();
+    report.write(3, 1, "North").unwrap();
+    report.write(3, 2, 120).unwrap();
+    report.write(4, 1, "South").unwrap();
+    report.write(4, 2, 80).unwrap();
+    report.write(6, 1, "Total").unwrap();
+    report.write(6, 2, 200).unwrap();
+    report.write(8, 0, "Notes: preliminary").unwrap();
+}
+
+#[test]
+fn test_xlsx_sheet_by_index() {
+    let input = create_temp_xlsx("sheet_index.xlsx", write_multi_sheet);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--sheet", "1"]);
+
+    assert!(output.contains(r#""name":"Gadget""#));
+    assert!(!output.contains(r#""name":"Widget""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_cell_range() {
+    let input = create_temp_xlsx("cell_range.xlsx", write_report_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--sheet",
+        "Report",
+        "--range",
+        "B3:C5",
+    ]);
+
+    assert_eq!(output.lines().count(), 2);
+    assert!(output.contains(r#"{"region":"North","sales":120}"#));
+    assert!(output.contains(r#"{"region":"South","sales":80}"#));
+    assert!(!output.contains("Total"));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_open_ended_cell_range() {
+    let input = create_temp_xlsx("open_range.xlsx", write_report_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--sheet",
+        "Report",
+        "--range",
+        "B3:C",
+    ]);
+
+    // Everything below the header row is read, including the totals
+    assert!(output.contains(r#"{"region":"North","sales":120}"#));
+    assert!(output.contains(r#"{"region":"Total","sales":200}"#));
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes the report sheet plus a defined name over its table and an Excel table on a second sheet
+fn write_named_sources(workbook: &mut Workbook) {
+    write_report_sheet(workbook);
+    workbook
+        .define_name("SalesData", "=Report!$B$3:$C$5")
+        .unwrap();
+
+    let orders = workbook.add_worksheet().set_name("Orders").unwrap();
+    orders.write(0, 0, "Order list").unwrap();
+    orders.write(2, 0, "sku").unwrap();
+    orders.write(2, 1, "qty").unwrap();
+    orders.write(3, 0, "A-1").unwrap();
+    orders.write(3, 1, 4).unwrap();
+    orders.write(4, 0, "B-2").unwrap();
+    orders.write(4, 1, 7).unwrap();
+    let table = Table::new().set_name("tblOrders");
+    orders.add_table(2, 0, 4, 1, &table).unwrap();
+}
+
+#[test]
+fn test_xlsx_read_excel_table() {
+    let input = create_temp_xlsx("excel_table.xlsx", write_named_sources);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--table", "tblOrders"]);
+
+    assert_eq!(output.lines().count(), 2);
+    assert!(output.contains(r#"{"qty":4,"sku":"A-1"}"#));
+    assert!(output.contains(r#"{"qty":7,"sku":"B-2"}"#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_read_defined_name() {
+    let input = create_temp_xlsx("defined_name.xlsx", write_named_sources);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--table", "SalesData"]);
+
+    assert_eq!(output.lines().count(), 2);
+    assert!(output.contains(r#"{"region":"North","sales":120}"#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_list_sheets_with_tables() {
+    let input = create_temp_xlsx("list_tables.xlsx", write_named_sources);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--list-sheets"]);
+    let sheets: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(sheets[1]["name"], "Report");
+    assert_eq!(sheets[1]["names"], serde_json::json!(["SalesData"]));
+    assert_eq!(sheets[2]["name"], "Orders");
+    assert_eq!(sheets[2]["tables"], serde_json::json!(["tblOrders"]));
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes a price/qty/total sheet where one row has a #DIV/0! formula result
+fn write_error_sheet(workbook: &mut Workbook) {
+    let sheet = workbook.add_worksheet();
+    sheet.write(0, 0, "item").unwrap();
+    sheet.write(0, 1, "qty").unwrap();
+    sheet.write(0, 2, "unit_price").unwrap();
+    sheet.write(1, 0, "Widget").unwrap();
+    sheet.write(1, 1, 4).unwrap();
+    sheet
+        .write_formula(1, 2, Formula::new("=10/B2").set_result("2.5"))
+        .unwrap();
+    sheet.write(2, 0, "Gadget").unwrap();
+    sheet.write(2, 1, 0).unwrap();
+    sheet
+        .write_formula(2, 2, Formula::new("=10/B3").set_result("#DIV/0!"))
+        .unwrap();
+}
+
+#[test]
+fn test_xlsx_error_cells_null_by_default() {
+    let input = create_temp_xlsx("error_null.xlsx", write_error_sheet);
+
+    let (success, output, stderr) = run_converter_full(&["--input", input.to_str().unwrap()]);
+
+    assert!(success);
+    assert!(output.contains(r#"{"item":"Gadget","qty":0,"unit_price":null}"#));
+    assert!(!output.contains("DIV"));
+    assert!(stderr.contains("Error cells per column: unit_price: 1"));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_error_cells_as_object() {
+    let input = create_temp_xlsx("error_object.xlsx", write_error_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--error-cells",
+        "object",
+    ]);
+
+    assert!(output.contains(r##""unit_price":{"$error":"#DIV/0!"}"##));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_error_cells_quarantine() {
+    let input = create_temp_xlsx("error_quarantine.xlsx", write_error_sheet);
+    let quarantine = PathBuf::from("tests/fixtures/error_quarantine.ndjson");
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--error-cells",
+        "quarantine",
+        "--quarantine-output",
+        quarantine.to_str().unwrap(),
+    ]);
+
+    assert_eq!(output.lines().count(), 1);
+    assert!(output.contains(r#""item":"Widget""#));
+    let rejected = fs::read_to_string(&quarantine).unwrap();
+    assert_eq!(rejected.lines().count(), 1);
+    assert!(rejected.contains(r#""_row":3"#));
+    assert!(rejected.contains(r##""unit_price":{"$error":"#DIV/0!"}"##));
+
+    cleanup_temp_file(&input);
+    cleanup_temp_file(&quarantine);
+}
+
+#[test]
+fn test_xlsx_error_cells_fail() {
+    let input = create_temp_xlsx("error_fail.xlsx", write_error_sheet);
+
+    let (success, _, stderr) =
+        run_converter_full(&["--input", input.to_str().unwrap(), "--error-cells", "fail"]);
+
+    assert!(!success);
+    assert!(stderr.contains("Error cell #DIV/0! at C3 (column 'unit_price')"));
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes a sheet with a two-row header (quarters merged over months) and a merged region label
+fn write_merged_sheet(workbook: &mut Workbook) {
+    let sheet = workbook.add_worksheet();
+    let format = Format::new();
+    sheet.merge_range(0, 0, 1, 0, "region", &format).unwrap();
+    sheet.merge_range(0, 1, 0, 3, "Q1", &format).unwrap();
+    sheet.merge_range(0, 4, 0, 5, "Q2", &format).unwrap();
+    for (col, month) in ["Jan", "Feb", "Mar", "Apr", "May"].iter().enumerate() {
+        sheet.write(1, col as u16 + 1, *month).unwrap();
+    }
+    sheet.merge_range(2, 0, 3, 0, "North", &format).unwrap();
+    for row in 2..4u32 {
+        for col in 1..6u16 {
+            sheet.write(row, col, row * 10 + col as u32).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_xlsx_merged_cells_not_filled_by_default() {
+    let input = create_temp_xlsx("merged_default.xlsx", write_merged_sheet);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--header-rows", "2"]);
+
+    // Merged header cells are still filled to build the names
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"Q1.Feb":22,"Q1.Jan":21,"Q1.Mar":23,"Q2.Apr":24,"Q2.May":25,"region":"North"}"#,
+            r#"{"Q1.Feb":32,"Q1.Jan":31,"Q1.Mar":33,"Q2.Apr":34,"Q2.May":35,"region":null}"#,
+        ]
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_fill_merged_with_multi_row_headers() {
+    let input = create_temp_xlsx("merged_fill.xlsx", write_merged_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--fill-merged",
+        "--header-rows",
+        "2",
+    ]);
+
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[0],
+        r#"{"Q1.Feb":22,"Q1.Jan":21,"Q1.Mar":23,"Q2.Apr":24,"Q2.May":25,"region":"North"}"#
+    );
+    assert!(lines[1].contains(r#""Q1.Jan":31"#));
+    assert!(lines[1].contains(r#""region":"North""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_multi_row_headers_custom_separator() {
+    let input = create_temp_xlsx("merged_separator.xlsx", write_merged_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--fill-merged",
+        "--header-rows",
+        "2",
+        "--header-separator",
+        "_",
+    ]);
+
+    assert!(output.contains(r#""Q1_Jan":21"#));
+    assert!(output.contains(r#""Q2_May":25"#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_nested_headers() {
+    let input = create_temp_xlsx("merged_nested.xlsx", write_merged_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--fill-merged",
+        "--header-rows",
+        "2",
+        "--nested-headers",
+    ]);
+
+    assert_eq!(
+        output.lines().next().unwrap(),
+        r#"{"Q1":{"Feb":22,"Jan":21,"Mar":23},"Q2":{"Apr":24,"May":25},"region":"North"}"#
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_nested_headers_conflict() {
+    let input = create_temp_xlsx("nested_conflict.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write(0, 0, "price").unwrap();
+        sheet.write(0, 1, "price.net").unwrap();
+        sheet.write(1, 0, 12).unwrap();
+        sheet.write(1, 1, 10).unwrap();
+    });
+
+    let (success, _, stderr) =
+        run_converter_full(&["--input", input.to_str().unwrap(), "--nested-headers"]);
+
+    assert!(!success);
+    assert!(stderr.contains("Header 'price.net' is both a value and a group of columns"));
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes a hidden lookup sheet followed by a data sheet with a hidden row and a hidden column
+fn write_hidden_cells(workbook: &mut Workbook) {
+    let lookup = workbook.add_worksheet().set_name("Lookup").unwrap();
+    lookup.write(0, 0, "code").unwrap();
+    lookup.write(1, 0, "X1").unwrap();
+    lookup.set_hidden(true);
+
+    let data = workbook.add_worksheet().set_name("Data").unwrap();
+    data.write(0, 0, "item").unwrap();
+    data.write(0, 1, "helper").unwrap();
+    data.write(0, 2, "qty").unwrap();
+    for (row, item) in ["Widget", "Scratch", "Gadget"].iter().enumerate() {
+        let row = row as u32 + 1;
+        data.write(row, 0, *item).unwrap();
+        data.write(row, 1, "tmp").unwrap();
+        data.write(row, 2, row).unwrap();
+    }
+    data.set_row_hidden(2).unwrap();
+    data.set_column_hidden(1).unwrap();
+    data.set_active(true);
+}
+
+#[test]
+fn test_xlsx_hidden_cells_included_by_default() {
+    let input = create_temp_xlsx("hidden_default.xlsx", write_hidden_cells);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--sheet", "Data"]);
+
+    assert_eq!(output.lines().count(), 3);
+    assert!(output.contains("Scratch"));
+    assert!(output.contains(r#""helper":"tmp""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_skip_hidden_rows_and_columns() {
+    let input = create_temp_xlsx("hidden_skip.xlsx", write_hidden_cells);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--sheet",
+        "Data",
+        "--skip-hidden-rows",
+        "--skip-hidden-columns",
+    ]);
+
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"item":"Widget","qty":1}"#,
+            r#"{"item":"Gadget","qty":3}"#
+        ]
+    );
+
+    // Expressions still see the values of hidden columns
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--sheet",
+        "Data",
+        "--skip-hidden-columns",
+        "--add-column",
+        "tag=upper(helper)",
+    ]);
+    assert_eq!(
+        output.lines().next(),
+        Some(r#"{"item":"Widget","qty":1,"tag":"TMP"}"#)
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_skip_hidden_sheets_defaults_to_first_visible() {
+    let input = create_temp_xlsx("hidden_first_sheet.xlsx", write_hidden_cells);
+
+    let output = run_converter(&["--input", input.to_str().unwrap()]);
+    assert!(output.contains(r#""code":"X1""#));
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--skip-hidden"]);
+    assert_eq!(output.lines().count(), 2);
+    assert!(output.contains(r#""item":"Widget""#));
+    assert!(!output.contains("helper"));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_all_sheets_skip_hidden_sheets() {
+    let input = create_temp_xlsx("hidden_all_sheets.xlsx", write_hidden_cells);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--all-sheets",
+        "--skip-hidden-sheets",
+    ]);
+
+    assert!(!output.contains(r#""_sheet":"Lookup""#));
+    assert!(output.contains(r#""_sheet":"Data""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_list_sheets_hidden_counts() {
+    let input = create_temp_xlsx("hidden_list.xlsx", write_hidden_cells);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--list-sheets"]);
+    let sheets: Vec<serde_json::Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(sheets[0]["visibility"], "hidden");
+    assert_eq!(sheets[1]["visibility"], "visible");
+    assert_eq!(sheets[1]["hidden_rows"], 1);
+    assert_eq!(sheets[1]["hidden_columns"], 1);
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes a sheet with a formula, a hyperlink and a comment
+fn write_audit_sheet(workbook: &mut Workbook) {
+    let sheet = workbook.add_worksheet();
+    sheet.write(0, 0, "item").unwrap();
+    sheet.write(0, 1, "qty").unwrap();
+    sheet.write(0, 2, "price").unwrap();
+    sheet
+        .write_url_with_text(1, 0, Url::new("https://example.com/widget"), "Widget")
+        .unwrap();
+    sheet.write(1, 1, 4).unwrap();
+    sheet
+        .write_formula(1, 2, Formula::new("=B2*2.5").set_result("10"))
+        .unwrap();
+    sheet
+        .insert_note(1, 1, &Note::new("Counted twice").add_author_prefix(false))
+        .unwrap();
+}
+
+#[test]
+fn test_xlsx_cell_details_off_by_default() {
+    let input = create_temp_xlsx("details_off.xlsx", write_audit_sheet);
+
+    let output = run_converter(&["--input", input.to_str().unwrap()]);
+
+    assert_eq!(output.trim(), r#"{"item":"Widget","price":10,"qty":4}"#);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_cell_details_as_objects() {
+    let input = create_temp_xlsx("details_object.xlsx", write_audit_sheet);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--cell-details",
+        "object",
+    ]);
+    let record: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+
+    assert_eq!(record["item"]["value"], "Widget");
+    assert_eq!(record["item"]["hyperlink"], "https://example.com/widget");
+    assert_eq!(record["qty"]["value"], 4);
+    assert_eq!(record["qty"]["comment"], "Counted twice");
+    assert_eq!(record["price"]["value"], 10);
+    assert_eq!(record["price"]["formula"], "=B2*2.5");
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_cell_details_as_sibling_keys() {
+    let input = create_temp_xlsx("details_keys.xlsx", write_audit_sheet);
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--cell-details", "keys"]);
+    let record: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+
+    assert_eq!(record["item"], "Widget");
+    assert_eq!(record["item__hyperlink"], "https://example.com/widget");
+    assert_eq!(record["qty__comment"], "Counted twice");
+    assert_eq!(record["price"], 10);
+    assert_eq!(record["price__formula"], "=B2*2.5");
+    assert!(record.get("qty__formula").is_none());
+
+    cleanup_temp_file(&input);
+}
+
+/// Writes a row of numbers with percent, zero-padded, currency, text and General formats
+fn write_number_formats(workbook: &mut Workbook) {
+    let sheet = workbook.add_worksheet();
+    for (col, header) in ["rate", "zip", "amount", "code", "qty"].iter().enumerate() {
+        sheet.write(0, col as u16, *header).unwrap();
+    }
+    sheet
+        .write_with_format(1, 0, 0.125, &Format::new().set_num_format("0.00%"))
+        .unwrap();
+    sheet
+        .write_with_format(1, 1, 123, &Format::new().set_num_format("00000"))
+        .unwrap();
+    sheet
+        .write_with_format(1, 2, 1234.5, &Format::new().set_num_format("$#,##0.00"))
+        .unwrap();
+    sheet
+        .write_with_format(1, 3, 42, &Format::new().set_num_format("@"))
+        .unwrap();
+    sheet.write(1, 4, 7).unwrap();
+}
+
+#[test]
+fn test_xlsx_number_formats_ignored_by_default() {
+    let input = create_temp_xlsx("number_formats_off.xlsx", write_number_formats);
+
+    let output = run_converter(&["--input", input.to_str().unwrap()]);
+
+    assert_eq!(
+        output.trim(),
+        r#"{"amount":1234.5,"code":42,"qty":7,"rate":0.125,"zip":123}"#
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_number_formats_display() {
+    let input = create_temp_xlsx("number_formats_display.xlsx", write_number_formats);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--number-formats",
+        "display",
+    ]);
+
+    assert_eq!(
+        output.trim(),
+        r#"{"amount":"$1,234.50","code":"42","qty":7,"rate":"12.50%","zip":"00123"}"#
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_number_formats_metadata() {
+    let input = create_temp_xlsx("number_formats_metadata.xlsx", write_number_formats);
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--number-formats",
+        "metadata",
+    ]);
+    let record: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+
+    assert_eq!(record["rate"], 0.125);
+    assert_eq!(record["rate__format"], "0.00%");
+    assert_eq!(record["zip__format"], "00000");
+    assert!(record.get("qty__format").is_none());
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--number-formats",
+        "metadata",
+        "--cell-details",
+        "object",
+    ]);
+    let record: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+
+    assert_eq!(record["rate"]["value"], 0.125);
+    assert_eq!(record["rate"]["format"], "0.00%");
+
+    cleanup_temp_file(&input);
+}
+
+/// Peak resident memory of a running process in kB, from /proc/<pid>/status
+#[cfg(target_os = "linux")]
+fn peak_rss_kb(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find(|line| line.starts_with("VmHWM:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse().ok())
+}
+
+/// Runs the converter to an output file, returning the output and the peak resident
+/// memory in kB
+#[cfg(target_os = "linux")]
+fn run_converter_peak_rss(args: &[&str], output: &PathBuf) -> (String, u64) {
+    let mut child = Command::new("./target/release/csv-converter")
+        .args(args)
+        .args(["--output", output.to_str().unwrap()])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to run converter");
+
+    // The peak is only readable while the process runs, so sample it until it exits
+    let mut peak_kb = 0;
+    let status = loop {
+        if let Some(kb) = peak_rss_kb(child.id()) {
+            peak_kb = peak_kb.max(kb);
+        }
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    };
+
+    assert!(status.success(), "converter failed with {:?}", args);
+    (fs::read_to_string(output).unwrap(), peak_kb)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_xlsx_large_sheet_streams_in_bounded_memory() {
+    const ROWS: u32 = 100_000;
+    const HEADERS: [&str; 7] = ["id", "name", "city", "amount", "ratio", "created", "double"];
+    let input = create_temp_xlsx("large_sheet.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        let date = Format::new().set_num_format("yyyy-mm-dd");
+        for (col, header) in HEADERS.iter().enumerate() {
+            sheet.write(0, col as u16, *header).unwrap();
+        }
+        let day = ExcelDateTime::from_ymd(2024, 3, 1).unwrap();
+        for row in 1..=ROWS {
+            sheet.write(row, 0, row).unwrap();
+            sheet.write(row, 1, format!("customer {}", row)).unwrap();
+            sheet
+                .write(row, 2, ["Amsterdam", "Berlin", "Paris"][row as usize % 3])
+                .unwrap();
+            sheet.write(row, 3, row as f64 * 1.25).unwrap();
+            sheet.write(row, 4, 1.0 / row as f64).unwrap();
+            sheet
+                .write_datetime_with_format(row, 5, &day, &date)
+                .unwrap();
+            let formula =
+                Formula::new(format!("=D{}*2", row + 1)).set_result((row as f64 * 2.5).to_string());
+            sheet.write_formula(row, 6, formula).unwrap();
+        }
+        let columns: Vec<TableColumn> = HEADERS
+            .iter()
+            .map(|header| TableColumn::new().set_header(*header))
+            .collect();
+        let table = Table::new().set_name("tblLarge").set_columns(&columns);
+        sheet.add_table(0, 0, ROWS, 6, &table).unwrap();
+    });
+    let path = input.to_str().unwrap();
+    let output = PathBuf::from("tests/fixtures/large_sheet.ndjson");
+    let last_record = r#""amount":125000,"city":"Berlin","created":"2024-03-01","#;
+
+    // Plain sheets, tables and sheets with formulas all stream
+    for (args, detail) in [
+        (vec!["--input", path], r#""double":250000"#),
+        (
+            vec!["--input", path, "--table", "tblLarge"],
+            r#""double":250000"#,
+        ),
+        (
+            vec!["--input", path, "--cell-details", "keys"],
+            r#""double__formula":"=D100001*2""#,
+        ),
+    ] {
+        let (ndjson, peak_kb) = run_converter_peak_rss(&args, &output);
+
+        assert_eq!(ndjson.lines().count(), ROWS as usize, "{:?}", args);
+        let last = ndjson.lines().last().unwrap();
+        for expected in [last_record, r#""id":100000"#, r#""ratio":0.00001"#, detail] {
+            assert!(last.contains(expected), "{:?}: {}", args, last);
+        }
+        // Only the shared strings (every distinct name) are held in memory, not the
+        // sheet; loading the whole sheet takes over 50 MB
+        assert!(
+            peak_kb < 32 * 1024,
+            "converter peaked at {} kB for {} rows with {:?}",
+            peak_kb,
+            ROWS,
+            args
+        );
+    }
+
+    cleanup_temp_file(&input);
+    cleanup_temp_file(&output);
+}
+
+#[test]
+fn test_xlsx_dates_in_text_cells() {
+    let input = create_temp_xlsx("text_dates.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "shipped").unwrap();
+        sheet.write_string(1, 0, "03/01/2024").unwrap();
+        sheet.write_string(2, 0, "1 Mar 2024").unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&["--input", path, "--parse-dates", "--day-first"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![r#"{"shipped":"2024-01-03"}"#, r#"{"shipped":"2024-03-01"}"#]
+    );
+
+    // Without date recognition the text is kept
+    let output = run_converter(&["--input", path]);
+    assert!(output.contains(r#""shipped":"03/01/2024""#));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_text_dates_scanned_per_column() {
+    let input = create_temp_xlsx("scanned_dates.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "due").unwrap();
+        sheet.write_string(0, 1, "sent").unwrap();
+        sheet.write_string(1, 0, "03/01/2024").unwrap();
+        sheet.write_string(1, 1, "03/01/2024").unwrap();
+        // Only a later row tells that the first column is day/month
+        sheet.write_string(2, 0, "13/01/2024").unwrap();
+        sheet.write_string(2, 1, "03/02/2024").unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    let (success, stdout, stderr) = run_converter_full(&["--input", path, "--parse-dates"]);
+    assert!(success);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"due":"2024-01-03","sent":"2024-03-01"}"#,
+            r#"{"due":"2024-01-13","sent":"2024-03-02"}"#
+        ]
+    );
+    assert!(stderr.contains("dates in column 'sent' fit both"));
+    assert!(!stderr.contains("column 'due'"));
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_null_and_boolean_vocabularies() {
+    let input = create_temp_xlsx("vocabulary.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "active").unwrap();
+        sheet.write_string(0, 1, "count").unwrap();
+        sheet.write_string(0, 2, "note").unwrap();
+        sheet.write_string(1, 0, "Yes").unwrap();
+        sheet.write_number(1, 1, 1).unwrap();
+        sheet.write_string(1, 2, "N/A").unwrap();
+        sheet.write_string(2, 0, "no").unwrap();
+        sheet.write_number(2, 1, 0).unwrap();
+        sheet.write_string(2, 2, "none").unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    // Numeric cells use the vocabulary like text cells
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--null-values",
+        "N/A",
+        "--true-values",
+        "yes,1",
+        "--false-values",
+        "no,0",
+    ]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"active":true,"count":true,"note":null}"#,
+            r#"{"active":false,"count":false,"note":"none"}"#,
+        ]
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_vocabulary_in_numeric_cells() {
+    let input = create_temp_xlsx("numeric_vocabulary.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "code").unwrap();
+        sheet.write_string(0, 1, "flag").unwrap();
+        sheet.write_string(0, 2, "amount").unwrap();
+        sheet.write_number(1, 0, -1).unwrap();
+        sheet.write_number(1, 1, 1).unwrap();
+        sheet.write_number(1, 2, 1.5).unwrap();
+        sheet.write_number(2, 0, 7).unwrap();
+        sheet.write_boolean(2, 1, false).unwrap();
+        sheet.write_number(2, 2, 1234).unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    // As for the same values in a CSV file; numeric cells ignore the number locale
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--true-values",
+        "1",
+        "--false-values",
+        "0",
+        "--null-values=-1",
+        "--locale",
+        "de",
+    ]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"amount":1.5,"code":null,"flag":true}"#,
+            r#"{"amount":1234,"code":7,"flag":false}"#,
+        ]
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_vocabulary_in_date_cells() {
+    let input = create_temp_xlsx("date_vocabulary.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        let date = Format::new().set_num_format("yyyy-mm-dd");
+        sheet.write_string(0, 0, "shipped").unwrap();
+        sheet.write_string(0, 1, "ordered").unwrap();
+        let placeholder = ExcelDateTime::from_ymd(1900, 1, 1).unwrap();
+        let day = ExcelDateTime::from_ymd(2024, 3, 1).unwrap();
+        sheet
+            .write_datetime_with_format(1, 0, &placeholder, &date)
+            .unwrap();
+        sheet
+            .write_datetime_with_format(1, 1, &placeholder, &date)
+            .unwrap();
+        sheet.write_datetime_with_format(2, 0, &day, &date).unwrap();
+        sheet.write_datetime_with_format(2, 1, &day, &date).unwrap();
+    });
+
+    // Date cells are matched as written, like a date in a CSV file
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--null-values",
+        "shipped=1900-01-01",
+    ]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"ordered":"1900-01-01","shipped":null}"#,
+            r#"{"ordered":"2024-03-01","shipped":"2024-03-01"}"#,
+        ]
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_big_numbers_in_text_cells() {
+    let input = create_temp_xlsx("big_numbers.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "iban_number").unwrap();
+        sheet.write_string(1, 0, "98765432109876543210").unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&["--input", path]);
+    assert_eq!(output.trim(), r#"{"iban_number":98765432109876543210}"#);
+
+    let output = run_converter(&["--input", path, "--big-numbers", "string"]);
+    assert_eq!(output.trim(), r#"{"iban_number":"98765432109876543210"}"#);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_json_and_list_columns() {
+    let input = create_temp_xlsx("transforms.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write_string(0, 0, "tags").unwrap();
+        sheet.write_string(0, 1, "meta").unwrap();
+        sheet.write_string(1, 0, "red, 2").unwrap();
+        sheet.write_string(1, 1, r#"{"a":[true]}"#).unwrap();
+    });
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--list-column",
+        "tags",
+        "--json-columns",
+        "meta",
+    ]);
+    assert_eq!(output.trim(), r#"{"meta":{"a":[true]},"tags":["red",2]}"#);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_unflatten_headers() {
+    let input = create_temp_xlsx("unflatten.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write(0, 0, "order.id").unwrap();
+        sheet.write(0, 1, "lines[0].sku").unwrap();
+        sheet.write(0, 2, "lines[1].sku").unwrap();
+        sheet.write(1, 0, 7).unwrap();
+        sheet.write(1, 1, "A1").unwrap();
+        sheet.write(1, 2, "B2").unwrap();
+    });
+
+    let output = run_converter(&["--input", input.to_str().unwrap(), "--unflatten"]);
+    assert_eq!(
+        output.trim(),
+        r#"{"lines":[{"sku":"A1"},{"sku":"B2"}],"order":{"id":7}}"#
+    );
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_normalize_headers() {
+    let input = create_temp_xlsx("normalize_headers.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write(0, 0, " Prénom ").unwrap();
+        sheet.write(0, 1, "Code Postal").unwrap();
+        sheet.write(1, 0, "Zoé").unwrap();
+        sheet.write(1, 1, "01500").unwrap();
+    });
+
+    let output = run_converter(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--normalize-headers",
+        "ascii,snake",
+        "--string-fields",
+        "code_postal",
+    ]);
+    assert_eq!(output.trim(), r#"{"code_postal":"01500","prenom":"Zoé"}"#);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_select_columns() {
+    let input = create_temp_xlsx("select_columns.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        for (col, header) in ["id", "name", "q1", "q2"].iter().enumerate() {
+            sheet.write(0, col as u16, *header).unwrap();
+        }
+        sheet.write(1, 0, 7).unwrap();
+        sheet.write(1, 1, "Ann").unwrap();
+        sheet.write(1, 2, 1.5).unwrap();
+        sheet.write(1, 3, 2.5).unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&["--input", path, "--columns", "name,/^q/"]);
+    assert_eq!(output.trim(), r#"{"name":"Ann","q1":1.5,"q2":2.5}"#);
+
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--columns",
+        "/./",
+        "--exclude-columns",
+        "2",
+    ]);
+    assert_eq!(output.trim(), r#"{"id":7,"name":"Ann","q2":2.5}"#);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_where_filters_records() {
+    let input = create_temp_xlsx("where.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write(0, 0, "country").unwrap();
+        sheet.write(0, 1, "amount").unwrap();
+        for (row, (country, amount)) in [("NL", 150), ("DE", 300), ("NL", 50)].iter().enumerate() {
+            sheet.write(row as u32 + 1, 0, *country).unwrap();
+            sheet.write(row as u32 + 1, 1, *amount).unwrap();
+        }
+    });
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--where",
+        "country != 'DE' and amount >= 100",
+    ]);
+    assert_eq!(output.trim(), r#"{"amount":150,"country":"NL"}"#);
+
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--where",
+        "amount < 100",
+        "--columns",
+        "country",
+    ]);
+    assert_eq!(output.trim(), r#"{"country":"NL"}"#);
+
+    let (success, _, stderr) = run_converter_full(&["--input", path, "--where", "total > 1"]);
+    assert!(!success);
+    assert!(stderr.contains("Unknown column 'total'"), "{}", stderr);
+
+    cleanup_temp_file(&input);
+}
+
+#[test]
+fn test_xlsx_add_computed_columns() {
+    let input = create_temp_xlsx("add_column.xlsx", |workbook| {
+        let sheet = workbook.add_worksheet();
+        sheet.write(0, 0, "name").unwrap();
+        sheet.write(0, 1, "qty").unwrap();
+        sheet.write(0, 2, "price").unwrap();
+        sheet.write(1, 0, " ann ").unwrap();
+        sheet.write(1, 1, 4).unwrap();
+        sheet.write(1, 2, 1.25).unwrap();
+    });
+    let path = input.to_str().unwrap();
+
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--add-column",
+        "total=round(qty * price * 1.21, 2)",
+        "--add-column",
+        "name=upper(trim(name))",
+    ]);
+    assert_eq!(
+        output.trim(),
+        r#"{"name":"ANN","price":1.25,"qty":4,"total":6.05}"#
+    );
+
+    let output = run_converter(&[
+        "--input",
+        path,
+        "--add-column",
+        "total=qty * price",
+        "--exclude-columns",
+        "qty,price",
+    ]);
+    assert_eq!(output.trim(), r#"{"name":" ann ","total":5.0}"#);
+
+    let (success, _, stderr) =
+        run_converter_full(&["--input", path, "--add-column", "total=qty * cost"]);
+    assert!(!success);
+    assert!(stderr.contains("Unknown column 'cost'"), "{}", stderr);
+
+    cleanup_temp_file(&input);
+}